@@ -6,13 +6,17 @@ for the React frontend to access project data.
 """
 
 import argparse
+import hmac
+import os
+import re
 import sys
 from contextlib import asynccontextmanager
 from pathlib import Path
 
 import uvicorn
-from fastapi import FastAPI
+from fastapi import FastAPI, Request
 from fastapi.middleware.cors import CORSMiddleware
+from fastapi.responses import JSONResponse
 
 # Skip path manipulation when running as a PyInstaller bundle.
 # PyInstaller freezes all modules into the binary; sys.path tweaks are unnecessary.
@@ -77,6 +81,32 @@ app = FastAPI(
     lifespan=lifespan,
 )
 
+# Bearer token the app spawned us with. Unset when run by hand, in which case
+# requests aren't checked.
+_AUTH_TOKEN = os.environ.get("CLAUDETINI_SIDECAR_TOKEN") or None
+# EventSource can't send headers, so the SSE streams also take the token as a
+# `token` query parameter. No other route does. The query string shows up in
+# the access log, where the app masks the token like any other output.
+_STREAM_PATH = re.compile(r"^/api/(dispatch|bootstrap)/stream/[^/]+$")
+
+
+# Registered before CORS so that CORS wraps it: preflights pass through and
+# rejections still carry CORS headers.
+@app.middleware("http")
+async def require_token(request: Request, call_next):
+    """Reject requests without the app's bearer token."""
+    if _AUTH_TOKEN is None or request.method == "OPTIONS":
+        return await call_next(request)
+    supplied = ""
+    authorization = request.headers.get("authorization", "")
+    if authorization.startswith("Bearer "):
+        supplied = authorization[len("Bearer "):]
+    elif request.method == "GET" and _STREAM_PATH.match(request.url.path):
+        supplied = request.query_params.get("token", "")
+    if not hmac.compare_digest(supplied.encode(), _AUTH_TOKEN.encode()):
+        return JSONResponse({"detail": "Missing or invalid token"}, status_code=401)
+    return await call_next(request)
+
 # CORS configuration for Tauri
 app.add_middleware(
     CORSMiddleware,
@@ -149,9 +179,20 @@ def main():
         default="127.0.0.1",
         help="Host to bind to (default: 127.0.0.1)",
     )
+    parser.add_argument(
+        "--socket",
+        type=str,
+        default=None,
+        help="Unix domain socket to listen on instead of --host/--port",
+    )
     args = parser.parse_args()
 
-    print(f"Starting Claudetini backend on {args.host}:{args.port}")
+    if args.socket:
+        print(f"Starting Claudetini backend on {args.socket}")
+        listen = {"uds": args.socket}
+    else:
+        print(f"Starting Claudetini backend on {args.host}:{args.port}")
+        listen = {"host": args.host, "port": args.port}
 
     log_config = uvicorn.config.LOGGING_CONFIG
     log_config["formatters"]["access"]["fmt"] = "%(asctime)s %(levelprefix)s %(client_addr)s - \"%(request_line)s\" %(status_code)s"
@@ -163,7 +204,7 @@ def main():
     # PyInstaller bundles require freeze_support() for workers>1, and single-worker
     # is simpler and sufficient for a local desktop sidecar.
     worker_count = 1 if getattr(sys, "frozen", False) else 2
-    uvicorn.run(app, host=args.host, port=args.port, log_level="info", workers=worker_count, log_config=log_config)
+    uvicorn.run(app, **listen, log_level="info", workers=worker_count, log_config=log_config)
 
 
 if __name__ == "__main__":
@@ -0,0 +1,75 @@
+//! Minimal stand-in for the Python sidecar, used by the lifecycle tests.
+//!
+//! Accepts `--port <n>` (0 lets the OS pick) and `--host <addr>`, binds,
+//! prints `CLAUDETINI_PORT=<n>` and `READY`, then answers `/health` and `/`
+//! like the real sidecar, and `/live` whenever it's listening. Test knobs:
+//!
+//! - `--exit-code <n>`: exit with `n` right after printing `READY`.
+//! - `--unhealthy-for-ms <n>`: answer `/health` with 503 for the first `n` ms.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+fn main() {
+    let mut host = "127.0.0.1".to_string();
+    let mut port = 0u16;
+    let mut exit_code = None;
+    let mut unhealthy_for = Duration::ZERO;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| panic!("{flag} needs a value"));
+        match flag.as_str() {
+            "--host" => host = value,
+            "--port" => port = value.parse().expect("--port must be a number"),
+            "--exit-code" => exit_code = Some(value.parse().expect("--exit-code must be a number")),
+            "--unhealthy-for-ms" => {
+                let ms = value.parse().expect("--unhealthy-for-ms must be a number");
+                unhealthy_for = Duration::from_millis(ms);
+            }
+            other => panic!("unknown flag {other}"),
+        }
+    }
+
+    let listener = TcpListener::bind((host.as_str(), port)).expect("bind failed");
+    let port = listener.local_addr().expect("no local addr").port();
+    println!("CLAUDETINI_PORT={port}");
+    println!("READY");
+    std::io::stdout().flush().unwrap();
+    if let Some(code) = exit_code {
+        std::process::exit(code);
+    }
+
+    let started = Instant::now();
+    for stream in listener.incoming().flatten() {
+        let healthy = started.elapsed() >= unhealthy_for;
+        let _ = respond(stream, healthy);
+    }
+}
+
+fn respond(mut stream: TcpStream, healthy: bool) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers; requests from the app never carry a body here.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, body) = match path {
+        "/health" if healthy => ("200 OK", r#"{"status":"ok"}"#),
+        "/health" => ("503 Service Unavailable", r#"{"status":"starting"}"#),
+        "/live" => ("200 OK", r#"{"status":"alive"}"#),
+        "/" => ("200 OK", r#"{"name":"Claudetini Backend","version":"stub"}"#),
+        _ => ("404 Not Found", r#"{"detail":"Not Found"}"#),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\
+         connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// Environment variable the sidecar reads its bearer token from. It expects
+/// the token as `Authorization: Bearer`, except on its SSE streams, which
+/// `EventSource` can't add headers to and which take a `token` query
+/// parameter instead.
+pub(crate) const TOKEN_ENV: &str = "CLAUDETINI_SIDECAR_TOKEN";
+
+/// Random bearer token generated for each spawn, so only this app can talk to
+/// the sidecar. `Debug` is redacted and there is no `Display`, so it can't end
+/// up in log output by accident.
+#[derive(Clone)]
+pub(crate) struct SidecarToken(String);
+
+impl SidecarToken {
+    /// 256 bits from the OS CSPRNG, hex-encoded.
+    pub fn generate() -> Result<Self, getrandom::Error> {
+        let mut bytes = [0u8; 32];
+        getrandom::fill(&mut bytes)?;
+        Ok(Self(bytes.iter().map(|b| format!("{b:02x}")).collect()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SidecarToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SidecarToken(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_and_redacted() {
+        let a = SidecarToken::generate().unwrap();
+        let b = SidecarToken::generate().unwrap();
+        assert_eq!(a.as_str().len(), 64);
+        assert_ne!(a.as_str(), b.as_str());
+        assert!(!format!("{a:?}").contains(a.as_str()));
+    }
+}
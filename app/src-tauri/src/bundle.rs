@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::Value;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::redact::MASK;
+
+/// Upper bound on the uncompressed contents of a diagnostics bundle. Log files
+/// are added newest first and cut to their tail once the budget runs low.
+pub(crate) const MAX_BUNDLE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Settings keys whose values are replaced wholesale: `env` may hold API keys
+/// for the sidecar, and patterns can spell out the secrets they look for.
+const SECRET_SETTINGS: &[&str] = &["env", "redact_patterns"];
+
+/// Environment variables whose names suggest a secret value.
+const SECRET_ENV_MARKERS: &[&str] = &["TOKEN", "KEY", "SECRET", "PASSWORD"];
+
+/// Files for a support bundle, collected in memory under a size cap and then
+/// written out as a zip.
+pub(crate) struct Bundle {
+    entries: Vec<(String, Vec<u8>)>,
+    size: u64,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            size: 0,
+        }
+    }
+
+    /// Bytes that can still be added before the cap.
+    pub fn remaining(&self) -> u64 {
+        MAX_BUNDLE_BYTES.saturating_sub(self.size)
+    }
+
+    /// Add `name`, unless it would push the bundle over the cap. Returns
+    /// whether it was added.
+    pub fn add(&mut self, name: impl Into<String>, data: Vec<u8>) -> bool {
+        if data.len() as u64 > self.remaining() {
+            return false;
+        }
+        self.size += data.len() as u64;
+        self.entries.push((name.into(), data));
+        true
+    }
+
+    pub fn add_json<T: Serialize>(&mut self, name: &str, value: &T) -> bool {
+        match serde_json::to_vec_pretty(value) {
+            Ok(data) => self.add(name, data),
+            Err(_) => false,
+        }
+    }
+
+    /// Write the zip to `path`, via a temporary file so a failed export never
+    /// leaves a truncated bundle behind.
+    pub fn write(self, path: &Path) -> io::Result<()> {
+        let partial = partial_path(path);
+        let result = write_zip(&partial, self.entries);
+        match result {
+            Ok(()) => fs::rename(&partial, path),
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                Err(e)
+            }
+        }
+    }
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+fn write_zip(path: &Path, entries: Vec<(String, Vec<u8>)>) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in entries {
+        zip.start_file(name, options).map_err(io::Error::other)?;
+        zip.write_all(&data)?;
+    }
+    zip.finish().map_err(io::Error::other)?.sync_all()
+}
+
+/// The settings file with anything that could hold a secret masked.
+pub(crate) fn sanitize_settings(mut settings: Value) -> Value {
+    if let Value::Object(fields) = &mut settings {
+        for key in SECRET_SETTINGS {
+            if let Some(value) = fields.get_mut(*key) {
+                *value = mask_values(value.take());
+            }
+        }
+    }
+    settings
+}
+
+/// Keep the shape (which keys were set) but none of the values.
+fn mask_values(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, _)| (key, Value::String(MASK.to_string())))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(
+            items.iter().map(|_| Value::String(MASK.to_string())).collect(),
+        ),
+        _ => Value::String(MASK.to_string()),
+    }
+}
+
+/// `CLAUDETINI_*` variables the app is running with, secret-looking ones
+/// masked.
+pub(crate) fn app_env() -> BTreeMap<String, String> {
+    std::env::vars()
+        .filter(|(key, _)| key.starts_with("CLAUDETINI_"))
+        .map(|(key, value)| {
+            let secret = SECRET_ENV_MARKERS.iter().any(|marker| key.contains(marker));
+            let value = if secret { MASK.to_string() } else { value };
+            (key, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn settings_keep_keys_but_not_secrets() {
+        let settings = serde_json::json!({
+            "port": 8123,
+            "env": { "ANTHROPIC_API_KEY": "sk-ant-secret" },
+            "redact_patterns": ["sk-live-[a-z]+"],
+        });
+        let sanitized = sanitize_settings(settings);
+        assert_eq!(sanitized["port"], 8123);
+        assert_eq!(sanitized["env"]["ANTHROPIC_API_KEY"], MASK);
+        assert_eq!(sanitized["redact_patterns"][0], MASK);
+    }
+
+    #[test]
+    fn enforces_the_size_cap_and_writes_a_zip() {
+        let mut bundle = Bundle::new();
+        assert!(bundle.add("a.txt", b"hello".to_vec()));
+        assert!(!bundle.add("big.bin", vec![0; MAX_BUNDLE_BYTES as usize]));
+        assert_eq!(bundle.remaining(), MAX_BUNDLE_BYTES - 5);
+
+        let dir = scratch_dir("bundle");
+        let path = dir.join("bundle.zip");
+        bundle.write(&path).unwrap();
+        let mut zip = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(zip.len(), 1);
+        assert_eq!(zip.by_index(0).unwrap().name(), "a.txt");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
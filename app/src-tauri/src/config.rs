@@ -0,0 +1,1034 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize, Serializer};
+use tracing::{info, warn};
+
+use crate::auth;
+use crate::degradation::DegradationThresholds;
+use crate::health::{Backoff, BodyExpectation, HealthProbe, ReadySignal};
+use crate::logging::{self, LogFilter, LogLevel, FILTER_ENV};
+use crate::logs::{LogBufferLimits, LogRotation};
+use crate::ports::PortRange;
+use crate::redact::MASK;
+use crate::transport::{Transport, LOOPBACK_HOST};
+
+/// Name of the optional settings file in the app config directory.
+pub(crate) const CONFIG_FILE_NAME: &str = "sidecar.json";
+
+/// Floor for the health interval, wherever it's set, so a typo can't hammer
+/// the sidecar.
+pub(crate) const MIN_WATCHDOG_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Bundled sidecar name, as passed to `shell().sidecar()`. White-label builds
+/// can set `CLAUDETINI_SIDECAR_NAME` at compile time to change it, alongside
+/// `externalBin` in `tauri.conf.json`.
+pub(crate) const DEFAULT_SIDECAR_NAME: &str = match option_env!("CLAUDETINI_SIDECAR_NAME") {
+    Some(name) => name,
+    None => "claudetini-sidecar",
+};
+
+/// Tunable settings for sidecar supervision.
+/// Defaults can be overridden by `sidecar.json` in the app config directory,
+/// and both by `CLAUDETINI_*` environment variables. Serializes with durations
+/// in milliseconds and secret-bearing settings masked.
+#[derive(Serialize)]
+pub(crate) struct SidecarConfig {
+    /// Run the app without a sidecar (`CLAUDETINI_NO_SIDECAR=1`, or the
+    /// `--no-sidecar` argument), for working on the frontend in isolation.
+    pub disabled: bool,
+    /// Expect an externally run sidecar on `dev_port` instead of spawning one
+    /// (`CLAUDETINI_MODE=dev|prod`). Defaults to dev in debug builds, so QA
+    /// builds can pick either flow regardless of optimization level.
+    pub dev_mode: bool,
+    /// Locally built sidecar to launch instead of the bundled one
+    /// (`CLAUDETINI_SIDECAR_BIN`). Spawned even in debug builds.
+    pub custom_binary: Option<PathBuf>,
+    /// Name of the bundled sidecar (`CLAUDETINI_SIDECAR_NAME`), without the
+    /// target-triple suffix, which the shell plugin still resolves.
+    pub sidecar_name: String,
+    /// How a spawned sidecar listens (`CLAUDETINI_SIDECAR_TRANSPORT=tcp|socket`).
+    /// The external dev sidecar is always reached over TCP.
+    pub transport: Transport,
+    /// Address the sidecar listens on and is probed at (`CLAUDETINI_SIDECAR_HOST`).
+    /// Must be loopback unless `remote` is set.
+    pub host: String,
+    /// Fixed port for a spawned sidecar (`CLAUDETINI_SIDECAR_PORT`), for local
+    /// tooling that needs it stable. Falls back to an ephemeral port if taken.
+    pub port: Option<u16>,
+    /// Ports a spawned sidecar may use when no fixed port is set or it's taken
+    /// (`CLAUDETINI_PORT_RANGE=20000-20100`), scanned from the low end. Any
+    /// ephemeral port when unset.
+    pub port_range: Option<PortRange>,
+    /// Remember the port of the last healthy spawn in the app config dir and
+    /// try it first next time (`CLAUDETINI_REMEMBER_PORT=true`), for external
+    /// tools that like a stable port. Off by default; a fixed `port` wins.
+    pub remember_port: bool,
+    /// Launch with `--port 0` and wait for the sidecar to print
+    /// `CLAUDETINI_PORT=<port>` once bound (`CLAUDETINI_PORT_HANDSHAKE=true`),
+    /// so no other process can take the port in between. Off by default for
+    /// sidecar builds that don't announce their port; ignored with a fixed
+    /// port, a port range or the socket transport.
+    pub port_handshake: bool,
+    /// Have a spawned sidecar listen on every interface
+    /// (`CLAUDETINI_BIND_LAN=true`) so other devices on the LAN can use it,
+    /// while the app keeps talking to it over loopback. Off by default; only
+    /// applies to the TCP transport on the default loopback host.
+    pub bind_lan: bool,
+    /// Serve a spawned TCP sidecar over HTTPS (`CLAUDETINI_TLS=true`) with a
+    /// self-signed certificate kept per install in the app data dir, and
+    /// pin health checks to it. Off by default. A sidecar that doesn't
+    /// support it is still reached over plain HTTP. Nothing trusts the
+    /// certificate by default: the webview sends requests through
+    /// `proxy_request`, and other clients must pin the `tls_certificate` or
+    /// `tls_fingerprint` from `get_sidecar_connection_info`.
+    pub tls: bool,
+    /// Allow a non-loopback `host` (`CLAUDETINI_REMOTE=true`), for a sidecar
+    /// deliberately running elsewhere.
+    pub remote: bool,
+    /// Try `::1` before `127.0.0.1` when probing a loopback sidecar
+    /// (`CLAUDETINI_PREFER_IPV6=true`). Both are always tried.
+    pub prefer_ipv6: bool,
+    /// Health endpoint path (`CLAUDETINI_HEALTH_PATH`) and the body value that
+    /// must be present for a check to pass (`CLAUDETINI_HEALTH_EXPECT=/ok=true`).
+    pub health_probe: HealthProbe,
+    /// A quicker liveness probe a starting sidecar must pass before the
+    /// health probe is tried, for sidecars with separate `/live` and `/ready`
+    /// endpoints (`CLAUDETINI_LIVE_PATH`, `CLAUDETINI_LIVE_EXPECT`). Off by
+    /// default; the monitor only ever uses the health probe.
+    pub live_probe: Option<HealthProbe>,
+    /// Run a spawned sidecar at lower priority (`CLAUDETINI_SIDECAR_NICE=1..19`)
+    /// so its background work doesn't starve the UI. On Windows any value means
+    /// the below-normal priority class. Failing to apply it is only logged.
+    pub niceness: Option<i32>,
+    /// Extra command-line arguments for the sidecar (`CLAUDETINI_SIDECAR_ARGS`,
+    /// whitespace-separated).
+    pub extra_args: Vec<String>,
+    /// Extra environment variables for a spawned sidecar; only settable from
+    /// the config file. Can't override the auth token.
+    #[serde(serialize_with = "mask_values")]
+    pub env: BTreeMap<String, String>,
+    /// Extra regexes for secrets to mask in sidecar output, on top of bearer
+    /// tokens, `sk-` keys and API key assignments; only settable from the
+    /// config file. A `secret` group limits the mask to that part.
+    #[serde(serialize_with = "mask_items")]
+    pub redact_patterns: Vec<String>,
+    /// Times to try launching the sidecar process before giving up
+    /// (`CLAUDETINI_SPAWN_ATTEMPTS`).
+    pub spawn_attempts: u32,
+    /// Port the externally run dev sidecar listens on (`CLAUDETINI_DEV_PORT`).
+    pub dev_port: u16,
+    /// Delays between startup health poll attempts for a spawned sidecar.
+    pub startup_backoff: Backoff,
+    /// Startup poll delays in dev mode, where the sidecar is usually already
+    /// running, so we poll at a short fixed interval instead of backing off.
+    pub dev_startup_backoff: Backoff,
+    /// Wait before the first startup health check of a spawned sidecar
+    /// (`CLAUDETINI_HEALTH_INITIAL_DELAY_MS`), which would otherwise nearly
+    /// always find nothing bound yet. Counts against `startup_timeout`; skipped
+    /// when the sidecar announces its port, since it has bound by then.
+    #[serde(serialize_with = "serialize_ms")]
+    pub health_initial_delay: Duration,
+    /// Overall time allowed for the sidecar to become healthy after launch.
+    #[serde(serialize_with = "serialize_ms")]
+    pub startup_timeout: Duration,
+    /// Startup health checks a sidecar may need before a successful start is
+    /// reported as `sidecar-slow-start` (`CLAUDETINI_SLOW_START_ATTEMPTS`, 10
+    /// by default).
+    pub slow_start_attempts: u32,
+    /// Extra wait after the first passing health check before the sidecar is
+    /// reported ready (`CLAUDETINI_READY_GRACE_MS`), for sidecars that answer
+    /// `/health` before every route is mounted. Zero by default.
+    #[serde(serialize_with = "serialize_ms")]
+    pub ready_grace_period: Duration,
+    /// What makes a spawned sidecar ready (`CLAUDETINI_READY_SIGNAL=health|line|both|either`):
+    /// a passing health check, a `READY` line on stdout, both, or whichever
+    /// comes first. All share `startup_timeout`. The dev sidecar only has health.
+    pub ready_signal: ReadySignal,
+    /// Delay between background health checks once the sidecar is ready; at
+    /// least `MIN_WATCHDOG_INTERVAL`.
+    #[serde(serialize_with = "serialize_ms")]
+    pub health_interval: Duration,
+    /// Connect timeout for a single background health check.
+    #[serde(serialize_with = "serialize_ms")]
+    pub health_timeout: Duration,
+    /// How long every window must be hidden or minimized before background
+    /// health checks pause (`CLAUDETINI_HIDDEN_GRACE_MS`).
+    #[serde(serialize_with = "serialize_ms")]
+    pub hidden_grace_period: Duration,
+    /// Consecutive failed checks before the sidecar is considered wedged and restarted.
+    pub health_failure_threshold: u32,
+    /// Emit `sidecar-heartbeat` with the round-trip latency after every passing
+    /// background check (`CLAUDETINI_HEARTBEAT_EVENTS=true`).
+    pub heartbeat_events: bool,
+    /// Ping a spawned sidecar over stdin and expect `pong <seq>` on stdout
+    /// (`CLAUDETINI_HEARTBEAT=true`). Off by default; the sidecar must opt in.
+    pub heartbeat: bool,
+    /// How often to ping the sidecar over stdin (`heartbeat_interval_ms` in
+    /// the config file; 10s by default).
+    #[serde(serialize_with = "serialize_ms")]
+    pub heartbeat_interval: Duration,
+    /// How long after a ping its pong may arrive before it counts as missed.
+    #[serde(serialize_with = "serialize_ms")]
+    pub heartbeat_timeout: Duration,
+    /// Consecutive missed pongs before the sidecar is considered hung.
+    pub heartbeat_missed_threshold: u32,
+    /// Sample a spawned sidecar's CPU, memory and open files for
+    /// `sidecar-resources` (`CLAUDETINI_RESOURCE_SAMPLING`, on by default)
+    /// every `resource_interval` (`CLAUDETINI_RESOURCE_INTERVAL_MS`, 5s by
+    /// default). Turning it off saves a little battery.
+    pub resource_sampling: bool,
+    #[serde(serialize_with = "serialize_ms")]
+    pub resource_interval: Duration,
+    /// Relay the sidecar's server-sent events as `sidecar-event`
+    /// (`CLAUDETINI_EVENT_RELAY`, on by default) from `event_stream_path`
+    /// (`CLAUDETINI_EVENT_STREAM_PATH`).
+    pub event_relay: bool,
+    pub event_stream_path: String,
+    /// When a responsive but slow or flapping sidecar is reported as degraded
+    /// (`CLAUDETINI_DEGRADED_P95_MS`, `CLAUDETINI_DEGRADED_FAILURES`).
+    pub degradation: DegradationThresholds,
+    /// When a session's log file is rotated (`CLAUDETINI_LOG_MAX_BYTES`, 5 MiB
+    /// by default), how many older files, earlier sessions' included, are kept
+    /// (`CLAUDETINI_LOG_KEEP_FILES`, 10 by default) and how much all of them
+    /// may take up (`CLAUDETINI_LOG_MAX_TOTAL_BYTES`, 25 MiB by default).
+    pub log_rotation: LogRotation,
+    /// Recent output kept in memory for `get_sidecar_logs` and diagnostics
+    /// (`CLAUDETINI_LOG_BUFFER_LINES`, `CLAUDETINI_LOG_BUFFER_BYTES`).
+    pub log_buffer: LogBufferLimits,
+    /// Longest line of sidecar output kept (`CLAUDETINI_LOG_MAX_LINE_BYTES`,
+    /// 16 KiB by default); the rest of a longer one is dropped and counted.
+    pub log_max_line_bytes: usize,
+    /// Keep the raw bytes of output lines that weren't valid UTF-8 in the
+    /// buffer (`CLAUDETINI_LOG_RAW_BYTES=true`), so the log panel can show
+    /// them as hex. Off by default; replaced bytes are always counted.
+    pub log_raw_bytes: bool,
+    /// Which Rust-side diagnostics are logged (`CLAUDETINI_LOG`, e.g. `debug`
+    /// or `info,claudetini_app_lib::health=trace`). Defaults to `info`.
+    pub log_filter: LogFilter,
+    /// Level picked with `set_log_level` in an earlier session: the default
+    /// for `log_filter` and forwarded to the sidecar. `CLAUDETINI_LOG` wins.
+    pub log_level: Option<LogLevel>,
+    /// Maximum automatic restarts allowed within `restart_window` before giving up.
+    pub max_restarts: u32,
+    #[serde(serialize_with = "serialize_ms")]
+    pub restart_window: Duration,
+    /// How long to wait for the sidecar to exit on its own before force-killing it.
+    #[serde(serialize_with = "serialize_ms")]
+    pub graceful_stop_timeout: Duration,
+    /// Where each setting that isn't a default came from, keyed as it
+    /// serializes. Filled in by `load`.
+    #[serde(skip)]
+    pub sources: BTreeMap<String, ConfigSource>,
+}
+
+/// Which layer a setting's value came from. The level saved by
+/// `set_log_level` lives in the config dir, so it counts as the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ConfigSource {
+    Default,
+    File,
+    Env,
+    /// A command-line argument.
+    Cli,
+}
+
+/// A setting as it serializes, next to where it came from.
+#[derive(Serialize)]
+pub(crate) struct EffectiveSetting {
+    pub value: serde_json::Value,
+    pub source: ConfigSource,
+}
+
+/// Serialize a duration as whole milliseconds.
+pub(crate) fn serialize_ms<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(duration.as_millis() as u64)
+}
+
+/// Keep which keys are set, but none of the values.
+fn mask_values<S: Serializer>(map: &BTreeMap<String, String>, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_map(map.keys().map(|key| (key, MASK)))
+}
+
+fn mask_items<S: Serializer>(items: &[String], s: S) -> Result<S::Ok, S::Error> {
+    s.collect_seq(items.iter().map(|_| MASK))
+}
+
+impl Default for SidecarConfig {
+    fn default() -> Self {
+        Self {
+            disabled: false,
+            dev_mode: cfg!(debug_assertions),
+            custom_binary: None,
+            sidecar_name: DEFAULT_SIDECAR_NAME.to_string(),
+            transport: Transport::Tcp,
+            host: LOOPBACK_HOST.to_string(),
+            port: None,
+            port_range: None,
+            remember_port: false,
+            port_handshake: false,
+            bind_lan: false,
+            tls: false,
+            remote: false,
+            prefer_ipv6: false,
+            health_probe: HealthProbe {
+                path: "/health".to_string(),
+                expect: None,
+            },
+            live_probe: None,
+            niceness: None,
+            extra_args: Vec::new(),
+            env: BTreeMap::new(),
+            redact_patterns: Vec::new(),
+            spawn_attempts: 3,
+            dev_port: 9876,
+            startup_backoff: Backoff {
+                initial: Duration::from_millis(50),
+                max: Duration::from_secs(1),
+                jitter: 0.2,
+            },
+            dev_startup_backoff: Backoff {
+                initial: Duration::from_millis(50),
+                max: Duration::from_millis(50),
+                jitter: 0.2,
+            },
+            health_initial_delay: Duration::from_millis(100),
+            startup_timeout: Duration::from_secs(6),
+            slow_start_attempts: 10,
+            ready_grace_period: Duration::ZERO,
+            ready_signal: ReadySignal::Health,
+            health_interval: Duration::from_secs(5),
+            health_timeout: Duration::from_secs(2),
+            hidden_grace_period: Duration::from_secs(60),
+            health_failure_threshold: 5,
+            heartbeat_events: false,
+            heartbeat: false,
+            heartbeat_interval: Duration::from_secs(10),
+            heartbeat_timeout: Duration::from_secs(5),
+            heartbeat_missed_threshold: 3,
+            resource_sampling: true,
+            resource_interval: Duration::from_secs(5),
+            event_relay: true,
+            event_stream_path: "/api/events".to_string(),
+            degradation: DegradationThresholds {
+                window: Duration::from_secs(120),
+                p95_latency: Duration::from_millis(1000),
+                failures: 2,
+                min_checks: 5,
+                min_event_interval: Duration::from_secs(30),
+            },
+            log_rotation: LogRotation {
+                max_bytes: 5 * 1024 * 1024,
+                keep_files: 10,
+                max_total_bytes: 25 * 1024 * 1024,
+            },
+            log_buffer: LogBufferLimits {
+                max_lines: 1000,
+                max_bytes: 1024 * 1024,
+            },
+            log_max_line_bytes: 16 * 1024,
+            log_raw_bytes: false,
+            log_filter: LogFilter::default(),
+            log_level: None,
+            max_restarts: 3,
+            restart_window: Duration::from_secs(60),
+            graceful_stop_timeout: Duration::from_secs(3),
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+impl SidecarConfig {
+    /// Start from defaults, then apply `sidecar.json` from `config_dir` if it
+    /// exists, then any environment variable overrides.
+    pub fn load(config_dir: Option<&Path>) -> Self {
+        let mut config = Self::default();
+        let defaults = config.snapshot();
+        if let Some(path) = config_dir.map(|dir| dir.join(CONFIG_FILE_NAME)) {
+            match ConfigFile::read(&path) {
+                Ok(Some(file)) => {
+                    info!("Loaded sidecar settings from {}", path.display());
+                    config.apply_file(file);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Ignoring {}: {e}", path.display()),
+            }
+        }
+        if let Some(level) = config_dir.and_then(logging::read_saved_level) {
+            config.log_level = Some(level);
+            config.log_filter = config.log_filter.clone().with_default(level);
+        }
+        let from_file = config.snapshot();
+        config.apply_env();
+        config.sources = sources(&defaults, &from_file, &config.snapshot());
+        config
+    }
+
+    /// Every setting with where its value came from, keyed as it serializes.
+    pub fn effective(&self) -> BTreeMap<String, EffectiveSetting> {
+        self.snapshot()
+            .into_iter()
+            .map(|(key, value)| {
+                let source = self.sources.get(&key).copied().unwrap_or(ConfigSource::Default);
+                (key, EffectiveSetting { value, source })
+            })
+            .collect()
+    }
+
+    fn snapshot(&self) -> serde_json::Map<String, serde_json::Value> {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        }
+    }
+
+    /// The probes a starting sidecar has to pass, in order.
+    pub fn startup_probes(&self) -> Vec<HealthProbe> {
+        self.live_probe.iter().chain([&self.health_probe]).cloned().collect()
+    }
+
+    fn set_live_path(&mut self, path: String) {
+        match &mut self.live_probe {
+            Some(probe) => probe.path = path,
+            None => self.live_probe = Some(HealthProbe { path, expect: None }),
+        }
+    }
+
+    fn apply_file(&mut self, file: ConfigFile) {
+        let config = self;
+        if let Some(mode) = file_value::<Mode>("mode", file.mode) {
+            config.dev_mode = mode == Mode::Dev;
+        }
+        if let Some(path) = file.sidecar_bin.filter(|p| !p.as_os_str().is_empty()) {
+            config.custom_binary = Some(path);
+        }
+        if let Some(name) = file.sidecar_name {
+            match validate_sidecar_name(&name) {
+                Ok(()) => config.sidecar_name = name,
+                Err(e) => warn!("Ignoring sidecar_name in {CONFIG_FILE_NAME}: {e}"),
+            }
+        }
+        if let Some(transport) = file_value::<Transport>("transport", file.transport) {
+            config.transport = transport;
+        }
+        if let Some(bind_lan) = file.bind_lan {
+            config.bind_lan = bind_lan;
+        }
+        if let Some(tls) = file.tls {
+            config.tls = tls;
+        }
+        if let Some(remote) = file.remote {
+            config.remote = remote;
+        }
+        if let Some(prefer) = file.prefer_ipv6 {
+            config.prefer_ipv6 = prefer;
+        }
+        if let Some(host) = file.host {
+            match validate_host(&host, config.remote) {
+                Ok(()) => config.host = host,
+                Err(e) => warn!("Ignoring host in {CONFIG_FILE_NAME}: {e}"),
+            }
+        }
+        if let Some(port) = file.port {
+            config.port = Some(port).filter(|&p| p != 0);
+        }
+        if let Some(range) = file_value::<PortRange>("port_range", file.port_range) {
+            config.port_range = Some(range);
+        }
+        if let Some(remember) = file.remember_port {
+            config.remember_port = remember;
+        }
+        if let Some(handshake) = file.port_handshake {
+            config.port_handshake = handshake;
+        }
+        if let Some(path) = file.health_path {
+            match validate_health_path(&path) {
+                Ok(()) => config.health_probe.path = path,
+                Err(e) => warn!("Ignoring health_path in {CONFIG_FILE_NAME}: {e}"),
+            }
+        }
+        if let Some(expect) = file_value::<BodyExpectation>("health_expect", file.health_expect) {
+            config.health_probe.expect = Some(expect);
+        }
+        if let Some(path) = file.live_path {
+            match validate_health_path(&path) {
+                Ok(()) => config.set_live_path(path),
+                Err(e) => warn!("Ignoring live_path in {CONFIG_FILE_NAME}: {e}"),
+            }
+        }
+        if let Some(expect) = file_value::<BodyExpectation>("live_expect", file.live_expect) {
+            match config.live_probe.as_mut() {
+                Some(probe) => probe.expect = Some(expect),
+                None => warn!("Ignoring live_expect in {CONFIG_FILE_NAME} without live_path"),
+            }
+        }
+        if let Some(nice) = file.nice {
+            config.niceness = Some(nice.clamp(1, 19)).filter(|_| nice > 0);
+        }
+        if let Some(args) = file.args {
+            config.extra_args = args;
+        }
+        for (key, value) in file.env {
+            if key.is_empty() || key.contains('=') || key == auth::TOKEN_ENV {
+                warn!("Ignoring env {key:?} in {CONFIG_FILE_NAME}");
+                continue;
+            }
+            config.env.insert(key, value);
+        }
+        if let Some(patterns) = file.redact_patterns {
+            config.redact_patterns = patterns;
+        }
+        if let Some(n) = file.spawn_attempts {
+            config.spawn_attempts = n.max(1);
+        }
+        if let Some(port) = file.dev_port.filter(|&p| p != 0) {
+            config.dev_port = port;
+        }
+        if let Some(ms) = file.dev_poll_interval_ms {
+            let interval = Duration::from_millis(ms.max(1));
+            config.dev_startup_backoff.initial = interval;
+            config.dev_startup_backoff.max = interval;
+        }
+        if let Some(ms) = file.health_initial_delay_ms {
+            config.health_initial_delay = Duration::from_millis(ms);
+        }
+        if let Some(ms) = file.startup_timeout_ms {
+            config.startup_timeout = Duration::from_millis(ms);
+        }
+        if let Some(n) = file.slow_start_attempts {
+            config.slow_start_attempts = n;
+        }
+        if let Some(ms) = file.ready_grace_ms {
+            config.ready_grace_period = Duration::from_millis(ms);
+        }
+        if let Some(signal) = file_value::<ReadySignal>("ready_signal", file.ready_signal) {
+            config.ready_signal = signal;
+        }
+        if let Some(ms) = file.health_interval_ms {
+            config.health_interval = health_interval("health_interval_ms", ms);
+        }
+        if let Some(ms) = file.health_timeout_ms {
+            config.health_timeout = Duration::from_millis(ms);
+        }
+        if let Some(ms) = file.hidden_grace_ms {
+            config.hidden_grace_period = Duration::from_millis(ms);
+        }
+        if let Some(n) = file.health_failure_threshold {
+            config.health_failure_threshold = n.max(1);
+        }
+        if let Some(enabled) = file.heartbeat_events {
+            config.heartbeat_events = enabled;
+        }
+        if let Some(enabled) = file.heartbeat {
+            config.heartbeat = enabled;
+        }
+        if let Some(ms) = file.heartbeat_interval_ms {
+            config.heartbeat_interval = Duration::from_millis(ms.max(1));
+        }
+        if let Some(ms) = file.heartbeat_timeout_ms {
+            config.heartbeat_timeout = Duration::from_millis(ms);
+        }
+        if let Some(n) = file.heartbeat_missed_threshold {
+            config.heartbeat_missed_threshold = n.max(1);
+        }
+        if let Some(enabled) = file.resource_sampling {
+            config.resource_sampling = enabled;
+        }
+        if let Some(ms) = file.resource_interval_ms {
+            config.resource_interval = Duration::from_millis(ms.max(1));
+        }
+        if let Some(enabled) = file.event_relay {
+            config.event_relay = enabled;
+        }
+        if let Some(path) = file.event_stream_path {
+            match validate_health_path(&path) {
+                Ok(()) => config.event_stream_path = path,
+                Err(e) => warn!("Ignoring event_stream_path in {CONFIG_FILE_NAME}: {e}"),
+            }
+        }
+        if let Some(ms) = file.degraded_p95_ms {
+            config.degradation.p95_latency = Duration::from_millis(ms);
+        }
+        if let Some(n) = file.degraded_failures {
+            config.degradation.failures = n.max(1);
+        }
+        if let Some(bytes) = file.log_max_bytes {
+            config.log_rotation.max_bytes = bytes.max(1);
+        }
+        if let Some(n) = file.log_keep_files {
+            config.log_rotation.keep_files = n;
+        }
+        if let Some(bytes) = file.log_max_total_bytes {
+            config.log_rotation.max_total_bytes = bytes;
+        }
+        if let Some(n) = file.log_buffer_lines {
+            config.log_buffer.max_lines = n.max(1);
+        }
+        if let Some(bytes) = file.log_buffer_bytes {
+            config.log_buffer.max_bytes = bytes;
+        }
+        if let Some(bytes) = file.log_max_line_bytes {
+            config.log_max_line_bytes = bytes.max(1);
+        }
+        if let Some(enabled) = file.log_raw_bytes {
+            config.log_raw_bytes = enabled;
+        }
+        if let Some(filter) = file_value::<LogFilter>("log_filter", file.log_filter) {
+            config.log_filter = filter;
+        }
+        if let Some(n) = file.max_restarts {
+            config.max_restarts = n;
+        }
+        if let Some(ms) = file.restart_window_ms {
+            config.restart_window = Duration::from_millis(ms);
+        }
+        if let Some(ms) = file.graceful_stop_timeout_ms {
+            config.graceful_stop_timeout = Duration::from_millis(ms);
+        }
+    }
+
+    fn apply_env(&mut self) {
+        let config = self;
+        if let Some(disabled) = env_flag("CLAUDETINI_NO_SIDECAR") {
+            config.disabled = disabled;
+        }
+        if let Some(mode) = env_value::<Mode>("CLAUDETINI_MODE") {
+            config.dev_mode = mode == Mode::Dev;
+        }
+        if let Some(path) = std::env::var_os("CLAUDETINI_SIDECAR_BIN").filter(|v| !v.is_empty()) {
+            config.custom_binary = Some(PathBuf::from(path));
+        }
+        if let Ok(name) = std::env::var("CLAUDETINI_SIDECAR_NAME") {
+            match validate_sidecar_name(&name) {
+                Ok(()) => config.sidecar_name = name,
+                Err(e) => warn!("Ignoring CLAUDETINI_SIDECAR_NAME: {e}"),
+            }
+        }
+        if let Some(transport) = env_value::<Transport>("CLAUDETINI_SIDECAR_TRANSPORT") {
+            config.transport = transport;
+        }
+        if let Some(bind_lan) = env_value::<bool>("CLAUDETINI_BIND_LAN") {
+            config.bind_lan = bind_lan;
+        }
+        if let Some(tls) = env_value::<bool>("CLAUDETINI_TLS") {
+            config.tls = tls;
+        }
+        if let Some(remote) = env_value::<bool>("CLAUDETINI_REMOTE") {
+            config.remote = remote;
+        }
+        if let Some(prefer) = env_value::<bool>("CLAUDETINI_PREFER_IPV6") {
+            config.prefer_ipv6 = prefer;
+        }
+        if let Ok(host) = std::env::var("CLAUDETINI_SIDECAR_HOST") {
+            match validate_host(&host, config.remote) {
+                Ok(()) => config.host = host,
+                Err(e) => warn!("Ignoring CLAUDETINI_SIDECAR_HOST: {e}"),
+            }
+        }
+        if let Some(port) = env_value::<u16>("CLAUDETINI_SIDECAR_PORT") {
+            config.port = Some(port).filter(|&p| p != 0);
+        }
+        if let Some(range) = env_value::<PortRange>("CLAUDETINI_PORT_RANGE") {
+            config.port_range = Some(range);
+        }
+        if let Some(remember) = env_value::<bool>("CLAUDETINI_REMEMBER_PORT") {
+            config.remember_port = remember;
+        }
+        if let Some(handshake) = env_value::<bool>("CLAUDETINI_PORT_HANDSHAKE") {
+            config.port_handshake = handshake;
+        }
+        if let Ok(path) = std::env::var("CLAUDETINI_HEALTH_PATH") {
+            match validate_health_path(&path) {
+                Ok(()) => config.health_probe.path = path,
+                Err(e) => warn!("Ignoring CLAUDETINI_HEALTH_PATH: {e}"),
+            }
+        }
+        if let Some(expect) = env_value::<BodyExpectation>("CLAUDETINI_HEALTH_EXPECT") {
+            config.health_probe.expect = Some(expect);
+        }
+        if let Ok(path) = std::env::var("CLAUDETINI_LIVE_PATH") {
+            match validate_health_path(&path) {
+                Ok(()) => config.set_live_path(path),
+                Err(e) => warn!("Ignoring CLAUDETINI_LIVE_PATH: {e}"),
+            }
+        }
+        if let Some(expect) = env_value::<BodyExpectation>("CLAUDETINI_LIVE_EXPECT") {
+            match config.live_probe.as_mut() {
+                Some(probe) => probe.expect = Some(expect),
+                None => warn!("Ignoring CLAUDETINI_LIVE_EXPECT without a live path"),
+            }
+        }
+        if let Some(nice) = env_value::<i32>("CLAUDETINI_SIDECAR_NICE") {
+            config.niceness = Some(nice.clamp(1, 19)).filter(|_| nice > 0);
+        }
+        if let Ok(args) = std::env::var("CLAUDETINI_SIDECAR_ARGS") {
+            config.extra_args = args.split_whitespace().map(String::from).collect();
+        }
+        if let Some(n) = env_value::<u32>("CLAUDETINI_SPAWN_ATTEMPTS") {
+            config.spawn_attempts = n.max(1);
+        }
+        if let Some(port) = env_value::<u16>("CLAUDETINI_DEV_PORT").filter(|&p| p != 0) {
+            config.dev_port = port;
+        }
+        if let Some(ms) = env_value::<u64>("CLAUDETINI_DEV_POLL_INTERVAL_MS") {
+            let interval = Duration::from_millis(ms.max(1));
+            config.dev_startup_backoff.initial = interval;
+            config.dev_startup_backoff.max = interval;
+        }
+        if let Some(ms) = env_value::<u64>("CLAUDETINI_HEALTH_INITIAL_DELAY_MS") {
+            config.health_initial_delay = Duration::from_millis(ms);
+        }
+        if let Some(n) = env_value::<u32>("CLAUDETINI_SLOW_START_ATTEMPTS") {
+            config.slow_start_attempts = n;
+        }
+        if let Some(ms) = env_value::<u64>("CLAUDETINI_READY_GRACE_MS") {
+            config.ready_grace_period = Duration::from_millis(ms);
+        }
+        if let Some(signal) = env_value::<ReadySignal>("CLAUDETINI_READY_SIGNAL") {
+            config.ready_signal = signal;
+        }
+        if let Some(ms) = env_value::<u64>("CLAUDETINI_HEALTH_INTERVAL_MS") {
+            config.health_interval = health_interval("CLAUDETINI_HEALTH_INTERVAL_MS", ms);
+        }
+        if let Some(ms) = env_value::<u64>("CLAUDETINI_HIDDEN_GRACE_MS") {
+            config.hidden_grace_period = Duration::from_millis(ms);
+        }
+        if let Some(n) = env_value::<u32>("CLAUDETINI_HEALTH_FAILURE_THRESHOLD") {
+            config.health_failure_threshold = n.max(1);
+        }
+        if let Some(enabled) = env_value::<bool>("CLAUDETINI_HEARTBEAT_EVENTS") {
+            config.heartbeat_events = enabled;
+        }
+        if let Some(enabled) = env_value::<bool>("CLAUDETINI_EVENT_RELAY") {
+            config.event_relay = enabled;
+        }
+        if let Ok(path) = std::env::var("CLAUDETINI_EVENT_STREAM_PATH") {
+            match validate_health_path(&path) {
+                Ok(()) => config.event_stream_path = path,
+                Err(e) => warn!("Ignoring CLAUDETINI_EVENT_STREAM_PATH: {e}"),
+            }
+        }
+        if let Some(ms) = env_value::<u64>("CLAUDETINI_DEGRADED_P95_MS") {
+            config.degradation.p95_latency = Duration::from_millis(ms);
+        }
+        if let Some(n) = env_value::<usize>("CLAUDETINI_DEGRADED_FAILURES") {
+            config.degradation.failures = n.max(1);
+        }
+        if let Some(enabled) = env_value::<bool>("CLAUDETINI_HEARTBEAT") {
+            config.heartbeat = enabled;
+        }
+        if let Some(enabled) = env_value::<bool>("CLAUDETINI_RESOURCE_SAMPLING") {
+            config.resource_sampling = enabled;
+        }
+        if let Some(ms) = env_value::<u64>("CLAUDETINI_RESOURCE_INTERVAL_MS") {
+            config.resource_interval = Duration::from_millis(ms.max(1));
+        }
+        if let Some(bytes) = env_value::<u64>("CLAUDETINI_LOG_MAX_BYTES") {
+            config.log_rotation.max_bytes = bytes.max(1);
+        }
+        if let Some(n) = env_value::<u32>("CLAUDETINI_LOG_KEEP_FILES") {
+            config.log_rotation.keep_files = n;
+        }
+        if let Some(bytes) = env_value::<u64>("CLAUDETINI_LOG_MAX_TOTAL_BYTES") {
+            config.log_rotation.max_total_bytes = bytes;
+        }
+        if let Some(n) = env_value::<usize>("CLAUDETINI_LOG_BUFFER_LINES") {
+            config.log_buffer.max_lines = n.max(1);
+        }
+        if let Some(bytes) = env_value::<usize>("CLAUDETINI_LOG_BUFFER_BYTES") {
+            config.log_buffer.max_bytes = bytes;
+        }
+        if let Some(bytes) = env_value::<usize>("CLAUDETINI_LOG_MAX_LINE_BYTES") {
+            config.log_max_line_bytes = bytes.max(1);
+        }
+        if let Some(enabled) = env_value::<bool>("CLAUDETINI_LOG_RAW_BYTES") {
+            config.log_raw_bytes = enabled;
+        }
+        if let Some(filter) = env_value::<LogFilter>(FILTER_ENV) {
+            config.log_filter = filter;
+        }
+    }
+}
+
+/// Value of `CLAUDETINI_MODE`.
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Dev,
+    Prod,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dev" | "development" => Ok(Mode::Dev),
+            "prod" | "production" | "release" => Ok(Mode::Prod),
+            other => Err(format!("unknown mode {other:?} (expected dev or prod)")),
+        }
+    }
+}
+
+/// Contents of `sidecar.json`. Every key is optional; durations are in
+/// milliseconds and names follow the matching `CLAUDETINI_*` variables.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    mode: Option<String>,
+    sidecar_bin: Option<PathBuf>,
+    sidecar_name: Option<String>,
+    transport: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    port_range: Option<String>,
+    remember_port: Option<bool>,
+    port_handshake: Option<bool>,
+    bind_lan: Option<bool>,
+    tls: Option<bool>,
+    remote: Option<bool>,
+    prefer_ipv6: Option<bool>,
+    health_path: Option<String>,
+    health_expect: Option<String>,
+    live_path: Option<String>,
+    live_expect: Option<String>,
+    nice: Option<i32>,
+    args: Option<Vec<String>>,
+    env: BTreeMap<String, String>,
+    redact_patterns: Option<Vec<String>>,
+    spawn_attempts: Option<u32>,
+    dev_port: Option<u16>,
+    dev_poll_interval_ms: Option<u64>,
+    health_initial_delay_ms: Option<u64>,
+    startup_timeout_ms: Option<u64>,
+    slow_start_attempts: Option<u32>,
+    ready_grace_ms: Option<u64>,
+    ready_signal: Option<String>,
+    health_interval_ms: Option<u64>,
+    health_timeout_ms: Option<u64>,
+    hidden_grace_ms: Option<u64>,
+    health_failure_threshold: Option<u32>,
+    heartbeat_events: Option<bool>,
+    heartbeat: Option<bool>,
+    heartbeat_interval_ms: Option<u64>,
+    heartbeat_timeout_ms: Option<u64>,
+    heartbeat_missed_threshold: Option<u32>,
+    resource_sampling: Option<bool>,
+    resource_interval_ms: Option<u64>,
+    event_relay: Option<bool>,
+    event_stream_path: Option<String>,
+    degraded_p95_ms: Option<u64>,
+    degraded_failures: Option<usize>,
+    log_max_bytes: Option<u64>,
+    log_keep_files: Option<u32>,
+    log_max_total_bytes: Option<u64>,
+    log_buffer_lines: Option<usize>,
+    log_buffer_bytes: Option<usize>,
+    log_max_line_bytes: Option<usize>,
+    log_raw_bytes: Option<bool>,
+    log_filter: Option<String>,
+    max_restarts: Option<u32>,
+    restart_window_ms: Option<u64>,
+    graceful_stop_timeout_ms: Option<u64>,
+}
+
+impl ConfigFile {
+    /// `Ok(None)` when there is no file, so defaults apply silently.
+    fn read(path: &Path) -> Result<Option<Self>, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map(Some).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Accept loopback IPs and `localhost`; anything else only in remote mode.
+pub(crate) fn validate_host(host: &str, remote: bool) -> Result<(), String> {
+    if host.is_empty() || host.contains(|c: char| c.is_whitespace() || c == '/') {
+        return Err(format!("{host:?} is not a valid host"));
+    }
+    let loopback = host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    if !loopback && !remote {
+        return Err(format!(
+            "{host} is not a loopback address; set CLAUDETINI_REMOTE=true to allow it"
+        ));
+    }
+    Ok(())
+}
+
+/// The health path goes straight into the request line, so it must be a
+/// plain absolute path.
+pub(crate) fn validate_health_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') || path.contains(|c: char| c.is_whitespace() || c.is_control()) {
+        return Err(format!("{path:?} must be an absolute path without spaces"));
+    }
+    Ok(())
+}
+
+/// A bundled sidecar name is a bare file name; paths go in `sidecar_bin`.
+fn validate_sidecar_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!("{name:?} is not a plain binary name"));
+    }
+    Ok(())
+}
+
+/// Attribute each setting to the last layer that changed it. One set to its
+/// default value counts as a default.
+fn sources(
+    defaults: &serde_json::Map<String, serde_json::Value>,
+    from_file: &serde_json::Map<String, serde_json::Value>,
+    resolved: &serde_json::Map<String, serde_json::Value>,
+) -> BTreeMap<String, ConfigSource> {
+    resolved
+        .iter()
+        .filter_map(|(key, value)| {
+            if from_file.get(key) != Some(value) {
+                Some((key.clone(), ConfigSource::Env))
+            } else if defaults.get(key) != Some(value) {
+                Some((key.clone(), ConfigSource::File))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// A configured health interval, raised to `MIN_WATCHDOG_INTERVAL` if `key`
+/// set it lower.
+fn health_interval(key: &str, ms: u64) -> Duration {
+    let interval = Duration::from_millis(ms);
+    if interval < MIN_WATCHDOG_INTERVAL {
+        let floor = MIN_WATCHDOG_INTERVAL.as_millis();
+        warn!("{key} of {ms}ms is below the {floor}ms minimum; using {floor}ms");
+        return MIN_WATCHDOG_INTERVAL;
+    }
+    interval
+}
+
+/// Parse a string-valued config file key, ignoring it (with a warning) if malformed.
+fn file_value<T: FromStr<Err = String>>(key: &str, raw: Option<String>) -> Option<T> {
+    match raw?.parse() {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Ignoring {key} in {CONFIG_FILE_NAME}: {e}");
+            None
+        }
+    }
+}
+
+/// An on/off environment variable, accepting `1`/`0` and `yes`/`no` as well
+/// as `true`/`false`.
+fn env_flag(key: &str) -> Option<bool> {
+    let raw = std::env::var(key).ok()?;
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => {
+            warn!("Ignoring invalid {key}={raw:?}");
+            None
+        }
+    }
+}
+
+/// Parse an environment variable, ignoring it (with a warning) if malformed.
+fn env_value<T: FromStr>(key: &str) -> Option<T> {
+    let raw = std::env::var(key).ok()?;
+    match raw.trim().parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!("Ignoring invalid {key}={raw:?}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_must_be_loopback_unless_remote() {
+        assert!(validate_host("127.0.0.1", false).is_ok());
+        assert!(validate_host("127.0.0.2", false).is_ok());
+        assert!(validate_host("::1", false).is_ok());
+        assert!(validate_host("localhost", false).is_ok());
+        assert!(validate_host("192.168.1.20", false).is_err());
+        assert!(validate_host("sidecar.internal", false).is_err());
+        assert!(validate_host("192.168.1.20", true).is_ok());
+        assert!(validate_host("", true).is_err());
+    }
+
+    #[test]
+    fn config_file_overrides_defaults() {
+        let file: ConfigFile = serde_json::from_str(
+            r#"{
+                "port": 8123,
+                "health_path": "/api/healthz",
+                "live_path": "/api/live",
+                "args": ["--workers", "2"],
+                "env": { "PYTHONUNBUFFERED": "1", "CLAUDETINI_SIDECAR_TOKEN": "x" },
+                "health_interval_ms": 1500,
+                "resource_sampling": false,
+                "host": "10.0.0.5"
+            }"#,
+        )
+        .unwrap();
+        let mut config = SidecarConfig::default();
+        config.apply_file(file);
+        assert_eq!(config.port, Some(8123));
+        assert_eq!(config.health_probe.path, "/api/healthz");
+        let probes: Vec<_> = config.startup_probes().into_iter().map(|p| p.path).collect();
+        assert_eq!(probes, ["/api/live", "/api/healthz"]);
+        assert_eq!(config.extra_args, ["--workers", "2"]);
+        assert_eq!(config.env.len(), 1);
+        assert_eq!(config.health_interval, Duration::from_millis(1500));
+        assert!(!config.resource_sampling);
+        // Still loopback-only without `remote`.
+        assert_eq!(config.host, LOOPBACK_HOST);
+        assert_eq!(config.spawn_attempts, 3);
+        let serialized = serde_json::to_value(&config).unwrap();
+        assert_eq!(serialized["env"]["PYTHONUNBUFFERED"], MASK);
+        assert_eq!(serialized["health_interval"], 1500);
+
+        let defaults = SidecarConfig::default().snapshot();
+        let from_file = config.snapshot();
+        config.port = Some(9000);
+        config.sources = sources(&defaults, &from_file, &config.snapshot());
+        let effective = config.effective();
+        assert_eq!(effective["port"].source, ConfigSource::Env);
+        assert_eq!(effective["port"].value, 9000);
+        assert_eq!(effective["health_interval"].source, ConfigSource::File);
+        assert_eq!(effective["spawn_attempts"].source, ConfigSource::Default);
+        assert!(!effective.contains_key("sources"));
+
+        assert!(serde_json::from_str::<ConfigFile>(r#"{ "prot": 1 }"#).is_err());
+
+        let file = serde_json::from_str(r#"{ "mode": "prod" }"#).unwrap();
+        config.apply_file(file);
+        assert!(!config.dev_mode);
+
+        let file = serde_json::from_str(r#"{ "health_interval_ms": 0 }"#).unwrap();
+        config.apply_file(file);
+        assert_eq!(config.health_interval, MIN_WATCHDOG_INTERVAL);
+
+        let file = serde_json::from_str(r#"{ "sidecar_name": "../acme" }"#).unwrap();
+        config.apply_file(file);
+        assert_eq!(config.sidecar_name, DEFAULT_SIDECAR_NAME);
+        let file = serde_json::from_str(r#"{ "sidecar_name": "acme-sidecar" }"#).unwrap();
+        config.apply_file(file);
+        assert_eq!(config.sidecar_name, "acme-sidecar");
+    }
+
+    #[test]
+    fn health_path_must_be_a_plain_absolute_path() {
+        assert!(validate_health_path("/api/healthz").is_ok());
+        assert!(validate_health_path("health").is_err());
+        assert!(validate_health_path("/health HTTP/1.1\r\nX: y").is_err());
+    }
+}
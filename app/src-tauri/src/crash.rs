@@ -0,0 +1,179 @@
+use std::backtrace::Backtrace;
+use std::fs;
+use std::io;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::logs::LogLine;
+
+/// Directory under the app data dir that crash reports are written to.
+pub(crate) const CRASH_DIR: &str = "crashes";
+/// Buffered output lines kept in a report.
+pub(crate) const REPORT_LOG_LINES: usize = 100;
+const PREFIX: &str = "crash-";
+/// A report is read once `app-crash-detected` has told the frontend about
+/// it; a marker file with this extension records that.
+const READ_MARKER: &str = "read";
+
+/// What the panic hook writes to `crashes/crash-<unix ms>.json`.
+#[derive(Serialize)]
+pub(crate) struct CrashReport {
+    pub timestamp: u64,
+    pub app_version: String,
+    pub message: String,
+    /// `file:line:column` of the panic.
+    pub location: Option<String>,
+    pub thread: Option<String>,
+    pub backtrace: String,
+    /// `SidecarStatus` at the time, or `null` if the state was locked by the
+    /// panicking thread.
+    pub sidecar_status: serde_json::Value,
+    pub recent_logs: Vec<LogLine>,
+}
+
+impl CrashReport {
+    /// Everything about the panic itself; the caller adds app details.
+    pub fn new(info: &PanicHookInfo<'_>, timestamp: u64) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| (*s).to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "non-string panic payload".to_string());
+        Self {
+            timestamp,
+            app_version: String::new(),
+            message,
+            location: info.location().map(ToString::to_string),
+            thread: std::thread::current().name().map(str::to_string),
+            backtrace: Backtrace::force_capture().to_string(),
+            sidecar_status: serde_json::Value::Null,
+            recent_logs: Vec::new(),
+        }
+    }
+}
+
+/// A crash report on disk, as `list_crash_reports` shows it.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct CrashReportInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub timestamp: u64,
+    pub message: String,
+    pub size_bytes: u64,
+    pub read: bool,
+}
+
+/// Fields of a report needed to list it.
+#[derive(Deserialize)]
+struct Summary {
+    timestamp: u64,
+    message: String,
+}
+
+/// Reports not yet announced with `app-crash-detected`, held until the
+/// frontend has loaded and can hear it.
+pub(crate) struct PendingCrashes(Mutex<Option<Vec<CrashReportInfo>>>);
+
+impl PendingCrashes {
+    pub fn new(reports: Vec<CrashReportInfo>) -> Self {
+        Self(Mutex::new(Some(reports).filter(|r| !r.is_empty())))
+    }
+
+    /// The reports to announce, the first time only.
+    pub fn take(&self) -> Option<Vec<CrashReportInfo>> {
+        self.0.lock().ok()?.take()
+    }
+}
+
+/// Write `report` to `dir`, creating it if needed.
+pub(crate) fn write(dir: &Path, report: &CrashReport) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{PREFIX}{}.json", report.timestamp));
+    let json = serde_json::to_vec_pretty(report).map_err(io::Error::other)?;
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+/// Every report in `dir`, newest first. Files that don't parse as a report
+/// are skipped; a missing directory just means no crashes.
+pub(crate) fn list(dir: &Path) -> io::Result<Vec<CrashReportInfo>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut reports = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(PREFIX) || path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let summary = fs::read(&path).ok().and_then(|raw| serde_json::from_slice(&raw).ok());
+        let Some(Summary { timestamp, message }) = summary else {
+            continue;
+        };
+        reports.push(CrashReportInfo {
+            read: path.with_extension(READ_MARKER).exists(),
+            size_bytes: entry.metadata().map_or(0, |m| m.len()),
+            name,
+            path,
+            timestamp,
+            message,
+        });
+    }
+    reports.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
+    Ok(reports)
+}
+
+/// Record that the frontend has been told about `reports`.
+pub(crate) fn mark_read(reports: &[CrashReportInfo]) -> io::Result<()> {
+    for report in reports {
+        fs::write(report.path.with_extension(READ_MARKER), b"")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    fn report(timestamp: u64, message: &str) -> CrashReport {
+        CrashReport {
+            timestamp,
+            app_version: "1.0.0".into(),
+            message: message.into(),
+            location: Some("src/lib.rs:1:1".into()),
+            thread: None,
+            backtrace: String::new(),
+            sidecar_status: serde_json::json!({ "state": "ready" }),
+            recent_logs: vec![LogLine::new("stdout", "hello".into(), timestamp)],
+        }
+    }
+
+    #[test]
+    fn lists_newest_first_and_tracks_read() {
+        let dir = scratch_dir("crashes");
+        assert!(list(&dir).unwrap().is_empty());
+
+        write(&dir, &report(1, "first")).unwrap();
+        write(&dir, &report(2, "second")).unwrap();
+        fs::write(dir.join("notes.txt"), b"not a report").unwrap();
+        fs::write(dir.join("crash-3.json"), b"{ truncated").unwrap();
+
+        let reports = list(&dir).unwrap();
+        let messages: Vec<_> = reports.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!(messages, ["second", "first"]);
+        assert!(reports.iter().all(|r| !r.read && r.size_bytes > 0));
+
+        mark_read(&reports[1..]).unwrap();
+        let read: Vec<_> = list(&dir).unwrap().iter().map(|r| r.read).collect();
+        assert_eq!(read, [false, true]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::config::serialize_ms;
+use crate::health::HealthStats;
+
+/// When a sidecar that is still passing most checks counts as degraded.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub(crate) struct DegradationThresholds {
+    /// How far back the monitor looks when judging latency and failures.
+    #[serde(serialize_with = "serialize_ms")]
+    pub window: Duration,
+    /// p95 latency of successful checks above which the sidecar is degraded.
+    #[serde(serialize_with = "serialize_ms")]
+    pub p95_latency: Duration,
+    /// Failed checks within the window that count as flapping.
+    pub failures: usize,
+    /// Minimum checks in the window before latency is judged at all.
+    pub min_checks: usize,
+    /// Minimum time between `sidecar-degraded`/`-cleared` events.
+    #[serde(serialize_with = "serialize_ms")]
+    pub min_event_interval: Duration,
+}
+
+/// A change worth telling the frontend about.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Transition {
+    Degraded(String),
+    Cleared,
+}
+
+/// Remembers whether we last reported degraded, so events fire on changes
+/// only and no more often than `min_event_interval`.
+#[derive(Default)]
+pub(crate) struct DegradationTracker {
+    degraded: bool,
+    last_change: Option<Instant>,
+}
+
+impl DegradationTracker {
+    pub fn evaluate(
+        &mut self,
+        stats: &HealthStats,
+        thresholds: &DegradationThresholds,
+        now: Instant,
+    ) -> Option<Transition> {
+        let reason = degradation_reason(stats, thresholds);
+        if reason.is_some() == self.degraded {
+            return None;
+        }
+        let rate_limited = self
+            .last_change
+            .is_some_and(|t| now.duration_since(t) < thresholds.min_event_interval);
+        if rate_limited {
+            return None;
+        }
+        self.degraded = reason.is_some();
+        self.last_change = Some(now);
+        Some(reason.map_or(Transition::Cleared, Transition::Degraded))
+    }
+}
+
+fn degradation_reason(stats: &HealthStats, thresholds: &DegradationThresholds) -> Option<String> {
+    let window_secs = thresholds.window.as_secs();
+    if stats.failures_in_window >= thresholds.failures {
+        return Some(format!(
+            "{} of the last {} health checks failed within {window_secs}s",
+            stats.failures_in_window, stats.checks_in_window
+        ));
+    }
+    let limit_ms = thresholds.p95_latency.as_millis() as u64;
+    match stats.latency_p95_ms {
+        Some(p95) if stats.checks_in_window >= thresholds.min_checks && p95 > limit_ms => Some(
+            format!("Health check p95 latency {p95}ms exceeds {limit_ms}ms over {window_secs}s"),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: DegradationThresholds = DegradationThresholds {
+        window: Duration::from_secs(120),
+        p95_latency: Duration::from_millis(1000),
+        failures: 2,
+        min_checks: 5,
+        min_event_interval: Duration::from_secs(30),
+    };
+
+    fn stats(checks: usize, failures: usize, p95: u64) -> HealthStats {
+        HealthStats {
+            window_secs: 120,
+            checks_in_window: checks,
+            failures_in_window: failures,
+            success_ratio: None,
+            latency_p50_ms: Some(p95 / 2),
+            latency_p95_ms: Some(p95),
+            ms_since_last_failure: None,
+        }
+    }
+
+    #[test]
+    fn reports_slow_or_flapping_sidecar_once() {
+        let mut tracker = DegradationTracker::default();
+        let t0 = Instant::now();
+
+        assert_eq!(tracker.evaluate(&stats(10, 0, 50), &THRESHOLDS, t0), None);
+        let slow = tracker.evaluate(&stats(10, 0, 2500), &THRESHOLDS, t0);
+        assert!(matches!(slow, Some(Transition::Degraded(r)) if r.contains("2500ms")));
+        // Still degraded, now for a different reason: no new event.
+        assert_eq!(tracker.evaluate(&stats(10, 3, 50), &THRESHOLDS, t0), None);
+    }
+
+    #[test]
+    fn ignores_latency_until_enough_checks() {
+        let mut tracker = DegradationTracker::default();
+        assert_eq!(tracker.evaluate(&stats(2, 0, 5000), &THRESHOLDS, Instant::now()), None);
+    }
+
+    #[test]
+    fn transitions_are_rate_limited() {
+        let mut tracker = DegradationTracker::default();
+        let t0 = Instant::now();
+        let flapping = stats(10, 2, 50);
+        let healthy = stats(10, 0, 50);
+
+        assert!(tracker.evaluate(&flapping, &THRESHOLDS, t0).is_some());
+        let soon = t0 + Duration::from_secs(5);
+        assert_eq!(tracker.evaluate(&healthy, &THRESHOLDS, soon), None);
+        let later = t0 + Duration::from_secs(31);
+        assert_eq!(tracker.evaluate(&healthy, &THRESHOLDS, later), Some(Transition::Cleared));
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::transport::SidecarEndpoint;
+
+/// Name of the discovery file inside the app data dir.
+const DISCOVERY_FILE_NAME: &str = "sidecar.json";
+
+/// What external tooling (CLI helpers, editor plugins) needs to reach the
+/// running sidecar. Holds the auth token, so only the current user may read it.
+#[derive(Serialize)]
+pub(crate) struct Discovery<'a> {
+    /// TCP port, or `None` when the sidecar listens on a socket.
+    pub port: Option<u16>,
+    pub endpoint: &'a SidecarEndpoint,
+    pub pid: u32,
+    pub token: Option<&'a str>,
+    pub app_version: &'a str,
+}
+
+pub(crate) fn discovery_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(DISCOVERY_FILE_NAME)
+}
+
+/// Replace the discovery file atomically, so readers never see a partial
+/// write, creating it readable by the current user only.
+pub(crate) fn write(path: &Path, discovery: &Discovery) -> io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    let _ = fs::remove_file(&tmp);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp)?;
+    serde_json::to_writer_pretty(&mut file, discovery)?;
+    file.write_all(b"\n")?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Delete the discovery file; a file that's already gone is fine.
+pub(crate) fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn writes_private_file_and_removes_it() {
+        let dir = scratch_dir("discovery");
+        let path = discovery_path(&dir);
+        let endpoint = SidecarEndpoint::tcp("127.0.0.1", 8123);
+        let discovery = Discovery {
+            port: endpoint.port(),
+            endpoint: &endpoint,
+            pid: 42,
+            token: Some("secret"),
+            app_version: "0.1.0",
+        };
+
+        write(&path, &discovery).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(json["port"], 8123);
+        assert_eq!(json["token"], "secret");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        remove(&path).unwrap();
+        assert!(!path.exists());
+        remove(&path).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
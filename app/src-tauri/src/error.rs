@@ -0,0 +1,360 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Serialize, Serializer};
+
+use crate::paths::DirError;
+
+/// Stderr lines of a sidecar that died while starting quoted in the message.
+const QUOTED_STDERR_LINES: usize = 5;
+
+/// Everything that can go wrong starting, reaching, or controlling the sidecar.
+/// Sources are behind `Arc` so results can be shared between restart callers.
+#[derive(Clone, Debug)]
+pub(crate) enum SidecarError {
+    /// No loopback port could be bound.
+    PortBind(Arc<io::Error>),
+    /// A port was bound but its address couldn't be read back.
+    LocalAddr(Arc<io::Error>),
+    /// The app data/log directories are unusable.
+    AppDirs(Arc<DirError>),
+    /// The sidecar log file couldn't be read.
+    LogFile(Arc<io::Error>),
+    /// The diagnostics bundle couldn't be written.
+    Diagnostics(Arc<io::Error>),
+    /// The log directory hasn't been created yet.
+    LogDirMissing(PathBuf),
+    /// Nothing has been written to the log file yet.
+    LogFileMissing(PathBuf),
+    /// `read_log_file` was asked for something that isn't a log file in the
+    /// log dir.
+    UnknownLogFile(String),
+    /// The OS refused to open a path for us.
+    Open(String),
+    /// `CLAUDETINI_SIDECAR_BIN` doesn't point at something we can run.
+    CustomBinary(String),
+    /// The socket transport couldn't be set up.
+    Socket(String),
+    /// No random auth token could be generated.
+    Token(String),
+    /// The shell plugin couldn't build the sidecar command.
+    Command(String),
+    /// Every spawn attempt failed.
+    Spawn { attempts: u32 },
+    /// Another process kept taking the sidecar's port before it could bind.
+    PortInUse { attempts: u32 },
+    /// A sidecar launched with `--port 0` never printed the port it bound.
+    PortNotAnnounced { after_ms: u128 },
+    /// The ready signal asked for a `READY` line that never came.
+    ReadyLineMissing { after_ms: u128 },
+    /// Nothing accepted a connection at the endpoint.
+    Connect { endpoint: String, source: Arc<io::Error> },
+    /// A single probe didn't finish in time.
+    Timeout { endpoint: String, after_ms: u128 },
+    /// Something answered, but not with HTTP we understand.
+    InvalidResponse { endpoint: String, reason: String },
+    /// Something other than our sidecar answered on the dev port.
+    WrongService { endpoint: String, responder: String },
+    /// The health endpoint answered with a non-2xx status.
+    Unhealthy { endpoint: String, status: u16 },
+    /// The health body didn't hold the configured value.
+    UnexpectedBody { endpoint: String, reason: String },
+    /// The sidecar process exited before it ever passed a health check.
+    /// `stderr` holds the first few lines it printed there.
+    ExitedDuringStartup {
+        code: Option<i32>,
+        signal: Option<i32>,
+        stderr: Vec<String>,
+    },
+    /// The sidecar never became healthy during startup.
+    HealthTimeout {
+        attempts: u32,
+        endpoint: String,
+        last: Box<SidecarError>,
+    },
+    /// The automatic restart budget is exhausted.
+    CrashLoop {
+        restarts: u32,
+        window_secs: u64,
+        reason: String,
+    },
+    /// The sidecar isn't up, so there's nothing to forward to.
+    NotReady,
+    /// No sidecar process is running.
+    NotRunning,
+    /// The sidecar never acknowledged a reload on its control channel.
+    ReloadTimeout { after_ms: u128 },
+    /// The sidecar didn't become ready while `wait_for_sidecar_ready` waited.
+    ReadyTimeout { after_ms: u128 },
+    /// The signal number isn't one the sidecar may be sent.
+    #[cfg_attr(windows, allow(dead_code))]
+    InvalidSignal(i32),
+    /// Sending a signal failed, or signals aren't supported here.
+    Signal(Arc<io::Error>),
+    /// The sidecar closed its stdin, so nothing more can be written to it.
+    StdinClosed,
+    /// Writing to the sidecar's stdin failed otherwise.
+    Stdin(Arc<io::Error>),
+    /// The sidecar's TLS certificate couldn't be read or generated.
+    Tls(Arc<io::Error>),
+    /// TLS was asked for while it's off.
+    TlsDisabled,
+    /// A proxied request was malformed or over the size limit.
+    InvalidProxyRequest(String),
+    /// A proxied response was larger than the proxy will buffer.
+    ResponseTooLarge { limit: u64 },
+    /// `set_log_level` was given something other than a known level.
+    InvalidLogLevel(String),
+    /// A log subscription filter named an unknown level or a bad regex.
+    InvalidLogFilter(String),
+    /// Extra arguments tried to set a flag the app manages.
+    ReservedArg(String),
+    /// A debugging aid that release builds leave out.
+    DebugOnly,
+    /// Crash reports couldn't be listed.
+    CrashReports(Arc<io::Error>),
+    /// The app was started without a sidecar.
+    Disabled,
+    /// The system clipboard couldn't be written.
+    Clipboard(String),
+    /// The dev sidecar isn't ours to restart or signal.
+    ExternalSidecar,
+    RestartInProgress,
+    /// Whoever was performing a restart gave up without a result.
+    RestartAbandoned,
+    LockPoisoned,
+}
+
+impl SidecarError {
+    /// Stable identifier for events and frontend matching.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SidecarError::PortBind(_) | SidecarError::LocalAddr(_) => "port_bind",
+            SidecarError::AppDirs(_) => "app_dirs",
+            SidecarError::LogFile(_) => "log_file",
+            SidecarError::Diagnostics(_) => "diagnostics_export",
+            SidecarError::LogDirMissing(_) => "log_dir_missing",
+            SidecarError::LogFileMissing(_) => "log_file_missing",
+            SidecarError::UnknownLogFile(_) => "unknown_log_file",
+            SidecarError::Open(_) => "open",
+            SidecarError::CustomBinary(_) => "custom_binary",
+            SidecarError::Socket(_) => "socket",
+            SidecarError::Token(_) => "token",
+            SidecarError::Command(_) => "command",
+            SidecarError::Spawn { .. } => "spawn",
+            SidecarError::PortInUse { .. } => "port_in_use",
+            SidecarError::PortNotAnnounced { .. } => "port_not_announced",
+            SidecarError::ReadyLineMissing { .. } => "ready_line_missing",
+            SidecarError::Connect { .. } => "connect",
+            SidecarError::Timeout { .. } => "timeout",
+            SidecarError::InvalidResponse { .. } => "invalid_response",
+            SidecarError::WrongService { .. } => "wrong_service_on_dev_port",
+            SidecarError::Unhealthy { .. } => "unhealthy",
+            SidecarError::UnexpectedBody { .. } => "unexpected_body",
+            SidecarError::ExitedDuringStartup { .. } => "exited_during_startup",
+            SidecarError::HealthTimeout { .. } => "health_timeout",
+            SidecarError::CrashLoop { .. } => "crash_loop",
+            SidecarError::NotReady => "not_ready",
+            SidecarError::NotRunning => "not_running",
+            SidecarError::ReloadTimeout { .. } => "reload_timeout",
+            SidecarError::ReadyTimeout { .. } => "ready_timeout",
+            SidecarError::InvalidSignal(_) => "invalid_signal",
+            SidecarError::Signal(_) => "signal",
+            SidecarError::StdinClosed => "stdin_closed",
+            SidecarError::Stdin(_) => "stdin",
+            SidecarError::Tls(_) => "tls",
+            SidecarError::TlsDisabled => "tls_disabled",
+            SidecarError::InvalidProxyRequest(_) => "invalid_request",
+            SidecarError::ResponseTooLarge { .. } => "response_too_large",
+            SidecarError::InvalidLogLevel(_) => "invalid_log_level",
+            SidecarError::InvalidLogFilter(_) => "invalid_log_filter",
+            SidecarError::ReservedArg(_) => "invalid_args",
+            SidecarError::DebugOnly => "debug_only",
+            SidecarError::CrashReports(_) => "crash_reports",
+            SidecarError::Disabled => "sidecar_disabled",
+            SidecarError::Clipboard(_) => "clipboard",
+            SidecarError::ExternalSidecar => "external_sidecar",
+            SidecarError::RestartInProgress => "restart_in_progress",
+            SidecarError::RestartAbandoned => "restart_abandoned",
+            SidecarError::LockPoisoned => "lock_poisoned",
+        }
+    }
+}
+
+impl fmt::Display for SidecarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SidecarError::PortBind(e) => write!(f, "Failed to bind TCP: {e}"),
+            SidecarError::LocalAddr(e) => write!(f, "Failed to get local addr: {e}"),
+            SidecarError::AppDirs(e) => write!(f, "{e}"),
+            SidecarError::LogFile(e) => write!(f, "Could not read the sidecar log file: {e}"),
+            SidecarError::Diagnostics(e) => {
+                write!(f, "Could not write the diagnostics bundle: {e}")
+            }
+            SidecarError::LogDirMissing(path) => {
+                write!(f, "The log folder {} doesn't exist yet", path.display())
+            }
+            SidecarError::LogFileMissing(path) => {
+                write!(f, "Nothing has been logged to {} yet", path.display())
+            }
+            SidecarError::UnknownLogFile(name) => write!(f, "{name:?} is not a log file"),
+            SidecarError::Open(e) => write!(f, "Could not show it in the file manager: {e}"),
+            SidecarError::CustomBinary(message) | SidecarError::Socket(message) => {
+                f.write_str(message)
+            }
+            SidecarError::Token(e) => write!(f, "Failed to generate a sidecar auth token: {e}"),
+            SidecarError::Command(e) => write!(f, "Failed to create sidecar command: {e}"),
+            SidecarError::Spawn { attempts } => {
+                write!(f, "All {attempts} sidecar spawn attempts failed")
+            }
+            SidecarError::PortInUse { attempts } => write!(
+                f,
+                "The sidecar's port was taken by another process {attempts} times in a row"
+            ),
+            SidecarError::PortNotAnnounced { after_ms } => write!(
+                f,
+                "The sidecar didn't print CLAUDETINI_PORT=<port> within {after_ms}ms; \
+                 it may not support --port 0 (unset CLAUDETINI_PORT_HANDSHAKE)"
+            ),
+            SidecarError::ReadyLineMissing { after_ms } => write!(
+                f,
+                "The sidecar didn't print READY within {after_ms}ms; \
+                 set CLAUDETINI_READY_SIGNAL=health if it never does"
+            ),
+            SidecarError::Connect { endpoint, source } => {
+                write!(f, "Connection to {endpoint} failed: {source}")
+            }
+            SidecarError::Timeout { endpoint, after_ms } => {
+                write!(f, "Connection to {endpoint} timed out after {after_ms}ms")
+            }
+            SidecarError::InvalidResponse { endpoint, reason } => {
+                write!(f, "Invalid HTTP response from {endpoint}: {reason}")
+            }
+            SidecarError::WrongService {
+                endpoint,
+                responder,
+            } => write!(
+                f,
+                "{endpoint} is answered by something other than the claudetini sidecar: \
+                 {responder}"
+            ),
+            SidecarError::Unhealthy { endpoint, status } => {
+                write!(f, "Health endpoint on {endpoint} returned HTTP {status}")
+            }
+            SidecarError::UnexpectedBody { endpoint, reason } => {
+                write!(f, "Health response from {endpoint} did not match: {reason}")
+            }
+            SidecarError::ExitedDuringStartup {
+                code,
+                signal,
+                stderr,
+            } => {
+                write!(
+                    f,
+                    "The sidecar exited before becoming healthy (code={code:?} signal={signal:?})"
+                )?;
+                // The whole capture travels with `sidecar-error`; quote the
+                // end, where a traceback or loader error usually sits.
+                if !stderr.is_empty() {
+                    let tail = &stderr[stderr.len().saturating_sub(QUOTED_STDERR_LINES)..];
+                    write!(f, ". Its error output ended:\n{}", tail.join("\n"))?;
+                }
+                Ok(())
+            }
+            SidecarError::HealthTimeout {
+                attempts,
+                endpoint,
+                last,
+            } => write!(
+                f,
+                "Sidecar failed to become healthy after {attempts} attempts on {endpoint}: {last}"
+            ),
+            SidecarError::CrashLoop {
+                restarts,
+                window_secs,
+                reason,
+            } => write!(
+                f,
+                "Sidecar restarted {restarts} times within {window_secs}s, giving up. \
+                 Last failure: {reason}"
+            ),
+            SidecarError::NotReady => f.write_str("The sidecar is not ready"),
+            SidecarError::NotRunning => f.write_str("The sidecar is not running"),
+            SidecarError::ReloadTimeout { after_ms } => write!(
+                f,
+                "The sidecar didn't acknowledge the reload within {after_ms}ms; \
+                 it may not support reloading, so restart it instead"
+            ),
+            SidecarError::ReadyTimeout { after_ms } => {
+                write!(f, "The sidecar didn't become ready within {after_ms}ms")
+            }
+            SidecarError::InvalidSignal(signum) => write!(
+                f,
+                "Signal {signum} can't be sent to the sidecar (allowed: SIGHUP, SIGUSR1, SIGUSR2)"
+            ),
+            SidecarError::Signal(e) => write!(f, "Could not signal the sidecar: {e}"),
+            SidecarError::StdinClosed => f.write_str("The sidecar has closed its stdin"),
+            SidecarError::Stdin(e) => write!(f, "Could not write to the sidecar's stdin: {e}"),
+            SidecarError::Tls(e) => write!(f, "Could not set up the sidecar's certificate: {e}"),
+            SidecarError::TlsDisabled => {
+                f.write_str("TLS is off for the sidecar; set CLAUDETINI_TLS=true to use it")
+            }
+            SidecarError::InvalidProxyRequest(reason) => {
+                write!(f, "Invalid proxy request: {reason}")
+            }
+            SidecarError::ResponseTooLarge { limit } => write!(
+                f,
+                "The sidecar's response was larger than {limit} bytes and was not returned"
+            ),
+            SidecarError::InvalidLogLevel(message) | SidecarError::InvalidLogFilter(message) => {
+                f.write_str(message)
+            }
+            SidecarError::ReservedArg(flag) => write!(
+                f,
+                "{flag} is managed by the app and can't be passed as an extra sidecar argument"
+            ),
+            SidecarError::DebugOnly => f.write_str("Only available in debug builds"),
+            SidecarError::CrashReports(e) => write!(f, "Could not read the crash reports: {e}"),
+            SidecarError::Disabled => f.write_str(
+                "The sidecar is disabled for this session (CLAUDETINI_NO_SIDECAR or --no-sidecar)",
+            ),
+            SidecarError::Clipboard(e) => write!(f, "Could not copy to the clipboard: {e}"),
+            SidecarError::ExternalSidecar => f.write_str(
+                "The dev sidecar runs outside the app and can't be controlled from here",
+            ),
+            SidecarError::RestartInProgress => {
+                f.write_str("A restart is already in progress; try again once it finishes")
+            }
+            SidecarError::RestartAbandoned => f.write_str("The in-flight restart was abandoned"),
+            SidecarError::LockPoisoned => f.write_str("Sidecar state lock poisoned"),
+        }
+    }
+}
+
+impl std::error::Error for SidecarError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SidecarError::PortBind(e)
+            | SidecarError::LocalAddr(e)
+            | SidecarError::LogFile(e)
+            | SidecarError::Diagnostics(e)
+            | SidecarError::CrashReports(e)
+            | SidecarError::Signal(e)
+            | SidecarError::Stdin(e)
+            | SidecarError::Tls(e) => Some(e.as_ref()),
+            SidecarError::Connect { source, .. } => Some(source.as_ref()),
+            SidecarError::AppDirs(e) => Some(e.as_ref()),
+            SidecarError::HealthTimeout { last, .. } => Some(last.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Commands hand errors to the frontend as their display message.
+impl Serialize for SidecarError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
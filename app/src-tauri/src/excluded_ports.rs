@@ -0,0 +1,69 @@
+use std::ops::RangeInclusive;
+
+/// Ephemeral ports to try before giving up on one outside the excluded ranges.
+#[cfg(windows)]
+pub(crate) const MAX_ATTEMPTS: u32 = 10;
+
+/// Whether Windows has reserved `port` (Hyper-V, WSL, Docker and friends
+/// reserve chunks of the ephemeral range). `netsh` is only asked once per run;
+/// if it can't be queried, no port is treated as excluded.
+#[cfg(windows)]
+pub(crate) fn is_excluded(port: u16) -> bool {
+    use std::sync::OnceLock;
+
+    static RANGES: OnceLock<Vec<RangeInclusive<u16>>> = OnceLock::new();
+    RANGES
+        .get_or_init(|| match query_netsh() {
+            Ok(output) => parse_excluded_ranges(&output),
+            Err(e) => {
+                tracing::warn!("Could not read excluded port ranges: {e}");
+                Vec::new()
+            }
+        })
+        .iter()
+        .any(|range| range.contains(&port))
+}
+
+#[cfg(windows)]
+fn query_netsh() -> std::io::Result<String> {
+    use std::os::windows::process::CommandExt;
+
+    /// Keeps a console window from flashing up.
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let output = std::process::Command::new("netsh")
+        .args(["interface", "ipv4", "show", "excludedportrange", "protocol=tcp"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse the `Start Port  End Port` table `netsh` prints. Header, separator
+/// and footer lines don't start with two numbers and are skipped.
+pub(crate) fn parse_excluded_ranges(output: &str) -> Vec<RangeInclusive<u16>> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let start = fields.next()?.parse().ok()?;
+            let end = fields.next()?.parse().ok()?;
+            Some(start..=end)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_netsh_table() {
+        let output = "\r\nProtocol tcp Port Exclusion Ranges\r\n\r\n\
+                      Start Port    End Port\r\n\
+                      ----------    --------\r\n\
+                      \x20     5357        5357\r\n\
+                      \x20    50000       50059     *\r\n\r\n\
+                      * - Administered port exclusions.\r\n";
+        assert_eq!(parse_excluded_ranges(output), vec![5357..=5357, 50000..=50059]);
+        assert!(parse_excluded_ranges("").is_empty());
+    }
+}
@@ -0,0 +1,818 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::auth::SidecarToken;
+use crate::config::serialize_ms;
+use crate::error::SidecarError;
+use crate::http;
+use crate::now_unix_ms;
+use crate::transport::SidecarEndpoint;
+
+/// Shortest connect timeout given to a startup attempt, even right at the deadline.
+const MIN_ATTEMPT_TIMEOUT: Duration = Duration::from_millis(50);
+/// Path and `name` our sidecar reports from its info endpoint.
+const IDENTITY_PATH: &str = "/";
+const SIDECAR_SERVICE_NAME: &str = "Claudetini Backend";
+/// How much of a foreign responder's body to quote back.
+const RESPONDER_SNIPPET_CHARS: usize = 200;
+
+/// Outcome of a single health check, from either the startup poller or the
+/// background monitor.
+#[derive(Clone, Serialize)]
+pub(crate) struct HealthRecord {
+    pub timestamp: u64,
+    pub ok: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+/// What a health check requests and what counts as a healthy answer.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct HealthProbe {
+    pub path: String,
+    /// Required value in the JSON body. Without one, any 2xx is healthy.
+    pub expect: Option<BodyExpectation>,
+}
+
+/// A JSON pointer into the health body and the value it must hold, written
+/// `<pointer>=<value>` (e.g. `/ok=true`, `/status=ready`). The value is
+/// parsed as JSON, falling back to a plain string.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct BodyExpectation {
+    pub pointer: String,
+    pub value: serde_json::Value,
+}
+
+impl FromStr for BodyExpectation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pointer, raw) = s
+            .split_once('=')
+            .ok_or_else(|| format!("{s:?} should look like /pointer=value"))?;
+        if !pointer.starts_with('/') {
+            return Err(format!("JSON pointer {pointer:?} must start with '/'"));
+        }
+        let value = serde_json::from_str(raw)
+            .unwrap_or_else(|_| serde_json::Value::String(raw.to_string()));
+        Ok(Self {
+            pointer: pointer.to_string(),
+            value,
+        })
+    }
+}
+
+impl BodyExpectation {
+    pub fn check(&self, body: &[u8]) -> Result<(), String> {
+        let doc: serde_json::Value =
+            serde_json::from_slice(body).map_err(|e| format!("body is not JSON: {e}"))?;
+        match doc.pointer(&self.pointer) {
+            Some(found) if *found == self.value => Ok(()),
+            Some(found) => Err(format!("{} is {found}, expected {}", self.pointer, self.value)),
+            None => Err(format!("{} is missing, expected {}", self.pointer, self.value)),
+        }
+    }
+}
+
+/// What has to happen before a spawned sidecar counts as ready. `READY` on
+/// stdout means the process finished initialising; a passing health check
+/// means its HTTP layer is up. Either can come first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReadySignal {
+    Health,
+    Line,
+    Both,
+    Either,
+}
+
+impl ReadySignal {
+    pub fn wants_line(self) -> bool {
+        self != ReadySignal::Health
+    }
+}
+
+impl FromStr for ReadySignal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "health" | "http" => Ok(ReadySignal::Health),
+            "line" | "stdout" => Ok(ReadySignal::Line),
+            "both" | "all" => Ok(ReadySignal::Both),
+            "either" | "any" => Ok(ReadySignal::Either),
+            other => Err(format!(
+                "unknown ready signal {other:?} (expected health, line, both or either)"
+            )),
+        }
+    }
+}
+
+/// A dependency check the sidecar ran on itself (CLI available, credentials
+/// present, ...), as reported in the `checks` array of its health response.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DependencyCheck {
+    pub name: String,
+    pub ok: bool,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+/// A failed readiness probe, with any checks the sidecar reported anyway.
+#[derive(Debug)]
+pub(crate) struct ProbeError {
+    pub error: SidecarError,
+    pub checks: Vec<DependencyCheck>,
+}
+
+impl From<SidecarError> for ProbeError {
+    fn from(error: SidecarError) -> Self {
+        Self {
+            error,
+            checks: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+/// Compact copy of a health record, taken so statistics can be computed
+/// without holding the state lock.
+#[derive(Clone, Copy)]
+pub(crate) struct HealthSample {
+    pub timestamp: u64,
+    pub ok: bool,
+    pub latency_ms: u64,
+}
+
+impl From<&HealthRecord> for HealthSample {
+    fn from(r: &HealthRecord) -> Self {
+        Self {
+            timestamp: r.timestamp,
+            ok: r.ok,
+            latency_ms: r.latency_ms,
+        }
+    }
+}
+
+/// Aggregate health statistics over a recent time window.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct HealthStats {
+    pub window_secs: u64,
+    pub checks_in_window: usize,
+    pub failures_in_window: usize,
+    /// Fraction of checks in the window that succeeded.
+    pub success_ratio: Option<f64>,
+    /// Latency percentiles over successful checks in the window.
+    pub latency_p50_ms: Option<u64>,
+    pub latency_p95_ms: Option<u64>,
+    /// Time since the most recent failed check anywhere in the history.
+    pub ms_since_last_failure: Option<u64>,
+}
+
+impl HealthStats {
+    pub fn compute(samples: &[HealthSample], now_ms: u64, window: Duration) -> Self {
+        let window_ms = window.as_millis() as u64;
+        let cutoff = now_ms.saturating_sub(window_ms);
+        let recent: Vec<_> = samples.iter().filter(|s| s.timestamp >= cutoff).collect();
+        let successes = recent.iter().filter(|s| s.ok).count();
+        let mut latencies: Vec<u64> =
+            recent.iter().filter(|s| s.ok).map(|s| s.latency_ms).collect();
+        latencies.sort_unstable();
+
+        Self {
+            window_secs: window.as_secs(),
+            checks_in_window: recent.len(),
+            failures_in_window: recent.len() - successes,
+            success_ratio: (!recent.is_empty())
+                .then(|| successes as f64 / recent.len() as f64),
+            latency_p50_ms: percentile(&latencies, 0.50),
+            latency_p95_ms: percentile(&latencies, 0.95),
+            ms_since_last_failure: samples
+                .iter()
+                .rev()
+                .find(|s| !s.ok)
+                .map(|s| now_ms.saturating_sub(s.timestamp)),
+        }
+    }
+}
+
+/// Nearest-rank percentile of already-sorted values.
+fn percentile(sorted: &[u64], p: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Exponential backoff between startup health attempts. Jitter keeps several
+/// sidecars that start together from probing in lockstep.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub(crate) struct Backoff {
+    /// Delay after the first failed attempt.
+    #[serde(serialize_with = "serialize_ms")]
+    pub initial: Duration,
+    /// Upper bound on the un-jittered delay.
+    #[serde(serialize_with = "serialize_ms")]
+    pub max: Duration,
+    /// Fraction of each delay randomly added or removed, e.g. 0.2 for ±20%.
+    pub jitter: f64,
+}
+
+impl Backoff {
+    /// Delay to wait after failed attempt number `attempt` (1-based).
+    /// `sample` is a random value in [-1.0, 1.0] that scales the jitter.
+    pub fn delay(&self, attempt: u32, sample: f64) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(16);
+        let base = self.initial.saturating_mul(1 << doublings).min(self.max);
+        let factor = 1.0 + self.jitter * sample.clamp(-1.0, 1.0);
+        base.mul_f64(factor.max(0.0))
+    }
+}
+
+/// Time source for the startup poller, injectable so tests don't depend on
+/// the wall clock.
+pub(crate) trait PollTimer {
+    /// Time elapsed since polling began.
+    fn elapsed(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration) -> impl Future<Output = ()> + Send;
+    /// Random value in [-1.0, 1.0] used to jitter delays.
+    fn jitter_sample(&mut self) -> f64;
+}
+
+/// Real timer backed by tokio, with a small xorshift generator for jitter.
+pub(crate) struct TokioTimer {
+    started: Instant,
+    rng: u64,
+}
+
+impl TokioTimer {
+    pub fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0x9E37_79B9_7F4A_7C15);
+        Self {
+            started: Instant::now(),
+            rng: seed | 1,
+        }
+    }
+}
+
+impl PollTimer for TokioTimer {
+    fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+
+    fn jitter_sample(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+    }
+}
+
+/// Run `probe` until it succeeds or `deadline` passes, backing off between
+/// attempts. `probe` receives the time left before the deadline so it can
+/// bound its own connect timeout. Every attempt is passed to `record`.
+/// Returns the number of attempts made along with the final probe result.
+pub(crate) async fn poll_with_backoff<P, Fut, T, E>(
+    deadline: Duration,
+    backoff: Backoff,
+    timer: &mut impl PollTimer,
+    mut probe: P,
+    mut record: impl FnMut(HealthRecord),
+) -> Result<(u32, T), (u32, E)>
+where
+    P: FnMut(Duration) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: fmt::Display,
+{
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let started = timer.elapsed();
+        let remaining = deadline.saturating_sub(started).max(MIN_ATTEMPT_TIMEOUT);
+        let result = probe(remaining).await;
+        record(HealthRecord {
+            timestamp: now_unix_ms(),
+            ok: result.is_ok(),
+            latency_ms: timer.elapsed().saturating_sub(started).as_millis() as u64,
+            error: result.as_ref().err().map(E::to_string),
+        });
+        let error = match result {
+            Ok(value) => return Ok((attempt, value)),
+            Err(e) => e,
+        };
+
+        let remaining = deadline.saturating_sub(timer.elapsed());
+        if remaining.is_zero() {
+            return Err((attempt, error));
+        }
+        let sample = timer.jitter_sample();
+        timer.sleep(backoff.delay(attempt, sample).min(remaining)).await;
+    }
+}
+
+/// Poll the sidecar until each of `probes` has answered healthy in turn,
+/// e.g. a quick liveness probe and then the slower readiness one, or until
+/// `deadline` passes for all of them together. A loopback endpoint is tried
+/// on both IP families, preferred first, until one answers; later probes
+/// stick to it. Returns that endpoint and the dependency checks the last
+/// probe reported.
+pub(crate) async fn poll_health(
+    endpoint: &SidecarEndpoint,
+    prefer_ipv6: bool,
+    probes: &[HealthProbe],
+    token: Option<&SidecarToken>,
+    deadline: Duration,
+    backoff: Backoff,
+    mut record: impl FnMut(HealthRecord),
+) -> Result<(SidecarEndpoint, Vec<DependencyCheck>), ProbeError> {
+    let (mut preferred, mut alternate) = endpoint.loopback_families(prefer_ipv6);
+    let mut timer = TokioTimer::new();
+    let mut checks = Vec::new();
+    for probe in probes {
+        let attempt =
+            |remaining| check_ready_either(&preferred, alternate.as_ref(), probe, token, remaining);
+        match poll_with_backoff(deadline, backoff, &mut timer, attempt, &mut record).await {
+            Ok((attempt, (answered, reported))) => {
+                info!(
+                    "Sidecar passed {} on {answered} (attempt {attempt}, {}ms)",
+                    probe.path,
+                    timer.elapsed().as_millis()
+                );
+                (preferred, alternate, checks) = (answered, None, reported);
+            }
+            Err((attempts, last)) => {
+                return Err(ProbeError {
+                    error: SidecarError::HealthTimeout {
+                        attempts,
+                        endpoint: endpoint.to_string(),
+                        last: Box::new(last.error),
+                    },
+                    checks: last.checks,
+                })
+            }
+        }
+    }
+    Ok((preferred, checks))
+}
+
+/// Probe `preferred`, then `alternate` if that fails. Reports the preferred
+/// endpoint's error, since the alternate is only a fallback.
+async fn check_ready_either(
+    preferred: &SidecarEndpoint,
+    alternate: Option<&SidecarEndpoint>,
+    probe: &HealthProbe,
+    token: Option<&SidecarToken>,
+    timeout: Duration,
+) -> Result<(SidecarEndpoint, Vec<DependencyCheck>), ProbeError> {
+    let error = match check_ready(preferred, probe, token, timeout).await {
+        Ok(checks) => return Ok((preferred.clone(), checks)),
+        Err(e) => e,
+    };
+    match alternate {
+        Some(alternate) => match check_ready(alternate, probe, token, timeout).await {
+            Ok(checks) => Ok((alternate.clone(), checks)),
+            Err(_) => Err(error),
+        },
+        None => Err(error),
+    }
+}
+
+/// Single HTTP health probe: `GET` the probe path, which must answer 2xx
+/// (and match the body expectation, if any) within `timeout`. Used both
+/// while starting up and by the background monitor.
+pub(crate) async fn check_ready(
+    endpoint: &SidecarEndpoint,
+    probe: &HealthProbe,
+    token: Option<&SidecarToken>,
+    timeout: Duration,
+) -> Result<Vec<DependencyCheck>, ProbeError> {
+    let token = token.map(SidecarToken::as_str);
+    let request = http::get_request(&endpoint.host_header(), &probe.path, token);
+    let raw = with_timeout(endpoint, timeout, endpoint.round_trip(&request)).await?;
+    let response =
+        http::parse_response(&raw).map_err(|reason| SidecarError::InvalidResponse {
+            endpoint: endpoint.to_string(),
+            reason,
+        })?;
+    let checks = parse_checks(&response.body);
+    let error = if !response.is_success() {
+        SidecarError::Unhealthy {
+            endpoint: endpoint.to_string(),
+            status: response.status,
+        }
+    } else if let Some(Err(reason)) = probe.expect.as_ref().map(|e| e.check(&response.body)) {
+        SidecarError::UnexpectedBody {
+            endpoint: endpoint.to_string(),
+            reason,
+        }
+    } else {
+        return Ok(checks);
+    };
+    Err(ProbeError { error, checks })
+}
+
+/// What a bare connect to the sidecar's port ran into. Nothing listening is
+/// refused straight away; a connection that hangs or is denied points at a
+/// firewall or security software dropping loopback traffic.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Reachability {
+    Open,
+    Refused,
+    Filtered(String),
+    Failed(String),
+}
+
+/// Connect to `endpoint` and nothing more, to tell a sidecar that isn't up
+/// from one something is blocking.
+pub(crate) async fn preflight(endpoint: &SidecarEndpoint, timeout: Duration) -> Reachability {
+    match tokio::time::timeout(timeout, endpoint.connect()).await {
+        Ok(Ok(_)) => Reachability::Open,
+        Ok(Err(e)) => classify_connect_error(&e),
+        Err(_) => Reachability::Filtered(format!("no answer within {}ms", timeout.as_millis())),
+    }
+}
+
+fn classify_connect_error(error: &io::Error) -> Reachability {
+    match error.kind() {
+        io::ErrorKind::ConnectionRefused => Reachability::Refused,
+        // Packet filters answer with EPERM/EACCES (WSAEACCES on Windows) or
+        // let the SYN go unanswered.
+        io::ErrorKind::TimedOut | io::ErrorKind::PermissionDenied => {
+            Reachability::Filtered(error.to_string())
+        }
+        _ => Reachability::Failed(error.to_string()),
+    }
+}
+
+/// Ask whatever answered on `endpoint` to identify itself, so another dev
+/// server holding the port isn't mistaken for the sidecar.
+pub(crate) async fn verify_identity(
+    endpoint: &SidecarEndpoint,
+    token: Option<&SidecarToken>,
+    timeout: Duration,
+) -> Result<(), SidecarError> {
+    let response = fetch_identity(endpoint, token, timeout).await?;
+    if is_sidecar_identity(&response) {
+        return Ok(());
+    }
+    let body: String = String::from_utf8_lossy(&response.body)
+        .chars()
+        .take(RESPONDER_SNIPPET_CHARS)
+        .collect();
+    Err(wrong_service(endpoint, format!("HTTP {} {}", response.status, body.trim())))
+}
+
+/// The `version` the sidecar reports at its identity endpoint.
+pub(crate) async fn sidecar_version(
+    endpoint: &SidecarEndpoint,
+    token: Option<&SidecarToken>,
+    timeout: Duration,
+) -> Result<String, SidecarError> {
+    let response = fetch_identity(endpoint, token, timeout).await?;
+    serde_json::from_slice::<serde_json::Value>(&response.body)
+        .ok()
+        .filter(|_| response.is_success())
+        .and_then(|doc| doc["version"].as_str().map(str::to_string))
+        .ok_or_else(|| {
+            wrong_service(endpoint, format!("HTTP {} without a version", response.status))
+        })
+}
+
+/// GET the identity endpoint. A reply that isn't HTTP at all already rules
+/// out the sidecar.
+async fn fetch_identity(
+    endpoint: &SidecarEndpoint,
+    token: Option<&SidecarToken>,
+    timeout: Duration,
+) -> Result<http::Response, SidecarError> {
+    let token = token.map(SidecarToken::as_str);
+    let request = http::get_request(&endpoint.host_header(), IDENTITY_PATH, token);
+    let raw = with_timeout(endpoint, timeout, endpoint.round_trip(&request)).await?;
+    http::parse_response(&raw).map_err(|responder| wrong_service(endpoint, responder))
+}
+
+fn wrong_service(endpoint: &SidecarEndpoint, responder: String) -> SidecarError {
+    SidecarError::WrongService {
+        endpoint: endpoint.to_string(),
+        responder,
+    }
+}
+
+fn is_sidecar_identity(response: &http::Response) -> bool {
+    response.is_success()
+        && serde_json::from_slice::<serde_json::Value>(&response.body)
+            .is_ok_and(|doc| doc["name"] == SIDECAR_SERVICE_NAME)
+}
+
+/// Pull the optional `checks` array out of a health response body. A missing
+/// or malformed array means no checks; malformed entries are skipped.
+fn parse_checks(body: &[u8]) -> Vec<DependencyCheck> {
+    let Ok(value) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return Vec::new();
+    };
+    let Some(items) = value.get("checks").and_then(serde_json::Value::as_array) else {
+        return Vec::new();
+    };
+    items
+        .iter()
+        .filter_map(|item| DependencyCheck::deserialize(item).ok())
+        .collect()
+}
+
+/// Run an I/O operation against `endpoint`, mapping failures and timeouts.
+pub(crate) async fn with_timeout<T>(
+    endpoint: &SidecarEndpoint,
+    timeout: Duration,
+    op: impl Future<Output = std::io::Result<T>>,
+) -> Result<T, SidecarError> {
+    match tokio::time::timeout(timeout, op).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(SidecarError::Connect {
+            endpoint: endpoint.to_string(),
+            source: e.into(),
+        }),
+        Err(_) => Err(SidecarError::Timeout {
+            endpoint: endpoint.to_string(),
+            after_ms: timeout.as_millis(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub_sidecar::StubSidecar;
+
+    const BACKOFF: Backoff = Backoff {
+        initial: Duration::from_millis(50),
+        max: Duration::from_secs(1),
+        jitter: 0.2,
+    };
+
+    /// Virtual clock: sleeping advances time instantly and is recorded.
+    struct FakeTimer {
+        now: Duration,
+        sleeps: Vec<Duration>,
+        sample: f64,
+    }
+
+    impl FakeTimer {
+        fn new(sample: f64) -> Self {
+            Self {
+                now: Duration::ZERO,
+                sleeps: Vec::new(),
+                sample,
+            }
+        }
+    }
+
+    impl PollTimer for FakeTimer {
+        fn elapsed(&self) -> Duration {
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) -> impl Future<Output = ()> + Send {
+            self.now += duration;
+            self.sleeps.push(duration);
+            std::future::ready(())
+        }
+
+        fn jitter_sample(&mut self) -> f64 {
+            self.sample
+        }
+    }
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&v| Duration::from_millis(v)).collect()
+    }
+
+    #[test]
+    fn delay_doubles_up_to_cap() {
+        let delays: Vec<_> = (1..=7).map(|n| BACKOFF.delay(n, 0.0)).collect();
+        assert_eq!(delays, ms(&[50, 100, 200, 400, 800, 1000, 1000]));
+    }
+
+    #[test]
+    fn delay_jitter_stays_within_bounds() {
+        assert_eq!(BACKOFF.delay(1, -1.0), Duration::from_millis(40));
+        assert_eq!(BACKOFF.delay(1, 1.0), Duration::from_millis(60));
+        assert_eq!(BACKOFF.delay(10, 1.0), Duration::from_millis(1200));
+        // Out-of-range samples are clamped rather than trusted.
+        assert_eq!(BACKOFF.delay(10, 5.0), Duration::from_millis(1200));
+    }
+
+    #[test]
+    fn stats_cover_only_the_window() {
+        let sample = |timestamp, ok, latency_ms| HealthSample {
+            timestamp,
+            ok,
+            latency_ms,
+        };
+        let mut samples = vec![sample(1_000, false, 0)];
+        samples.extend((1..=20).map(|i| sample(100_000 + i, true, i)));
+        samples.push(sample(100_050, false, 900));
+
+        let stats = HealthStats::compute(&samples, 100_100, Duration::from_secs(60));
+
+        assert_eq!(stats.checks_in_window, 21);
+        assert_eq!(stats.failures_in_window, 1);
+        assert_eq!(stats.success_ratio, Some(20.0 / 21.0));
+        assert_eq!(stats.latency_p50_ms, Some(10));
+        assert_eq!(stats.latency_p95_ms, Some(19));
+        assert_eq!(stats.ms_since_last_failure, Some(50));
+    }
+
+    #[test]
+    fn stats_are_empty_without_samples() {
+        let stats = HealthStats::compute(&[], 5_000, Duration::from_secs(300));
+        assert_eq!(stats.checks_in_window, 0);
+        assert_eq!(stats.success_ratio, None);
+        assert_eq!(stats.latency_p95_ms, None);
+        assert_eq!(stats.ms_since_last_failure, None);
+    }
+
+    #[test]
+    fn body_expectation_matches_json_pointer() {
+        let ok: BodyExpectation = "/ok=true".parse().unwrap();
+        assert_eq!(ok.value, serde_json::Value::Bool(true));
+        assert!(ok.check(br#"{"ok":true}"#).is_ok());
+        assert!(ok.check(br#"{"ok":false}"#).is_err());
+        assert!(ok.check(br#"{"status":"ready"}"#).is_err());
+        assert!(ok.check(b"ok").is_err());
+
+        let status: BodyExpectation = "/status=ready".parse().unwrap();
+        assert!(status.check(br#"{"status":"ready","checks":[]}"#).is_ok());
+
+        let nested: BodyExpectation = "/data/state=\"up\"".parse().unwrap();
+        assert!(nested.check(br#"{"data":{"state":"up"}}"#).is_ok());
+
+        assert!("status=ready".parse::<BodyExpectation>().is_err());
+        assert!("/status".parse::<BodyExpectation>().is_err());
+    }
+
+    #[test]
+    fn ready_signal_parses_aliases() {
+        assert_eq!("BOTH".parse(), Ok(ReadySignal::Both));
+        assert_eq!("stdout".parse(), Ok(ReadySignal::Line));
+        assert_eq!("any".parse(), Ok(ReadySignal::Either));
+        assert!(!"http".parse::<ReadySignal>().unwrap().wants_line());
+        assert!("socket".parse::<ReadySignal>().is_err());
+    }
+
+    #[test]
+    fn checks_skip_malformed_entries() {
+        let body = br#"{"status":"ok","checks":[
+            {"name":"claude_cli","ok":true},
+            {"name":"disk","ok":false,"detail":"read-only"},
+            {"ok":"yes"}
+        ]}"#;
+        let checks = parse_checks(body);
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[1].detail.as_deref(), Some("read-only"));
+
+        assert!(parse_checks(br#"{"status":"ok"}"#).is_empty());
+        assert!(parse_checks(br#"{"checks":"all good"}"#).is_empty());
+        assert!(parse_checks(b"not json").is_empty());
+    }
+
+    #[tokio::test]
+    async fn poll_backs_off_until_probe_succeeds() {
+        let mut timer = FakeTimer::new(0.0);
+        let mut calls = 0;
+        let mut records = Vec::new();
+        let probe = |_| {
+            calls += 1;
+            let ok = calls == 4;
+            async move { if ok { Ok(()) } else { Err("refused".to_string()) } }
+        };
+
+        let result = poll_with_backoff(Duration::from_secs(6), BACKOFF, &mut timer, probe, |r| {
+            records.push(r)
+        })
+        .await;
+
+        assert_eq!(result, Ok((4, ())));
+        assert_eq!(timer.sleeps, ms(&[50, 100, 200]));
+        assert_eq!(records.iter().filter(|r| r.ok).count(), 1);
+        assert_eq!(records.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn poll_respects_overall_deadline() {
+        let mut timer = FakeTimer::new(1.0);
+        let probe = |_| async { Err::<(), _>("refused".to_string()) };
+
+        let result =
+            poll_with_backoff(Duration::from_secs(3), BACKOFF, &mut timer, probe, |_| {}).await;
+
+        // 60+120+240+480+960 = 1860ms, then the 1200ms step is clamped to 1140ms.
+        assert_eq!(timer.sleeps, ms(&[60, 120, 240, 480, 960, 1140]));
+        assert_eq!(timer.now, Duration::from_secs(3));
+        assert_eq!(result, Err((7, "refused".to_string())));
+    }
+
+    fn probe() -> HealthProbe {
+        HealthProbe {
+            path: "/health".to_string(),
+            expect: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn stub_is_ready_only_once_health_passes() {
+        let stub = StubSidecar::spawn(&["--unhealthy-for-ms", "300"]);
+        let mut records = Vec::new();
+        let started = Instant::now();
+
+        let deadline = Duration::from_secs(5);
+        let record = |r| records.push(r);
+        let probes = [probe()];
+        let result = poll_health(&stub.endpoint(), false, &probes, None, deadline, BACKOFF, record)
+            .await;
+
+        let (endpoint, _) = result.expect("stub never became healthy");
+        assert_eq!(endpoint.to_string(), stub.endpoint().to_string());
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(!records[0].ok);
+        assert!(records.last().unwrap().ok);
+        verify_identity(&endpoint, None, Duration::from_secs(2)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn checks_liveness_before_readiness() {
+        let stub = StubSidecar::spawn(&["--unhealthy-for-ms", "300"]);
+        let mut records = Vec::new();
+
+        let live = HealthProbe {
+            path: "/live".to_string(),
+            expect: Some("/status=alive".parse().unwrap()),
+        };
+        let probes = [live, probe()];
+        let record = |r| records.push(r);
+        let deadline = Duration::from_secs(5);
+        let result = poll_health(&stub.endpoint(), false, &probes, None, deadline, BACKOFF, record)
+            .await;
+
+        result.expect("stub never became ready");
+        let passed: Vec<_> = records.iter().map(|r| r.ok).collect();
+        assert!(passed[0], "liveness should pass straight away");
+        assert!(!passed[1], "readiness should still be failing");
+        assert!(passed.last().unwrap());
+    }
+
+    #[tokio::test]
+    async fn poll_times_out_once_stub_exits() {
+        let mut stub = StubSidecar::spawn(&["--exit-code", "3"]);
+        assert_eq!(stub.wait().code(), Some(3));
+
+        let deadline = Duration::from_millis(300);
+        let result =
+            poll_health(&stub.endpoint(), false, &[probe()], None, deadline, BACKOFF, |_| {}).await;
+
+        let error = result.expect_err("nothing should answer on the exited stub's port");
+        assert_eq!(error.error.kind(), "health_timeout");
+    }
+
+    #[test]
+    fn recognizes_sidecar_identity() {
+        let ours = b"HTTP/1.1 200 OK\r\n\r\n{\"name\":\"Claudetini Backend\",\"docs\":\"/docs\"}";
+        assert!(is_sidecar_identity(&http::parse_response(ours).unwrap()));
+        let vite = b"HTTP/1.1 200 OK\r\n\r\n<!doctype html><html></html>";
+        assert!(!is_sidecar_identity(&http::parse_response(vite).unwrap()));
+        let missing = b"HTTP/1.1 404 Not Found\r\n\r\n{\"name\":\"Claudetini Backend\"}";
+        assert!(!is_sidecar_identity(&http::parse_response(missing).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn preflight_tells_refused_from_filtered() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let endpoint = SidecarEndpoint::tcp("127.0.0.1", port);
+        let timeout = Duration::from_secs(2);
+        assert_eq!(preflight(&endpoint, timeout).await, Reachability::Open);
+        drop(listener);
+        assert_eq!(preflight(&endpoint, timeout).await, Reachability::Refused);
+
+        let dropped = io::Error::from(io::ErrorKind::TimedOut);
+        assert!(matches!(classify_connect_error(&dropped), Reachability::Filtered(_)));
+    }
+}
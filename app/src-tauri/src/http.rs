@@ -0,0 +1,144 @@
+/// A parsed HTTP response.
+pub(crate) struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Build a `GET` request for `path`, with a bearer token if the sidecar
+/// requires one. We ask for `Connection: close`, so a response is simply
+/// everything read until the sidecar hangs up; that keeps this small enough
+/// to not need an HTTP client crate.
+pub(crate) fn get_request(host: &str, path: &str, token: Option<&str>) -> Vec<u8> {
+    let auth = token.map_or(String::new(), |t| format!("Authorization: Bearer {t}\r\n"));
+    format!(
+        "GET {path} HTTP/1.1\r\nHost: {host}\r\n{auth}\
+         Accept: application/json\r\nConnection: close\r\n\r\n"
+    )
+    .into_bytes()
+}
+
+/// Build a `GET` for a server-sent event stream. HTTP/1.0 keeps the sidecar
+/// from chunk-encoding the stream, so the body is just the raw event text.
+pub(crate) fn event_stream_request(host: &str, path: &str, token: Option<&str>) -> Vec<u8> {
+    let auth = token.map_or(String::new(), |t| format!("Authorization: Bearer {t}\r\n"));
+    format!(
+        "GET {path} HTTP/1.0\r\nHost: {host}\r\n{auth}\
+         Accept: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+    )
+    .into_bytes()
+}
+
+/// Build an arbitrary request for the proxy. Callers validate `method`,
+/// `path` and `headers`; `Content-Length` is always set from `body`.
+pub(crate) fn request(
+    method: &str,
+    host: &str,
+    path: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+    token: Option<&str>,
+) -> Vec<u8> {
+    let mut head = format!("{method} {path} HTTP/1.1\r\nHost: {host}\r\n");
+    if let Some(token) = token {
+        head.push_str(&format!("Authorization: Bearer {token}\r\n"));
+    }
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", body.len()));
+    let mut raw = head.into_bytes();
+    raw.extend_from_slice(body);
+    raw
+}
+
+/// Parse a complete response as read from the socket.
+pub(crate) fn parse_response(raw: &[u8]) -> Result<Response, String> {
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("Response ended before the headers were complete")?;
+    let head = std::str::from_utf8(&raw[..split]).map_err(|_| "Response headers are not UTF-8")?;
+    let body = &raw[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .strip_prefix("HTTP/1.")
+        .and_then(|rest| rest.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("Malformed status line {status_line:?}"))?;
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let chunked = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked")
+    });
+    let body = if chunked { decode_chunked(body)? } else { body.to_vec() };
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn decode_chunked(mut data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("Truncated chunk size")?;
+        let size_line = std::str::from_utf8(&data[..line_end]).map_err(|_| "Bad chunk size")?;
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16)
+            .map_err(|_| format!("Bad chunk size {size_hex:?}"))?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < size {
+            return Err("Truncated chunk".into());
+        }
+        body.extend_from_slice(&data[..size]);
+        data = data[size..].strip_prefix(b"\r\n").unwrap_or(&data[size..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_content_length_response() {
+        let raw = b"HTTP/1.1 200 OK\r\ncontent-length: 15\r\n\r\n{\"status\":\"ok\"}";
+        let response = parse_response(raw).unwrap();
+        assert!(response.is_success());
+        assert_eq!(response.body, b"{\"status\":\"ok\"}");
+        assert_eq!(response.headers, [("content-length".to_string(), "15".to_string())]);
+    }
+
+    #[test]
+    fn decodes_chunked_body() {
+        let raw = b"HTTP/1.1 503 Service Unavailable\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\n";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, 503);
+        assert!(!response.is_success());
+        assert_eq!(response.body, b"{\"a\":1}");
+    }
+
+    #[test]
+    fn rejects_non_http_replies() {
+        assert!(parse_response(b"hello").is_err());
+        assert!(parse_response(b"SSH-2.0-OpenSSH\r\n\r\n").is_err());
+    }
+}
@@ -1,200 +1,3991 @@
+mod auth;
+mod bundle;
+mod config;
+mod crash;
+mod degradation;
+mod discovery;
+mod error;
+#[cfg(any(windows, test))]
+mod excluded_ports;
+mod health;
+mod http;
+mod linebuf;
+mod logging;
+mod logs;
+mod logstream;
+mod logwriter;
+mod netwatch;
+mod paths;
+mod ports;
+mod priority;
+mod proxy;
+mod redact;
+mod relay;
+mod reload;
+mod replay;
+mod resources;
+mod restart;
+#[cfg(test)]
+mod scratch;
+mod signal;
+#[cfg(test)]
+mod stub_sidecar;
+mod summary;
+mod timings;
+mod tls;
+mod transport;
+mod visibility;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::io::Write as _;
 use std::net::TcpListener;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use serde::Serialize;
-use tauri::{AppHandle, Emitter, Manager, RunEvent};
+use tauri::ipc::JavaScriptChannelId;
+use tauri::webview::PageLoadEvent;
+use tauri::{Emitter, Manager, RunEvent, WindowEvent};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_opener::OpenerExt;
 use tauri_plugin_shell::ShellExt;
 use tauri_plugin_shell::process::{CommandChild, CommandEvent};
+use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tracing::{debug, error, info, info_span, instrument, trace, warn, Instrument};
+
+use auth::SidecarToken;
+use bundle::Bundle;
+use crash::{CrashReport, CrashReportInfo, PendingCrashes};
+use config::{
+    ConfigSource, EffectiveSetting, SidecarConfig, CONFIG_FILE_NAME, MIN_WATCHDOG_INTERVAL,
+};
+use degradation::{DegradationTracker, Transition};
+use discovery::Discovery;
+use error::SidecarError;
+use health::{
+    check_ready, poll_health, DependencyCheck, HealthRecord, HealthSample, HealthStats, PollTimer,
+    ProbeError, Reachability, ReadySignal, TokioTimer,
+};
+use linebuf::{Line, LineBuffer};
+use logging::{SIDECAR_TARGET, SUPERVISOR_TARGET};
+use logs::{LogBuffer, LogFile, LogFileInfo, LogLevel, LogLine, LogTail, LogUsage};
+use logstream::{LineFilter, LogBatch, LogSink, LogStream};
+use logwriter::LogWriter;
+use paths::AppDirs;
+use ports::bind_port;
+use proxy::{ProxyRequest, ProxyResponse};
+use timings::{Milestone, StartupTimings, StartupTimingsReport};
+use redact::Redactor;
+use relay::{EventRelay, StreamError};
+use reload::ReloadResult;
+use replay::ReplayQueue;
+use resources::{EmitThrottle, ResourceHistory, ResourceSample, Sampler};
+use restart::{CrashBudget, RestartGate, RestartResult, Turn};
+use summary::{Summary, SUMMARY_LOG_LINES};
+use transport::{SidecarEndpoint, SidecarUrl, Transport, LOOPBACK_HOST};
+use visibility::Visibility;
+
+/// The runtime the app runs on. Tests swap in Tauri's mock runtime, so the
+/// supervisor can be driven end to end without a window system.
+#[cfg(not(test))]
+pub(crate) type SidecarRuntime = tauri::Wry;
+#[cfg(test)]
+pub(crate) type SidecarRuntime = tauri::test::MockRuntime;
+pub(crate) type AppHandle = tauri::AppHandle<SidecarRuntime>;
+
+/// Stderr lines kept from a starting sidecar to explain an early exit, and
+/// how many characters of each.
+const STARTUP_STDERR_LINES: usize = 200;
+const STARTUP_STDERR_LINE_CHARS: usize = 300;
+
+/// Default number of log lines included in a diagnostics report.
+const DEFAULT_DIAGNOSTIC_LINES: usize = 50;
+
+/// Default and maximum number of bytes `read_log_file` returns.
+const DEFAULT_LOG_TAIL_BYTES: u64 = 256 * 1024;
+const MAX_LOG_TAIL_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Number of health check results kept for `get_health_history`.
+const HEALTH_HISTORY_CAPACITY: usize = 500;
+/// Status changes kept for a frontend that hasn't loaded yet.
+const STATE_CHANGE_REPLAY: usize = 64;
+
+/// `sidecar-state-changed` events waiting for the frontend to load.
+type StateChangeQueue = Mutex<ReplayQueue<SidecarStateChangedPayload>>;
+
+/// Window over which `get_sidecar_metrics` computes success ratio and latency.
+const METRICS_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Times a single start may respawn on a fresh port because another process
+/// grabbed ours before the sidecar could bind it.
+const MAX_PORT_CONFLICT_RETRIES: u32 = 3;
+
+/// Times a spawned sidecar that never became healthy is replaced by one on a
+/// fresh port before the startup failure is reported.
+const MAX_STARTUP_RETRIES: u32 = 2;
+
+/// Lowercased fragments of bind-failure messages (Python on Linux/macOS, Windows).
+const PORT_CONFLICT_PATTERNS: &[&str] = &[
+    "address already in use",
+    "eaddrinuse",
+    "only one usage of each socket address",
+    "winerror 10048",
+];
+
+/// Shown when the sidecar can't be started and the app carries on without it.
+const DEGRADED_MESSAGE: &str = "The helper process couldn't be started, so some features \
+     are unavailable. Retry, or check the logs.";
+
+/// Shown once the supervisor stops restarting a crash-looping sidecar.
+const CRASH_LOOP_MESSAGE: &str =
+    "The helper process crashed repeatedly and has been disabled. Restart the app or check logs.";
+
+/// Flags the app sets itself; callers can't override them via extra arguments.
+const RESERVED_SIDECAR_ARGS: &[&str] = &["--port", "--host", "--socket"];
+
+/// How long the pre-flight connect after failed startup checks may take.
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long quitting waits for the log file to reach disk.
+const LOG_CLOSE_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long quitting waits for the output task to write what it has.
+const DRAIN_STOP_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long `reload_sidecar_config` waits for the sidecar to answer.
+const RELOAD_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long `wait_for_sidecar_ready` waits unless told otherwise.
+const READY_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shown when loopback connections to the sidecar are being dropped.
+const BLOCKED_GUIDANCE: &str = "Connections to the helper process on this computer are being \
+     blocked, probably by a firewall or security software. Allow claudetini-sidecar to accept \
+     local connections, then retry.";
+
+/// Lifecycle status of the sidecar as seen by the Rust side.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum SidecarStatus {
+    NotStarted,
+    Starting,
+    Ready,
+    Unhealthy,
+    Restarting,
+    Stopped,
+    /// Terminal until an explicit restart: a dev sidecar that never became
+    /// reachable. A supervisor that gives up enters `Degraded` instead.
+    Failed { error: String },
+    /// The sidecar can't be started (missing binary, repeated crashes, never
+    /// healthy) and the supervisor has stopped trying. The app stays usable
+    /// without it until an explicit restart.
+    Degraded { error: String, message: String },
+    /// Started with `CLAUDETINI_NO_SIDECAR` or `--no-sidecar`; stays so for
+    /// the session.
+    Disabled,
+}
+
+impl SidecarStatus {
+    /// The `state` tag the frontend sees.
+    fn name(&self) -> &'static str {
+        match self {
+            SidecarStatus::NotStarted => "not_started",
+            SidecarStatus::Starting => "starting",
+            SidecarStatus::Ready => "ready",
+            SidecarStatus::Unhealthy => "unhealthy",
+            SidecarStatus::Restarting => "restarting",
+            SidecarStatus::Stopped => "stopped",
+            SidecarStatus::Failed { .. } => "failed",
+            SidecarStatus::Degraded { .. } => "degraded",
+            SidecarStatus::Disabled => "disabled",
+        }
+    }
+}
+
+/// The task reading the current child's output, so quitting can stop it
+/// cleanly instead of exiting mid-write.
+struct DrainTask {
+    stop: oneshot::Sender<()>,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+/// Exit details recorded when the sidecar process terminates.
+#[derive(Clone, Serialize)]
+struct ExitInfo {
+    code: Option<i32>,
+    signal: Option<i32>,
+    at_unix_ms: u64,
+}
 
 /// Holds the sidecar port and child handle for lifecycle management.
 struct SidecarState {
-    port: u16,
+    /// Where the sidecar listens; `None` until one has been chosen.
+    endpoint: Option<SidecarEndpoint>,
     child: Option<CommandChild>,
+    /// Fires when the current child process terminates.
+    exited: Option<oneshot::Receiver<()>>,
+    /// Reads the current child's output until it terminates.
+    drain: Option<DrainTask>,
+    /// Every status change, for `relay_state_changes` to emit.
+    state_changes: mpsc::UnboundedSender<SidecarStateChangedPayload>,
+    /// Bumped on every spawn so background tasks can tell when they're stale.
+    generation: u64,
+    /// The generation `sidecar-ready` was last emitted for.
+    ready_announced: Option<u64>,
+    /// What `sidecar-ready` carried for `ready_announced`.
+    ready_payload: Option<SidecarReadyPayload>,
+    /// Bumped on every status change and announcement, to wake
+    /// `wait_for_sidecar_ready`.
+    ready_changed: watch::Sender<()>,
+    /// Set when we stop the sidecar on purpose, so its exit isn't treated as a crash.
+    stop_requested: bool,
+    /// Recent automatic restarts, for crash-loop detection.
+    crash_budget: CrashBudget,
+    /// Arguments appended after the listen flags on every spawn.
+    extra_args: Vec<String>,
+    /// Whether spawns should listen on every interface rather than loopback
+    /// only; starts at the configured value, changed with `set_lan_binding`.
+    bind_lan: bool,
+    /// Delay between background health checks; starts at the configured value
+    /// and can be changed for the session with `set_watchdog_interval`.
+    health_interval: Duration,
+    /// Discovery file published for the current child, removed when it exits.
+    discovery_file: Option<PathBuf>,
+    /// Windows streaming sidecar output via `subscribe_sidecar_logs`.
+    log_stream: LogStream,
+    /// Bearer token the current child was spawned with; rotated on every
+    /// spawn. Only ever handed out by `get_sidecar_token`.
+    token: Option<SidecarToken>,
+    /// Masks secrets in sidecar output before it is stored or sent anywhere.
+    redactor: Redactor,
+    /// Keep raw bytes of output lines that weren't valid UTF-8.
+    keep_raw_output: bool,
+    /// Output bytes replaced because they weren't valid UTF-8, this session.
+    replaced_bytes: u64,
+    /// Output lines truncated by the line cap, this session.
+    truncated_lines: u64,
+    /// Level forwarded to every child, once one was picked with `set_log_level`.
+    sidecar_log_level: Option<logging::LogLevel>,
+    /// Highest heartbeat sequence number the current child has answered.
+    last_pong: u64,
+    /// Recent CPU and memory samples of the current child.
+    resources: ResourceHistory,
+    /// This install's certificate, once TLS has needed it.
+    certificate: Option<tls::Certificate>,
+    /// Sequence number of the last `reload` sent over the control channel.
+    reload_seq: u64,
+    /// Told the current child's answer to the outstanding reload.
+    pending_reload: Option<(u64, oneshot::Sender<ReloadResult>)>,
+    /// Round trip of the watchdog's last passing check on the current child.
+    last_latency_ms: Option<u64>,
+    /// First stderr lines of the current child while it's still starting;
+    /// dropped once it's ready.
+    startup_stderr: Vec<String>,
+    /// The last `sidecar-error` sent, for `get_last_sidecar_error`.
+    last_error: Option<SidecarErrorPayload>,
+    /// Respawns caused by port conflicts since the sidecar was last ready.
+    port_retries: u32,
+    /// Told the port a sidecar launched with `--port 0` announced on stdout.
+    port_announced: Option<oneshot::Sender<u16>>,
+    /// How long the app took to reach each step of its first startup.
+    startup_timings: StartupTimings,
+    /// Wakes the startup task once the current child prints `READY`, when
+    /// the ready signal asks for it.
+    ready_line: Option<oneshot::Sender<()>>,
+    /// Respawns caused by startup health timeouts since the sidecar was last ready.
+    startup_retries: u32,
+    /// Port the last conflicted child was given, until the respawn logs it.
+    conflicted_port: Option<u16>,
+    /// Set while an explicit restart is running; repeat requests join it.
+    restart_gate: RestartGate,
+    /// Only ever changed through `transition`.
+    status: SidecarStatus,
+    started_at: Option<Instant>,
+    /// Automatic and explicit restarts this session; never reset.
+    restart_count: u32,
+    last_exit: Option<ExitInfo>,
+    /// Recent output, kept across restarts for the session.
+    recent_logs: LogBuffer,
+    /// Persistent copy of sidecar output, shared with the tracing subscriber.
+    log_file: LogWriter,
+    health_history: VecDeque<HealthRecord>,
+    /// Dependency checks from the sidecar's last readiness response.
+    checks: Vec<DependencyCheck>,
+    /// Whether we've told the frontend the sidecar is degraded. Kept across
+    /// restarts so a cleared event still follows a degraded one.
+    degradation: DegradationTracker,
+}
+
+impl SidecarState {
+    fn new(
+        config: &SidecarConfig,
+        log_file: LogWriter,
+        setup_started: Instant,
+        state_changes: mpsc::UnboundedSender<SidecarStateChangedPayload>,
+    ) -> Self {
+        Self {
+            endpoint: None,
+            child: None,
+            exited: None,
+            drain: None,
+            state_changes,
+            generation: 0,
+            ready_announced: None,
+            ready_payload: None,
+            ready_changed: watch::Sender::new(()),
+            stop_requested: false,
+            crash_budget: CrashBudget::default(),
+            extra_args: config.extra_args.clone(),
+            bind_lan: config.bind_lan,
+            health_interval: config.health_interval,
+            discovery_file: None,
+            log_stream: LogStream::new(),
+            token: None,
+            redactor: Redactor::new(&config.redact_patterns),
+            keep_raw_output: config.log_raw_bytes,
+            replaced_bytes: 0,
+            truncated_lines: 0,
+            sidecar_log_level: config.log_level,
+            last_pong: 0,
+            resources: ResourceHistory::default(),
+            certificate: None,
+            reload_seq: 0,
+            pending_reload: None,
+            last_latency_ms: None,
+            startup_stderr: Vec::new(),
+            last_error: None,
+            port_retries: 0,
+            port_announced: None,
+            ready_line: None,
+            startup_timings: StartupTimings::new(setup_started),
+            startup_retries: 0,
+            conflicted_port: None,
+            restart_gate: RestartGate::default(),
+            status: SidecarStatus::NotStarted,
+            started_at: None,
+            restart_count: 0,
+            last_exit: None,
+            recent_logs: LogBuffer::new(config.log_buffer),
+            log_file,
+            health_history: VecDeque::with_capacity(HEALTH_HISTORY_CAPACITY),
+            checks: Vec::new(),
+            degradation: DegradationTracker::default(),
+        }
+    }
+
+    /// Move to `status` because of `reason`. Every lifecycle path goes
+    /// through here, so this is the one place that sees each change and
+    /// queues it for `sidecar-state-changed`.
+    fn transition(&mut self, status: SidecarStatus, reason: &str) {
+        if self.status == status {
+            return;
+        }
+        debug!("Sidecar status: {} -> {} ({reason})", self.status.name(), status.name());
+        let change = SidecarStateChangedPayload {
+            from: std::mem::replace(&mut self.status, status),
+            to: self.status.clone(),
+            at: now_unix_ms(),
+            reason: reason.to_string(),
+        };
+        // Only fails once the relay has stopped with the app.
+        let _ = self.state_changes.send(change);
+        self.ready_changed.send_replace(());
+    }
+
+    /// What `sidecar-ready` carried, if the current child is healthy and it
+    /// has been announced.
+    fn announced_ready(&self) -> Option<&SidecarReadyPayload> {
+        let current = matches!(self.status, SidecarStatus::Ready)
+            && self.ready_announced == Some(self.generation);
+        self.ready_payload.as_ref().filter(|_| current)
+    }
+
+    /// Append a line from `stream` to the log file, the in-memory buffer and
+    /// any live log subscribers. Returns the parsed entry. Sidecar output must
+    /// already have been through `redact`.
+    fn push_log(&mut self, stream: &'static str, line: String) -> LogLine {
+        self.push_entry(LogLine::new(stream, line, now_unix_ms()))
+    }
+
+    /// Log a line of sidecar output: mask secrets, count bytes that weren't
+    /// valid UTF-8 and keep them raw if configured to.
+    fn push_output(&mut self, stream: &'static str, line: Line) -> LogLine {
+        if line.replaced > 0 && self.replaced_bytes == 0 {
+            warn!("Sidecar {stream} contains invalid UTF-8; replaced bytes are counted");
+        }
+        self.replaced_bytes += line.replaced as u64;
+        if line.truncated > 0 {
+            if self.truncated_lines == 0 {
+                warn!("Sidecar {stream} has lines over the length cap; truncations are counted");
+            }
+            self.truncated_lines += 1;
+        }
+        let raw = line.raw.filter(|_| self.keep_raw_output).map(|raw| self.redact_raw(&raw));
+        let mut entry = LogLine::new(stream, self.redact(line.text), now_unix_ms());
+        entry.replaced_bytes = line.replaced;
+        entry.raw = raw;
+        self.push_entry(entry)
+    }
+
+    fn push_entry(&mut self, entry: LogLine) -> LogLine {
+        let tag = logs::stream_tag(entry.stream);
+        self.log_file.append(tag, entry.timestamp, &entry.line);
+        self.log_stream.push(entry.clone());
+        self.recent_logs.push(entry.clone());
+        entry
+    }
+
+    /// Tell the child which level to log at, as a `log-level <level>` line on
+    /// the same stdin control channel as the heartbeat. Sidecars that don't
+    /// read it just keep their own level.
+    fn forward_log_level(&mut self) -> std::io::Result<()> {
+        let (Some(level), Some(child)) = (self.sidecar_log_level, self.child.as_mut()) else {
+            return Ok(());
+        };
+        child
+            .write(format!("log-level {}\n", level.as_str()).as_bytes())
+            .map_err(std::io::Error::other)
+    }
+
+    /// Keep the opening stderr lines of a sidecar that hasn't become healthy
+    /// yet, so an exit during startup can say what went wrong.
+    fn capture_startup_stderr(&mut self, generation: u64, line: &str) {
+        if self.generation == generation
+            && matches!(self.status, SidecarStatus::Starting)
+            && self.startup_stderr.len() < STARTUP_STDERR_LINES
+        {
+            let line = line.chars().take(STARTUP_STDERR_LINE_CHARS).collect();
+            self.startup_stderr.push(line);
+        }
+    }
+
+    /// Have the log writer push what it has queued to disk.
+    fn flush_log(&mut self) {
+        self.log_file.flush();
+    }
+
+    /// Mask our own auth token and anything else that looks like a secret, in
+    /// case the sidecar echoes it.
+    fn redact(&self, line: String) -> String {
+        let line = match &self.token {
+            Some(token) if line.contains(token.as_str()) => {
+                line.replace(token.as_str(), redact::MASK)
+            }
+            _ => line,
+        };
+        match self.redactor.redact(&line) {
+            Cow::Borrowed(_) => line,
+            Cow::Owned(masked) => masked,
+        }
+    }
+
+    /// `redact` applied to each valid UTF-8 run of `raw`, keeping the
+    /// invalid bytes between them.
+    fn redact_raw(&self, raw: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(raw.len());
+        for chunk in raw.utf8_chunks() {
+            out.extend_from_slice(self.redact(chunk.valid().to_string()).as_bytes());
+            out.extend_from_slice(chunk.invalid());
+        }
+        out
+    }
+
+    /// The base URL, once the sidecar has passed its startup health check.
+    fn ready_url(&self) -> Option<SidecarUrl> {
+        match self.status {
+            SidecarStatus::Ready | SidecarStatus::Unhealthy => {
+                self.endpoint.as_ref().map(SidecarEndpoint::url)
+            }
+            _ => None,
+        }
+    }
+
+    /// Record the port a `--port 0` sidecar announced and wake its startup
+    /// task. Only the first announcement from the current process counts.
+    fn accept_announced_port(
+        &mut self,
+        generation: u64,
+        port: u16,
+    ) -> Option<SidecarPortAssignedPayload> {
+        if self.generation != generation {
+            return None;
+        }
+        let tx = self.port_announced.take()?;
+        info!("Sidecar announced port {port}");
+        self.startup_timings.mark(Milestone::PortAcquired);
+        if let Some(SidecarEndpoint::Tcp { port: p, .. }) = self.endpoint.as_mut() {
+            *p = port;
+        }
+        let _ = tx.send(port);
+        self.endpoint.as_ref().map(SidecarPortAssignedPayload::new)
+    }
+
+    /// Wake the startup task waiting for `READY` from the current child.
+    fn accept_ready_line(&mut self, generation: u64) {
+        if self.generation != generation {
+            return;
+        }
+        if let Some(tx) = self.ready_line.take() {
+            info!("Sidecar printed READY");
+            let _ = tx.send(());
+        }
+    }
+
+    /// Delete the discovery file, if one was written for the current child.
+    fn remove_discovery_file(&mut self) {
+        if let Some(path) = self.discovery_file.take() {
+            if let Err(e) = discovery::remove(&path) {
+                warn!("Could not remove discovery file {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// Take over a still-starting child whose output reported a bind conflict,
+    /// marking it restarting so its exit isn't treated as a crash. Returns false
+    /// if `generation` is stale or the sidecar already got past startup.
+    fn claim_port_conflict(&mut self, generation: u64) -> bool {
+        if self.generation != generation || !matches!(self.status, SidecarStatus::Starting) {
+            return false;
+        }
+        self.transition(SidecarStatus::Restarting, "port conflict");
+        self.port_retries += 1;
+        self.conflicted_port = self.endpoint.as_ref().and_then(SidecarEndpoint::port);
+        true
+    }
+
+    /// Append a health check result, dropping the oldest once at capacity.
+    fn push_health(&mut self, record: HealthRecord) {
+        if self.health_history.len() == HEALTH_HISTORY_CAPACITY {
+            self.health_history.pop_front();
+        }
+        self.health_history.push_back(record);
+    }
+}
+
+/// Payload emitted to the frontend when the sidecar is healthy.
+#[derive(Clone, Serialize)]
+struct SidecarReadyPayload {
+    /// TCP port, or 0 when the sidecar listens on a socket.
+    port: u16,
+    endpoint: SidecarEndpoint,
+    /// Same as `get_sidecar_url`.
+    url: SidecarUrl,
+    /// The sidecar's own dependency checks; empty if it reported none.
+    checks: Vec<DependencyCheck>,
+    /// The fixed port from `CLAUDETINI_SIDECAR_PORT`, if one was configured.
+    requested_port: Option<u16>,
+    /// Whether `port` is the requested one; `None` without a fixed port.
+    port_matched: Option<bool>,
+    /// Milliseconds from `spawn()` to the first passing health check; `None`
+    /// for the external dev sidecar, which we never spawn.
+    startup_ms: Option<u64>,
+}
+
+impl SidecarReadyPayload {
+    fn new(
+        endpoint: &SidecarEndpoint,
+        checks: Vec<DependencyCheck>,
+        requested_port: Option<u16>,
+        startup: Option<Duration>,
+    ) -> Self {
+        Self {
+            port: endpoint.port().unwrap_or(0),
+            endpoint: endpoint.clone(),
+            url: endpoint.url(),
+            checks,
+            requested_port,
+            port_matched: requested_port.map(|p| endpoint.port() == Some(p)),
+            startup_ms: startup.map(|d| d.as_millis() as u64),
+        }
+    }
+}
+
+/// Payload of `app-crash-detected`: reports from earlier sessions the
+/// frontend hasn't been told about.
+#[derive(Clone, Serialize)]
+struct AppCrashDetectedPayload<'a> {
+    reports: &'a [CrashReportInfo],
+}
+
+/// Payload emitted for problems the app worked around on its own.
+#[derive(Clone, Serialize)]
+struct SidecarWarningPayload {
+    kind: &'static str,
+    message: String,
+}
+
+/// Payload emitted as soon as the sidecar's address is chosen, before it is
+/// healthy. Lets the UI prepare clients; requests should still wait for ready.
+#[derive(Clone, Serialize)]
+struct SidecarPortAssignedPayload {
+    /// TCP port, or 0 when the sidecar listens on a socket.
+    port: u16,
+    endpoint: SidecarEndpoint,
+}
+
+impl SidecarPortAssignedPayload {
+    fn new(endpoint: &SidecarEndpoint) -> Self {
+        Self {
+            port: endpoint.port().unwrap_or(0),
+            endpoint: endpoint.clone(),
+        }
+    }
+}
+
+/// Payload emitted on every status change, alongside the specific events,
+/// so the frontend can follow the lifecycle from one listener.
+#[derive(Clone, Serialize)]
+struct SidecarStateChangedPayload {
+    from: SidecarStatus,
+    to: SidecarStatus,
+    /// Unix milliseconds.
+    at: u64,
+    reason: String,
+}
+
+/// Payload emitted when the supervisor restarts the sidecar.
+#[derive(Clone, Serialize)]
+struct SidecarRestartingPayload {
+    reason: String,
+    /// Position within the crash-loop budget; `None` for explicit restarts.
+    attempt: Option<u32>,
+}
+
+/// Payload emitted when the sidecar is running but not doing its job.
+#[derive(Clone, Serialize)]
+struct SidecarUnhealthyPayload {
+    kind: &'static str,
+    message: String,
+}
+
+/// Payload emitted when a check after a network change reached a remote sidecar.
+#[derive(Clone, Serialize)]
+struct SidecarRecoveredPayload {
+    latency_ms: u64,
+}
+
+/// Payload emitted when a check after a network change couldn't reach it.
+#[derive(Clone, Serialize)]
+struct SidecarUnreachablePayload {
+    message: String,
+}
+
+/// Payload emitted when the sidecar responds but is slow or flapping
+/// (`kind` "slow"), or can't be started at all ("unavailable").
+#[derive(Clone, Serialize)]
+struct SidecarDegradedPayload {
+    kind: &'static str,
+    reason: String,
+    /// Only for a slow sidecar.
+    window_stats: Option<HealthStats>,
+}
+
+/// Payload emitted once a degraded sidecar is behaving normally again.
+#[derive(Clone, Serialize)]
+struct SidecarDegradationClearedPayload {
+    window_stats: HealthStats,
+}
+
+/// Payload emitted after each passing background health check. Deliberately
+/// tiny, since it fires every `health_interval`.
+#[derive(Clone, Serialize)]
+struct SidecarHeartbeatPayload {
+    latency_ms: u64,
+}
+
+/// Payload emitted with a resource sample, throttled to noticeable changes.
+#[derive(Clone, Serialize)]
+struct SidecarResourcesPayload {
+    pid: u32,
+    #[serde(flatten)]
+    sample: ResourceSample,
+}
+
+/// Payload emitted when the sidecar became healthy, but only after more
+/// startup health checks than `slow_start_attempts`.
+#[derive(Clone, Serialize)]
+struct SidecarSlowStartPayload {
+    attempts: u32,
+    elapsed_ms: u64,
+}
+
+/// Payload emitted when the supervisor gives up on the sidecar.
+#[derive(Clone, Serialize)]
+struct SidecarFailedPayload {
+    error: String,
+    message: &'static str,
+}
+
+/// Payload emitted when something drops connections to the sidecar's port.
+#[derive(Clone, Serialize)]
+struct SidecarBlockedPayload {
+    endpoint: String,
+    reason: String,
+    guidance: &'static str,
+}
+
+/// Payload emitted when the sidecar can't be started or reached.
+#[derive(Clone, Serialize)]
+struct SidecarErrorPayload {
+    kind: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checks: Vec<DependencyCheck>,
+    /// Opening stderr lines of a sidecar that exited during startup.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stderr: Vec<String>,
+    /// How a sidecar that exited during startup ended.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<i32>,
+}
+
+/// Aggregate sidecar reliability numbers for dashboards.
+#[derive(Serialize)]
+struct SidecarMetrics {
+    #[serde(flatten)]
+    health: HealthStats,
+    restart_count: u32,
+}
+
+/// Whether the sidecar binary that would be spawned is actually there.
+#[derive(Serialize)]
+struct SidecarBinary {
+    available: bool,
+    path: PathBuf,
+    /// True when `CLAUDETINI_SIDECAR_BIN` overrides the bundled binary.
+    custom: bool,
+}
+
+/// How external tooling can reach the sidecar, including where the discovery
+/// file with the auth token lives.
+#[derive(Serialize)]
+struct ConnectionInfo {
+    url: Option<SidecarUrl>,
+    pid: Option<u32>,
+    discovery_file: Option<PathBuf>,
+    /// SHA-256 fingerprint of the certificate the sidecar serves, for
+    /// clients to pin; `None` over plain HTTP.
+    tls_fingerprint: Option<String>,
+    /// That certificate as PEM, for clients that trust a file instead.
+    tls_certificate: Option<String>,
+}
+
+/// Everything the supervisor knows about the sidecar, for support. Unlike
+/// `Diagnostics` it carries the config in effect and internal counters
+/// rather than logs.
+#[derive(Serialize)]
+struct SidecarStateDump {
+    status: SidecarStatus,
+    generation: u64,
+    endpoint: Option<SidecarEndpoint>,
+    pid: Option<u32>,
+    uptime_ms: Option<u64>,
+    /// Whether `sidecar-ready` went out for the current generation.
+    ready_announced: bool,
+    stop_requested: bool,
+    restart_count: u32,
+    /// Automatic restarts inside the crash-loop window.
+    recent_restarts: usize,
+    port_retries: u32,
+    startup_retries: u32,
+    last_exit: Option<ExitInfo>,
+    last_pong: u64,
+    last_latency_ms: Option<u64>,
+    health_interval_ms: u64,
+    health_checks_recorded: usize,
+    /// Output bytes that weren't valid UTF-8, replaced this session.
+    replaced_bytes: u64,
+    bind_lan: bool,
+    extra_args: Vec<String>,
+    sidecar_log_level: Option<logging::LogLevel>,
+    config: serde_json::Value,
+}
+
+/// Snapshot of sidecar state suitable for pasting into a bug report.
+#[derive(Serialize)]
+struct Diagnostics {
+    app_version: String,
+    port: Option<u16>,
+    status: SidecarStatus,
+    uptime_ms: Option<u64>,
+    restart_count: u32,
+    last_exit: Option<ExitInfo>,
+    /// Output bytes that weren't valid UTF-8; non-zero means the logs below
+    /// aren't exactly what the sidecar printed.
+    replaced_bytes: u64,
+    recent_logs: Vec<String>,
+}
+
+/// App, OS and architecture details for a diagnostics bundle.
+#[derive(Serialize)]
+struct SystemInfo {
+    app_name: String,
+    app_version: String,
+    tauri_version: &'static str,
+    os: &'static str,
+    os_family: &'static str,
+    arch: &'static str,
+}
+
+/// Build details for the About dialog and bug reports, for `get_app_info`.
+#[derive(Serialize)]
+struct AppInfo {
+    app_name: String,
+    app_version: String,
+    /// Short hash of the commit built from; `None` outside a git checkout.
+    git_commit: Option<&'static str>,
+    /// When the build script last ran (or `SOURCE_DATE_EPOCH`), in Unix seconds.
+    build_timestamp: Option<u64>,
+    tauri_version: &'static str,
+    /// Target triple, e.g. `aarch64-apple-darwin`.
+    target: &'static str,
+    debug: bool,
+}
+
+/// Milliseconds since the Unix epoch, used for timestamps sent to the frontend.
+fn now_unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Update the sidecar status in managed state, via `SidecarState::transition`.
+fn set_status(app_handle: &AppHandle, status: SidecarStatus, reason: &str) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    if let Ok(mut s) = state.lock() {
+        s.transition(status, reason);
+    };
+}
+
+/// Record a health check result in managed state.
+fn record_health(app_handle: &AppHandle, record: HealthRecord) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    if let Ok(mut s) = state.lock() {
+        s.push_health(record);
+    };
+}
+
+/// Reserve the fixed port if one is configured and free, otherwise fall back
+/// to a free port in the configured range, or any ephemeral port, and tell the
+/// frontend why.
+fn reserve_port(
+    app_handle: &AppHandle,
+    host: &str,
+    requested: Option<u16>,
+    last_good: Option<u16>,
+) -> Result<(u16, Vec<TcpListener>), SidecarError> {
+    if let Some(port) = requested {
+        match bind_port(host, port) {
+            Ok(reserved) => return Ok(reserved),
+            Err(e) => {
+                let message =
+                    format!("Port {port} is unavailable, picking another port instead: {e}");
+                warn!("{message}");
+                let payload = SidecarWarningPayload {
+                    kind: "port_unavailable",
+                    message,
+                };
+                let _ = app_handle.emit("sidecar-warning", payload);
+            }
+        }
+    }
+    if let Some(port) = last_good {
+        match bind_port(host, port) {
+            Ok(reserved) => return Ok(reserved),
+            Err(e) => info!("Last used port {port} is unavailable, picking another: {e}"),
+        }
+    }
+    ports::find_free_port(host, app_handle.state::<SidecarConfig>().port_range)
+}
+
+/// Emit `sidecar-ready` for `generation`. State is updated first, so
+/// `is_sidecar_ready` and `wait_for_sidecar_ready` report it even if the
+/// frontend wasn't listening yet.
+fn announce_ready(app_handle: &AppHandle, generation: u64, payload: SidecarReadyPayload) {
+    let mut first_ready = None;
+    if let Ok(mut s) = app_handle.state::<Mutex<SidecarState>>().lock() {
+        if s.generation == generation {
+            s.ready_announced = Some(generation);
+            s.ready_payload = Some(payload.clone());
+            s.ready_changed.send_replace(());
+        }
+        if s.startup_timings.mark(Milestone::Ready) {
+            first_ready = Some(s.startup_timings.summary());
+        }
+    }
+    if let Err(e) = app_handle.emit("sidecar-ready", payload) {
+        warn!("Could not emit sidecar-ready: {e}");
+    }
+    // Lands in the log file, so bundles show how earlier launches went.
+    if let Some(summary) = first_ready {
+        log_lifecycle(app_handle, &summary);
+    }
+}
+
+/// Note that startup reached `milestone`, if it hadn't before.
+fn mark_startup(app_handle: &AppHandle, milestone: Milestone) {
+    if let Ok(mut s) = app_handle.state::<Mutex<SidecarState>>().lock() {
+        s.startup_timings.mark(milestone);
+    }
+}
+
+/// Save `port` as the last known good one, when that's enabled. Only a
+/// convenience, so failures are just logged.
+fn remember_port(app_handle: &AppHandle, port: u16) {
+    if !app_handle.state::<SidecarConfig>().remember_port {
+        return;
+    }
+    let Some(dirs) = app_handle.try_state::<AppDirs>() else {
+        return;
+    };
+    let path = ports::last_port_path(&dirs.config);
+    if let Err(e) = ports::write_last_port(&path, port) {
+        warn!("Could not remember port {port} in {}: {e}", path.display());
+    }
+}
+
+/// Whether the current sidecar process is still the one spawned as `generation`.
+fn is_current(app_handle: &AppHandle, generation: u64) -> bool {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    state.lock().is_ok_and(|s| s.generation == generation)
+}
+
+/// Port from a `LISTENING <port>` or `LISTENING <host>:<port>` stdout line,
+/// which a sidecar may print once bound to confirm where it ended up.
+fn listening_port(line: &str) -> Option<u16> {
+    let addr = line.strip_prefix("LISTENING ")?.trim();
+    addr.rsplit(':').next()?.parse().ok()
+}
+
+/// The `READY` line a sidecar prints once its own initialisation is done.
+fn is_ready_line(line: &str) -> bool {
+    line.trim() == "READY"
+}
+
+/// Port from the `CLAUDETINI_PORT=<port>` line a sidecar launched with
+/// `--port 0` prints once bound.
+fn announced_port(line: &str) -> Option<u16> {
+    let port = line.strip_prefix("CLAUDETINI_PORT=")?.trim().parse().ok()?;
+    Some(port).filter(|&p| p != 0)
+}
+
+/// Whether a sidecar output line reports that its port was already taken.
+fn is_port_conflict(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    PORT_CONFLICT_PATTERNS.iter().any(|p| line.contains(p))
+}
+
+/// The sidecar failed to bind during startup (another process took the port,
+/// TIME_WAIT, antivirus), as claimed by `claim_port_conflict`. Respawn on a
+/// fresh port without touching the crash-loop budget, and report a port
+/// conflict (not a health timeout) once the retries run out.
+async fn retry_after_port_conflict(app_handle: &AppHandle) {
+    terminate_sidecar(app_handle).await;
+    let exhausted = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let Ok(mut s) = state.lock() else { return };
+        let exhausted = s.port_retries > MAX_PORT_CONFLICT_RETRIES;
+        if exhausted {
+            // Give a later manual restart a fresh set of retries.
+            s.port_retries = 0;
+            s.conflicted_port = None;
+        }
+        exhausted
+    };
+    if exhausted {
+        let attempts = MAX_PORT_CONFLICT_RETRIES + 1;
+        report_sidecar_error(app_handle, SidecarError::PortInUse { attempts });
+        return;
+    }
+    let _ = spawn_sidecar(app_handle);
+}
+
+/// Print a lifecycle message and record it in the sidecar log too, so the log
+/// file shows what the supervisor did around the sidecar's own output.
+/// Must not be called with the state lock held.
+fn log_lifecycle(app_handle: &AppHandle, message: &str) {
+    info!(target: SUPERVISOR_TARGET, "{message}");
+    if let Ok(mut s) = app_handle.state::<Mutex<SidecarState>>().lock() {
+        s.push_log("supervisor", message.to_string());
+    }
+}
+
+/// After a port-conflict respawn picks its new endpoint, record the old and
+/// new port in the console and the sidecar log so support can follow it.
+fn log_port_retry(app_handle: &AppHandle, endpoint: &SidecarEndpoint) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let Ok(mut s) = state.lock() else { return };
+    let Some(old) = s.conflicted_port.take() else { return };
+    let line = format!(
+        "Port {old} could not be bound, respawning on {endpoint} \
+         (retry {}/{MAX_PORT_CONFLICT_RETRIES})",
+        s.port_retries
+    );
+    warn!(target: SUPERVISOR_TARGET, "{line}");
+    s.push_log("supervisor", line);
+}
+
+/// Keep checking the sidecar after it becomes ready. A sidecar that is alive
+/// but not answering never produces a Terminated event, so after enough
+/// consecutive failures we restart it ourselves. Exits once `generation` is stale.
+/// Checks pause while the app is hidden; crashes are still caught by the
+/// Terminated event, which doesn't depend on polling.
+async fn monitor_health(app_handle: &AppHandle, generation: u64) {
+    let config = app_handle.state::<SidecarConfig>();
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let mut visibility = app_handle.state::<Visibility>().subscribe();
+    let threshold = config.health_failure_threshold;
+    let degradation = config.degradation;
+    let mut failures = 0u32;
+    // Only checks from this monitor, so startup attempts don't count as flapping.
+    let mut window: VecDeque<HealthSample> = VecDeque::new();
+
+    loop {
+        let interval = match state.lock() {
+            Ok(s) => s.health_interval,
+            Err(_) => return,
+        };
+        tokio::time::sleep(interval).await;
+        if visibility::pause_while_hidden(&mut visibility, config.hidden_grace_period).await {
+            // Check right away on resume rather than waiting another interval.
+            info!("App visible again, resuming sidecar health checks");
+        }
+
+        let (endpoint, token, managed) = match state.lock() {
+            Ok(s) if s.generation == generation
+                && matches!(s.status, SidecarStatus::Ready | SidecarStatus::Unhealthy) =>
+            {
+                match &s.endpoint {
+                    Some(endpoint) => (endpoint.clone(), s.token.clone(), s.child.is_some()),
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+
+        let started = Instant::now();
+        let probe = &config.health_probe;
+        let result = check_ready(&endpoint, probe, token.as_ref(), config.health_timeout).await;
+        let record = HealthRecord {
+            timestamp: now_unix_ms(),
+            ok: result.is_ok(),
+            latency_ms: started.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(ProbeError::to_string),
+        };
+        let latency_ms = record.latency_ms;
+        let window_ms = degradation.window.as_millis() as u64;
+        while window
+            .front()
+            .is_some_and(|s| record.timestamp.saturating_sub(s.timestamp) > window_ms)
+        {
+            window.pop_front();
+        }
+        window.push_back(HealthSample::from(&record));
+        let stats =
+            HealthStats::compute(window.make_contiguous(), record.timestamp, degradation.window);
+
+        let transition = match state.lock() {
+            // Bail out if the sidecar was restarted or stopped while we were checking.
+            Ok(s) if s.generation != generation
+                || !matches!(s.status, SidecarStatus::Ready | SidecarStatus::Unhealthy) =>
+            {
+                return;
+            }
+            Ok(mut s) => {
+                s.push_health(record);
+                match &result {
+                    Ok(checks) => {
+                        s.transition(SidecarStatus::Ready, "health check passed");
+                        s.checks = checks.clone();
+                        s.last_latency_ms = Some(latency_ms);
+                    }
+                    Err(_) => s.transition(SidecarStatus::Unhealthy, "health check failed"),
+                }
+                s.degradation.evaluate(&stats, &degradation, Instant::now())
+            }
+            Err(_) => None,
+        };
+        match transition {
+            Some(Transition::Degraded(reason)) => {
+                warn!("Sidecar degraded: {reason}");
+                let payload = SidecarDegradedPayload {
+                    kind: "slow",
+                    reason,
+                    window_stats: Some(stats),
+                };
+                let _ = app_handle.emit("sidecar-degraded", payload);
+            }
+            Some(Transition::Cleared) => {
+                info!("Sidecar no longer degraded");
+                let payload = SidecarDegradationClearedPayload { window_stats: stats };
+                let _ = app_handle.emit("sidecar-degradation-cleared", payload);
+            }
+            None => {}
+        }
+
+        match result {
+            Ok(_) => {
+                if failures > 0 {
+                    info!("Sidecar health recovered after {failures} failed checks");
+                }
+                failures = 0;
+                if config.heartbeat_events {
+                    let payload = SidecarHeartbeatPayload { latency_ms };
+                    let _ = app_handle.emit("sidecar-heartbeat", payload);
+                }
+            }
+            Err(e) => {
+                failures += 1;
+                warn!("Sidecar health check failed ({failures}/{threshold}): {e}");
+                if failures >= threshold && managed {
+                    let reason = format!("Sidecar failed {failures} consecutive health checks");
+                    auto_restart_sidecar(app_handle, reason).await;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// In remote mode, watch the route to the sidecar (VPN toggled, network
+/// switched) and re-check health as soon as it changes, emitting
+/// `sidecar-recovered` or `sidecar-unreachable` instead of waiting for the
+/// next monitor tick. A local sidecar isn't affected by network changes.
+async fn watch_network(app_handle: AppHandle) {
+    let config = app_handle.state::<SidecarConfig>();
+    if !config.remote {
+        return;
+    }
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let mut visibility = app_handle.state::<Visibility>().subscribe();
+    let mut last_route = None;
+
+    loop {
+        tokio::time::sleep(netwatch::POLL_INTERVAL).await;
+        visibility::pause_while_hidden(&mut visibility, config.hidden_grace_period).await;
+        let target = match state.lock() {
+            Ok(s) if matches!(s.status, SidecarStatus::Ready | SidecarStatus::Unhealthy) => {
+                s.endpoint.clone().map(|e| (e, s.token.clone(), s.generation))
+            }
+            Ok(_) => None,
+            Err(_) => return,
+        };
+        let Some((endpoint, token, generation)) = target else {
+            continue;
+        };
+        let route = netwatch::route_to(&endpoint).await;
+        match last_route.replace(route) {
+            Some(previous) if previous != route => {
+                info!("Network route to {endpoint} changed ({previous:?} -> {route:?})");
+            }
+            _ => continue,
+        }
+
+        let started = Instant::now();
+        let probe = &config.health_probe;
+        let result = check_ready(&endpoint, probe, token.as_ref(), config.health_timeout).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        record_health(
+            &app_handle,
+            HealthRecord {
+                timestamp: now_unix_ms(),
+                ok: result.is_ok(),
+                latency_ms,
+                error: result.as_ref().err().map(ProbeError::to_string),
+            },
+        );
+        if let Ok(mut s) = state.lock() {
+            if s.generation == generation
+                && matches!(s.status, SidecarStatus::Ready | SidecarStatus::Unhealthy)
+            {
+                let (status, reason) = match result {
+                    Ok(_) => (SidecarStatus::Ready, "reachable after network change"),
+                    Err(_) => (SidecarStatus::Unhealthy, "unreachable after network change"),
+                };
+                s.transition(status, reason);
+            }
+        };
+        match result {
+            Ok(_) => {
+                info!("Sidecar reachable after network change ({latency_ms}ms)");
+                let payload = SidecarRecoveredPayload { latency_ms };
+                let _ = app_handle.emit("sidecar-recovered", payload);
+            }
+            Err(e) => {
+                warn!("Sidecar unreachable after network change: {e}");
+                let payload = SidecarUnreachablePayload {
+                    message: e.to_string(),
+                };
+                let _ = app_handle.emit("sidecar-unreachable", payload);
+            }
+        }
+    }
+}
+
+/// Sample the child's CPU, memory and open files every `resource_interval`
+/// for `get_sidecar_resources` and `sidecar-resources`. The pid is taken once
+/// per generation and sampling ends with that child, so a pid reused after a
+/// restart is never read. Pauses while the app is hidden.
+async fn sample_resources(app_handle: &AppHandle, generation: u64) {
+    let config = app_handle.state::<SidecarConfig>();
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let mut visibility = app_handle.state::<Visibility>().subscribe();
+    let child_pid = |s: &SidecarState| {
+        s.child.as_ref().map(CommandChild::pid).filter(|_| s.generation == generation)
+    };
+    let Some(pid) = state.lock().ok().as_deref().and_then(child_pid) else {
+        return;
+    };
+    let mut sampler = Sampler::new(pid);
+    let mut throttle = EmitThrottle::default();
+    loop {
+        // Gone or its pid reused; the exit itself is handled elsewhere.
+        let Some(sample) = sampler.sample(now_unix_ms()) else {
+            return;
+        };
+        {
+            let Ok(mut s) = state.lock() else { return };
+            if child_pid(&s) != Some(pid) {
+                return;
+            }
+            s.resources.push(sample.clone());
+        }
+        if throttle.admit(&sample, Instant::now()) {
+            let payload = SidecarResourcesPayload { pid, sample };
+            let _ = app_handle.emit("sidecar-resources", payload);
+        }
+        tokio::time::sleep(config.resource_interval).await;
+        visibility::pause_while_hidden(&mut visibility, config.hidden_grace_period).await;
+    }
+}
+
+/// Ping the sidecar over stdin and expect a matching `pong <seq>` on stdout.
+/// Catches a sidecar whose listener still accepts connections while its
+/// event loop is deadlocked. Only runs against a child we spawned, since
+/// that's the only stdin we hold; exits once `generation` is stale.
+async fn heartbeat(app_handle: &AppHandle, generation: u64) {
+    let config = app_handle.state::<SidecarConfig>();
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let threshold = config.heartbeat_missed_threshold;
+    let mut missed = 0u32;
+
+    for seq in 1u64.. {
+        tokio::time::sleep(config.heartbeat_interval).await;
+        {
+            let Ok(mut s) = state.lock() else { return };
+            if s.generation != generation {
+                return;
+            }
+            let Some(child) = s.child.as_mut() else { return };
+            if let Err(e) = child.write(format!("ping {seq}\n").as_bytes()) {
+                warn!("Failed to write heartbeat to sidecar: {e}");
+                return;
+            }
+        }
+
+        tokio::time::sleep(config.heartbeat_timeout).await;
+        let answered = match state.lock() {
+            Ok(s) if s.generation == generation => s.last_pong >= seq,
+            _ => return,
+        };
+        if answered {
+            missed = 0;
+            continue;
+        }
+
+        missed += 1;
+        warn!("Sidecar missed heartbeat {seq} ({missed}/{threshold})");
+        if missed >= threshold {
+            let message = format!("Sidecar missed {missed} consecutive heartbeats");
+            set_status(app_handle, SidecarStatus::Unhealthy, &message);
+            let payload = SidecarUnhealthyPayload {
+                kind: "hung",
+                message: message.clone(),
+            };
+            let _ = app_handle.emit("sidecar-unhealthy", payload);
+            auto_restart_sidecar(app_handle, message).await;
+            return;
+        }
+    }
+}
+
+/// Terminate the sidecar process: ask it to exit (SIGTERM on Unix) and
+/// force-kill it if it hasn't terminated within the configured grace period.
+async fn terminate_sidecar(app_handle: &AppHandle) {
+    let (child, exited) = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let Ok(mut s) = state.lock() else {
+            return;
+        };
+        s.stop_requested = true;
+        (s.child.take(), s.exited.take())
+    };
+    let Some(child) = child else {
+        return;
+    };
+
+    #[cfg(unix)]
+    if let Some(exited) = exited {
+        let timeout = app_handle.state::<SidecarConfig>().graceful_stop_timeout;
+        if signal::terminate_gracefully(child.pid(), exited, timeout).await {
+            log_lifecycle(app_handle, "Sidecar exited gracefully");
+            return;
+        }
+        let ms = timeout.as_millis();
+        let message = format!("Sidecar did not exit on SIGTERM within {ms}ms, killing");
+        log_lifecycle(app_handle, &message);
+    }
+    // No graceful shutdown signal on Windows; go straight to kill.
+    #[cfg(not(unix))]
+    drop(exited);
+
+    if let Err(e) = child.kill() {
+        warn!("Failed to kill sidecar: {e}");
+    }
+}
+
+/// Restart the sidecar, unless it has already been restarted too often within
+/// the crash-loop window, in which case give up and emit `sidecar-failed`.
+#[instrument(name = "auto_restart", skip_all, fields(reason = %reason))]
+async fn auto_restart_sidecar(app_handle: &AppHandle, reason: String) {
+    let config = app_handle.state::<SidecarConfig>();
+    let attempt = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let Ok(mut s) = state.lock() else {
+            return;
+        };
+        // A late crash or failed check after giving up mustn't revive it.
+        if matches!(s.status, SidecarStatus::Failed { .. } | SidecarStatus::Degraded { .. }) {
+            return;
+        }
+        let attempt = s.crash_budget.spend(
+            Instant::now(),
+            config.restart_window,
+            config.max_restarts,
+        );
+        if attempt.is_some() {
+            s.restart_count += 1;
+            s.transition(SidecarStatus::Restarting, &reason);
+        }
+        attempt
+    };
+
+    let Some(attempt) = attempt else {
+        let error = SidecarError::CrashLoop {
+            restarts: config.max_restarts,
+            window_secs: config.restart_window.as_secs(),
+            reason,
+        }
+        .to_string();
+        error!("{error}");
+        terminate_sidecar(app_handle).await;
+        // Terminal: only an explicit restart, which resets the budget, tries again.
+        let payload = SidecarFailedPayload {
+            error: error.clone(),
+            message: CRASH_LOOP_MESSAGE,
+        };
+        let _ = app_handle.emit("sidecar-failed", payload);
+        enter_degraded_mode(app_handle, error, CRASH_LOOP_MESSAGE);
+        return;
+    };
+
+    let max = config.max_restarts;
+    log_lifecycle(app_handle, &format!("Restarting sidecar (attempt {attempt}/{max}): {reason}"));
+    let payload = SidecarRestartingPayload {
+        reason,
+        attempt: Some(attempt),
+    };
+    let _ = app_handle.emit("sidecar-restarting", payload);
+    terminate_sidecar(app_handle).await;
+    let _ = spawn_sidecar(app_handle);
+}
+
+/// Restart on request from the frontend. Not subject to the crash-loop budget.
+/// Returns the new port once the process is spawned: `None` in socket mode or
+/// when a `--port 0` sidecar has yet to announce one.
+#[instrument(name = "restart", skip_all, fields(reason = %reason))]
+async fn restart_explicitly(app_handle: &AppHandle, reason: &str) -> RestartResult {
+    {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        s.restart_count += 1;
+        // An explicit restart is how the user retries after the supervisor gave up.
+        s.crash_budget.reset();
+        s.transition(SidecarStatus::Restarting, reason);
+    }
+    log_lifecycle(app_handle, &format!("Restarting sidecar: {reason}"));
+    let payload = SidecarRestartingPayload {
+        reason: reason.to_string(),
+        attempt: None,
+    };
+    let _ = app_handle.emit("sidecar-restarting", payload);
+    terminate_sidecar(app_handle).await;
+    let endpoint = spawn_sidecar(app_handle)?;
+    Ok(endpoint.port().filter(|&p| p != 0))
+}
+
+/// Reject extra arguments that would override flags the app manages itself.
+fn validate_extra_args(args: &[String]) -> Result<(), SidecarError> {
+    for arg in args {
+        let flag = arg.split('=').next().unwrap_or(arg);
+        if RESERVED_SIDECAR_ARGS.contains(&flag) {
+            return Err(SidecarError::ReservedArg(flag.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Whether the sidecar runs outside the app (dev mode), so we can't spawn or restart it.
+fn uses_external_sidecar(app_handle: &AppHandle) -> bool {
+    let config = app_handle.state::<SidecarConfig>();
+    config.dev_mode && config.custom_binary.is_none()
+}
+
+/// LAN binding only makes sense for a sidecar we spawn over TCP on the
+/// default loopback host; explicit hosts and sockets are left alone.
+fn lan_binding_applies(app_handle: &AppHandle) -> bool {
+    let config = app_handle.state::<SidecarConfig>();
+    !uses_external_sidecar(app_handle)
+        && matches!(config.transport, Transport::Tcp)
+        && config.host == LOOPBACK_HOST
+}
+
+/// URLs other devices on the LAN can use for a sidecar on `port`.
+fn lan_urls(scheme: &str, port: u16) -> Vec<String> {
+    netwatch::lan_addresses()
+        .into_iter()
+        .map(|addr| format!("{scheme}://{addr}:{port}"))
+        .collect()
+}
+
+/// Tell the user, loudly, that the sidecar on `port` is open to the network.
+fn warn_lan_exposed(app_handle: &AppHandle, scheme: &str, port: u16) {
+    let urls = lan_urls(scheme, port);
+    let reachable_at = if urls.is_empty() {
+        String::new()
+    } else {
+        format!(" at {}", urls.join(", "))
+    };
+    let message = format!(
+        "The sidecar is reachable from other devices on your network{reachable_at}. \
+         Requests still need its auth token; turn LAN binding off when you're done."
+    );
+    warn!("{message}");
+    let payload = SidecarWarningPayload {
+        kind: "lan_exposed",
+        message,
+    };
+    let _ = app_handle.emit("sidecar-warning", payload);
+}
+
+fn ensure_enabled(app_handle: &AppHandle) -> Result<(), SidecarError> {
+    if app_handle.state::<SidecarConfig>().disabled {
+        return Err(SidecarError::Disabled);
+    }
+    Ok(())
+}
+
+fn ensure_restartable(app_handle: &AppHandle) -> Result<(), SidecarError> {
+    ensure_enabled(app_handle)?;
+    if uses_external_sidecar(app_handle) {
+        return Err(SidecarError::ExternalSidecar);
+    }
+    Ok(())
+}
+
+/// Whether the sidecar is served over TLS: only when asked for, and only for
+/// a TCP sidecar this app spawns.
+fn tls_applies(app_handle: &AppHandle) -> bool {
+    let config = app_handle.state::<SidecarConfig>();
+    config.tls && !uses_external_sidecar(app_handle) && matches!(config.transport, Transport::Tcp)
+}
+
+/// This install's sidecar certificate, loaded or generated on first use;
+/// `None` when TLS doesn't apply.
+fn sidecar_certificate(
+    app_handle: &AppHandle,
+    data_dir: &Path,
+) -> Result<Option<tls::Certificate>, SidecarError> {
+    if !tls_applies(app_handle) {
+        return Ok(None);
+    }
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let cached = state.lock().map_err(|_| SidecarError::LockPoisoned)?.certificate.clone();
+    if cached.is_some() {
+        return Ok(cached);
+    }
+    let certificate = tls::Certificate::load_or_generate(&data_dir.join(tls::DIR_NAME))
+        .map_err(|e| SidecarError::Tls(Arc::new(e)))?;
+    info!("Sidecar certificate fingerprint: {}", certificate.fingerprint());
+    if let Ok(mut s) = state.lock() {
+        s.certificate = Some(certificate.clone());
+    }
+    Ok(Some(certificate))
+}
+
+/// Tell the frontend why the sidecar couldn't be started, then carry on
+/// without it.
+fn report_sidecar_error(app_handle: &AppHandle, error: SidecarError) {
+    let message = error.to_string();
+    error!("Sidecar error ({}): {message}", error.kind());
+    let payload = SidecarErrorPayload {
+        kind: error.kind(),
+        message: message.clone(),
+        checks: Vec::new(),
+        stderr: Vec::new(),
+        exit_code: None,
+        signal: None,
+    };
+    emit_sidecar_error(app_handle, payload);
+    enter_degraded_mode(app_handle, message, DEGRADED_MESSAGE);
+}
+
+/// Give up on the sidecar until an explicit restart and say so with
+/// `sidecar-degraded`, so the UI can offer limited functionality and a retry
+/// instead of waiting forever.
+fn enter_degraded_mode(app_handle: &AppHandle, error: String, message: &str) {
+    log_lifecycle(app_handle, &format!("Entering degraded mode: {error}"));
+    let status = SidecarStatus::Degraded {
+        error: error.clone(),
+        message: message.to_string(),
+    };
+    set_status(app_handle, status, &error);
+    let payload = SidecarDegradedPayload {
+        kind: "unavailable",
+        reason: error,
+        window_stats: None,
+    };
+    let _ = app_handle.emit("sidecar-degraded", payload);
+}
+
+/// The sidecar died while starting: tell the frontend, with whatever it
+/// printed to stderr. Status is left to the restart that follows.
+fn report_early_exit(
+    app_handle: &AppHandle,
+    code: Option<i32>,
+    signal: Option<i32>,
+    stderr: Vec<String>,
+) {
+    let error = SidecarError::ExitedDuringStartup {
+        code,
+        signal,
+        stderr: stderr.clone(),
+    };
+    let message = error.to_string();
+    error!("Sidecar error ({}): {message}", error.kind());
+    let payload = SidecarErrorPayload {
+        kind: error.kind(),
+        message,
+        checks: Vec::new(),
+        stderr,
+        exit_code: code,
+        signal,
+    };
+    emit_sidecar_error(app_handle, payload);
+}
+
+/// Send `sidecar-error`, keeping it for `get_last_sidecar_error` so a
+/// frontend that wasn't listening yet can still show what went wrong.
+fn emit_sidecar_error(app_handle: &AppHandle, payload: SidecarErrorPayload) {
+    if let Ok(mut s) = app_handle.state::<Mutex<SidecarState>>().lock() {
+        s.last_error = Some(payload.clone());
+    }
+    let _ = app_handle.emit("sidecar-error", payload);
+}
+
+/// Remember the dependency checks from the latest readiness response.
+fn store_checks(app_handle: &AppHandle, checks: &[DependencyCheck]) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    if let Ok(mut s) = state.lock() {
+        s.checks = checks.to_vec();
+    };
+}
+
+/// The spawned sidecar never became healthy: report why, including whatever
+/// dependency checks it managed to report, and carry on without it.
+fn report_startup_failure(app_handle: &AppHandle, error: ProbeError) {
+    warn!("Sidecar health poll failed: {error}");
+    store_checks(app_handle, &error.checks);
+    let message = error.to_string();
+    let payload = SidecarErrorPayload {
+        kind: error.error.kind(),
+        message: message.clone(),
+        checks: error.checks,
+        stderr: Vec::new(),
+        exit_code: None,
+        signal: None,
+    };
+    emit_sidecar_error(app_handle, payload);
+    enter_degraded_mode(app_handle, message, DEGRADED_MESSAGE);
+}
+
+/// Publish the ready sidecar's address, pid and token in the app data dir for
+/// external tooling. Only a convenience, so failures are just logged.
+fn write_discovery_file(app_handle: &AppHandle) {
+    let Some(dirs) = app_handle.try_state::<AppDirs>() else {
+        return;
+    };
+    let path = discovery::discovery_path(&dirs.data);
+    let version = app_handle.package_info().version.to_string();
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let Ok(mut s) = state.lock() else {
+        return;
+    };
+    let (Some(endpoint), Some(child)) = (&s.endpoint, &s.child) else {
+        return;
+    };
+    let result = discovery::write(
+        &path,
+        &Discovery {
+            port: endpoint.port(),
+            endpoint,
+            pid: child.pid(),
+            token: s.token.as_ref().map(SidecarToken::as_str),
+            app_version: &version,
+        },
+    );
+    match result {
+        Ok(()) => s.discovery_file = Some(path),
+        Err(e) => warn!("Could not write discovery file {}: {e}", path.display()),
+    }
+}
+
+/// A spawned sidecar never became healthy, e.g. it got stuck binding or its
+/// port was hijacked. Kill it rather than leaving it orphaned on the port and
+/// respawn on a fresh one, reporting the failure only once retries run out.
+async fn retry_after_startup_timeout(app_handle: &AppHandle, error: ProbeError) {
+    let retry = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let Ok(mut s) = state.lock() else { return };
+        s.startup_retries += 1;
+        let retry = s.startup_retries <= MAX_STARTUP_RETRIES;
+        if retry {
+            s.transition(SidecarStatus::Restarting, "never became healthy");
+        } else {
+            s.startup_retries = 0;
+        }
+        retry
+    };
+    terminate_sidecar(app_handle).await;
+    if !retry {
+        report_startup_failure(app_handle, error);
+        return;
+    }
+    warn!("Sidecar never became healthy, respawning on a new port: {error}");
+    let _ = spawn_sidecar(app_handle);
+}
+
+/// Startup checks against `endpoint` failed. A refused connection just means
+/// the sidecar isn't listening, but one that hangs or is denied is likely a
+/// firewall; say so with `sidecar-blocked` instead of leaving the user with
+/// a sidecar that seems dead.
+async fn check_blocked(app_handle: &AppHandle, endpoint: &SidecarEndpoint) {
+    // Local sockets aren't filtered the way loopback TCP is.
+    if endpoint.port().is_none() {
+        return;
+    }
+    match health::preflight(endpoint, PREFLIGHT_TIMEOUT).await {
+        Reachability::Filtered(reason) => {
+            log_lifecycle(app_handle, &format!("Connections to {endpoint} blocked: {reason}"));
+            let payload = SidecarBlockedPayload {
+                endpoint: endpoint.to_string(),
+                reason,
+                guidance: BLOCKED_GUIDANCE,
+            };
+            let _ = app_handle.emit("sidecar-blocked", payload);
+        }
+        outcome => info!("Pre-flight connect to {endpoint}: {outcome:?}"),
+    }
+}
+
+/// Check that a custom sidecar path points at an executable file.
+fn validate_sidecar_binary(path: &Path) -> Result<(), SidecarError> {
+    let invalid = |problem: String| {
+        SidecarError::CustomBinary(format!("Custom sidecar binary {} {problem}", path.display()))
+    };
+    let meta = std::fs::metadata(path).map_err(|e| invalid(format!("is not accessible: {e}")))?;
+    if !meta.is_file() {
+        return Err(invalid("is not a file".into()));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.permissions().mode() & 0o111 == 0 {
+            return Err(invalid("is not executable (try chmod +x)".into()));
+        }
+    }
+    Ok(())
+}
+
+/// Where `shell().sidecar(name)` will look: next to the app
+/// executable (one level up from `deps` under `cargo test`), with `.exe` on
+/// Windows. The `-<target-triple>` suffix the binary has in `binaries/` is
+/// stripped when Tauri copies it there, so the runtime name has none.
+fn bundled_sidecar_path(name: &str) -> std::io::Result<PathBuf> {
+    let exe = tauri::utils::platform::current_exe()?;
+    let exe_dir = exe.parent().unwrap_or(Path::new("."));
+    let base_dir = if exe_dir.ends_with("deps") {
+        exe_dir.parent().unwrap_or(exe_dir)
+    } else {
+        exe_dir
+    };
+    let mut path = base_dir.join(name);
+    if cfg!(windows) {
+        path.as_mut_os_string().push(".exe");
+    }
+    Ok(path)
+}
+
+/// Start persisting sidecar output under `log_dir`, unless already doing so.
+fn open_log_file(app_handle: &AppHandle, log_dir: &Path) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let Ok(log_file) = state.lock().map(|s| s.log_file.clone()) else {
+        return;
+    };
+    if log_file.is_open() {
+        return;
+    }
+    let path = logs::session_log_path(log_dir, chrono::Local::now());
+    match LogFile::open(&path, app_handle.state::<SidecarConfig>().log_rotation) {
+        Ok(file) => log_file.install(file),
+        Err(e) => warn!("Could not open sidecar log file {}: {e}", path.display()),
+    }
+}
+
+/// Run the session without a sidecar. The app dirs are still resolved so app
+/// logs reach the log file.
+fn disable_sidecar(app_handle: &AppHandle) {
+    match paths::resolve_app_dirs(app_handle) {
+        Ok(dirs) => {
+            open_log_file(app_handle, &dirs.logs);
+            app_handle.manage(dirs);
+        }
+        Err(e) => warn!("{e}"),
+    }
+    if let Ok(mut s) = app_handle.state::<Mutex<SidecarState>>().lock() {
+        s.transition(SidecarStatus::Disabled, "disabled for this session");
+    }
+    log_lifecycle(app_handle, "Sidecar disabled for this session, not starting it");
+}
+
+/// Spawn the sidecar binary and wait for it to become healthy.
+/// In dev mode (debug builds unless `CLAUDETINI_MODE` says otherwise) we skip
+/// spawning and assume port 9876 (`CLAUDETINI_DEV_PORT`), unless a custom
+/// binary was given via `CLAUDETINI_SIDECAR_BIN`.
+///
+/// Returns the endpoint once the process is running (port 0 until a
+/// `--port 0` sidecar announces its own); health is verified in the
+/// background. Failures are also reported to the frontend as `sidecar-error`.
+fn spawn_sidecar(app_handle: &AppHandle) -> Result<SidecarEndpoint, SidecarError> {
+    start_sidecar(app_handle).inspect_err(|e| report_sidecar_error(app_handle, e.clone()))
+}
+
+#[instrument(name = "spawn", skip_all)]
+fn start_sidecar(app_handle: &AppHandle) -> Result<SidecarEndpoint, SidecarError> {
+    // Log files and other per-app state land here; fail loudly up front rather
+    // than with a cryptic error later on a locked-down machine.
+    let dirs = paths::resolve_app_dirs(app_handle).map_err(|e| SidecarError::AppDirs(Arc::new(e)))?;
+    info!("App data dir: {}, log dir: {}", dirs.data.display(), dirs.logs.display());
+    let data_dir = dirs.data.clone();
+    let last_port_path = ports::last_port_path(&dirs.config);
+    open_log_file(app_handle, &dirs.logs);
+    // Only the first spawn registers; restarts resolve the same paths.
+    app_handle.manage(dirs);
+
+    let custom_binary = app_handle.state::<SidecarConfig>().custom_binary.clone();
+    if let Some(path) = &custom_binary {
+        validate_sidecar_binary(path)?;
+        info!("Using custom sidecar binary {}", path.display());
+    }
+
+    if uses_external_sidecar(app_handle) {
+        // Dev mode: sidecar runs externally on the dev port.
+        let port = app_handle.state::<SidecarConfig>().dev_port;
+        info!("Dev mode: assuming sidecar on port {port}");
+        mark_startup(app_handle, Milestone::PortAcquired);
+        let endpoint = SidecarEndpoint::tcp(&app_handle.state::<SidecarConfig>().host, port);
+
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let generation = match state.lock() {
+            Ok(mut s) => {
+                s.endpoint = Some(endpoint.clone());
+                s.transition(SidecarStatus::Starting, "connecting to the dev sidecar");
+                s.generation += 1;
+                s.log_file.set_generation(s.generation);
+                s.generation
+            }
+            Err(_) => return Err(SidecarError::LockPoisoned),
+        };
+        let assigned = SidecarPortAssignedPayload::new(&endpoint);
+        let _ = app_handle.emit("sidecar-port-assigned", assigned);
+
+        // Poll aggressively: the external sidecar is usually already running.
+        let config = app_handle.state::<SidecarConfig>();
+        let (deadline, backoff) = (config.startup_timeout, config.dev_startup_backoff);
+        let probes = config.startup_probes();
+        let prefer_ipv6 = config.prefer_ipv6;
+        let health_timeout = config.health_timeout;
+
+        let handle = app_handle.clone();
+        let spawned = endpoint.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut attempts = 0;
+            let record = |r| {
+                attempts += 1;
+                record_health(&handle, r);
+            };
+            mark_startup(&handle, Milestone::FirstHealthAttempt);
+            let polling = Instant::now();
+            let result =
+                poll_health(&endpoint, prefer_ipv6, &probes, None, deadline, backoff, record)
+                    .instrument(info_span!("health_poll", %endpoint))
+                    .await;
+            match result {
+                Ok((endpoint, checks)) => {
+                    // Another dev server on the port may well answer the health path.
+                    if let Err(e) = health::verify_identity(&endpoint, None, health_timeout).await {
+                        warn!(
+                            "Port {port} isn't serving the claudetini sidecar. Stop whatever \
+                             holds it, or set CLAUDETINI_DEV_PORT to your sidecar's port."
+                        );
+                        report_sidecar_error(&handle, e);
+                        return;
+                    }
+                    ready_grace(&handle).await;
+                    {
+                        let state = handle.state::<Mutex<SidecarState>>();
+                        if let Ok(mut s) = state.lock() {
+                            // Keep using whichever IP family answered.
+                            s.endpoint = Some(endpoint.clone());
+                            s.transition(SidecarStatus::Ready, "dev sidecar healthy");
+                            s.started_at = Some(Instant::now());
+                            s.checks = checks.clone();
+                        };
+                    }
+                    let payload = SidecarReadyPayload::new(&endpoint, checks, None, None);
+                    announce_ready(&handle, generation, payload);
+                    report_slow_start(&handle, attempts, polling.elapsed());
+                    monitor_health(&handle, generation).await;
+                }
+                Err(e) => {
+                    warn!("Dev sidecar not reachable on port {port} -- frontend will retry");
+                    store_checks(&handle, &e.checks);
+                    let error = e.to_string();
+                    let status = SidecarStatus::Failed {
+                        error: error.clone(),
+                    };
+                    set_status(&handle, status, &error);
+                }
+            }
+        });
+        return Ok(spawned);
+    }
+
+    // Release mode: find a free port (or socket path), spawn the bundled binary
+    // via Tauri shell plugin. Make up to `spawn_attempts` tries
+    // (`CLAUDETINI_SPAWN_ATTEMPTS`, 3 by default) to handle TOCTOU races where
+    // the port gets claimed between find_free_port() and the sidecar binding to it.
+    let transport = app_handle.state::<SidecarConfig>().transport;
+    let host = app_handle.state::<SidecarConfig>().host.clone();
+    // A retry after a startup timeout deliberately moves off the fixed port,
+    // in case whatever answered there wasn't our sidecar.
+    let retrying = app_handle
+        .state::<Mutex<SidecarState>>()
+        .lock()
+        .is_ok_and(|s| s.startup_retries > 0);
+    let requested_port = app_handle
+        .state::<SidecarConfig>()
+        .port
+        .filter(|_| matches!(transport, Transport::Tcp) && !retrying);
+    // The last known good port is only a preference: tried quietly first, and
+    // skipped on a timeout retry just like a fixed port.
+    let config = app_handle.state::<SidecarConfig>();
+    let last_port = (config.remember_port
+        && matches!(transport, Transport::Tcp)
+        && requested_port.is_none()
+        && !retrying)
+        .then(|| ports::read_last_port(&last_port_path))
+        .flatten()
+        .filter(|&p| config.port_range.is_none_or(|r| (r.min..=r.max).contains(&p)));
+    let (extra_args, sidecar_log_level) = app_handle
+        .state::<Mutex<SidecarState>>()
+        .lock()
+        .map(|s| (s.extra_args.clone(), s.sidecar_log_level))
+        .unwrap_or_default();
+    validate_extra_args(&extra_args)?;
+    // Only the listen address widens: health checks and the app keep using
+    // loopback, and every spawn carries an auth token.
+    let lan_requested = app_handle
+        .state::<Mutex<SidecarState>>()
+        .lock()
+        .is_ok_and(|s| s.bind_lan);
+    let bind_lan = lan_requested && lan_binding_applies(app_handle);
+    if lan_requested && !bind_lan {
+        warn!("Ignoring LAN binding: it only applies to TCP on {LOOPBACK_HOST}");
+    }
+    let lan_args = if bind_lan {
+        vec!["--host", transport::LAN_BIND_HOST]
+    } else {
+        Vec::new()
+    };
+    let certificate = sidecar_certificate(app_handle, &data_dir)?;
+
+    // Let the sidecar pick its own port and tell us, rather than reserving one.
+    // It can't be held to a port range, so a range turns the handshake off.
+    let handshake = matches!(transport, Transport::Tcp)
+        && requested_port.is_none()
+        && last_port.is_none()
+        && config.port_range.is_none()
+        && config.port_handshake;
+
+    let attempts = app_handle.state::<SidecarConfig>().spawn_attempts;
+    // Why the last port reservation failed, reported if no attempt got as far
+    // as spawning (an excluded range on Windows, an exhausted `port_range`).
+    let mut port_error = None;
+    let mut reached_spawn = false;
+    for attempt in 1..=attempts {
+        let (endpoint, reservation) = match transport {
+            Transport::Tcp if handshake => (SidecarEndpoint::tcp(&host, 0), Vec::new()),
+            Transport::Tcp => match reserve_port(app_handle, &host, requested_port, last_port) {
+                Ok((p, listeners)) => (SidecarEndpoint::tcp(&host, p), listeners),
+                Err(e) => {
+                    warn!("Could not find free port (attempt {attempt}): {e}");
+                    port_error = Some(e);
+                    continue;
+                }
+            },
+            Transport::Socket => {
+                let endpoint = transport::socket_endpoint(&data_dir).map_err(SidecarError::Socket)?;
+                (endpoint, Vec::new())
+            }
+        };
+        // Each child gets its own pin, so falling back to plain HTTP for an
+        // older sidecar never outlives it.
+        let endpoint = match &certificate {
+            Some(certificate) => endpoint.with_tls(Arc::new(tls::Pin::new(certificate))),
+            None => endpoint,
+        };
+
+        // With the handshake, the port is assigned once the sidecar announces it.
+        if !handshake {
+            mark_startup(app_handle, Milestone::PortAcquired);
+            let assigned = SidecarPortAssignedPayload::new(&endpoint);
+            let _ = app_handle.emit("sidecar-port-assigned", assigned);
+        }
+        log_port_retry(app_handle, &endpoint);
+        log_lifecycle(app_handle, &format!("Spawning sidecar on {endpoint} (attempt {attempt})"));
+
+        let token = SidecarToken::generate().map_err(|e| SidecarError::Token(e.to_string()))?;
+
+        // Use the Tauri shell plugin's sidecar API, which handles path resolution
+        // and target-triple binary naming automatically. A custom binary is
+        // spawned by exact path instead, bypassing bundle resolution.
+        let sidecar_command = match &custom_binary {
+            Some(path) => Ok(app_handle.shell().command(path)),
+            None => app_handle.shell().sidecar(&app_handle.state::<SidecarConfig>().sidecar_name),
+        };
+        let sidecar_command = sidecar_command
+            .map_err(|e| SidecarError::Command(e.to_string()))?
+            .args(endpoint.listen_args())
+            .args(&lan_args)
+            .args(&extra_args)
+            .envs(app_handle.state::<SidecarConfig>().env.clone())
+            .envs(sidecar_log_level.map(|l| (logging::SIDECAR_LEVEL_ENV, l.as_str())))
+            .envs(certificate.iter().flat_map(|c| {
+                [(tls::CERT_ENV, &c.cert_path), (tls::KEY_ENV, &c.key_path)]
+            }))
+            .env(auth::TOKEN_ENV, token.as_str());
+
+        // Release the port as late as possible to shrink the window in which
+        // another process can take it; a loss is caught by is_port_conflict.
+        drop(reservation);
+        // Startup time is measured from here on every run.
+        let spawned_at = Instant::now();
+        reached_spawn = true;
+        match sidecar_command.spawn() {
+            Ok((rx, child)) => {
+                mark_startup(app_handle, Milestone::Spawned);
+                let pid = child.pid();
+                let message = format!("Sidecar process {pid} spawned, polling health");
+                log_lifecycle(app_handle, &message);
+
+                // Store the child handle in managed state so it lives for the
+                // app's lifetime and can be killed on shutdown.
+                let (exit_tx, exit_rx) = oneshot::channel();
+                let (port_tx, port_rx) = if handshake {
+                    let (tx, rx) = oneshot::channel();
+                    (Some(tx), Some(rx))
+                } else {
+                    (None, None)
+                };
+                let ready_signal = app_handle.state::<SidecarConfig>().ready_signal;
+                let (ready_tx, ready_rx) = if ready_signal.wants_line() {
+                    let (tx, rx) = oneshot::channel();
+                    (Some(tx), Some(rx))
+                } else {
+                    (None, None)
+                };
+                let state = app_handle.state::<Mutex<SidecarState>>();
+                let generation = match state.lock() {
+                    Ok(mut s) => {
+                        s.endpoint = Some(endpoint.clone());
+                        s.child = Some(child);
+                        s.exited = Some(exit_rx);
+                        s.port_announced = port_tx;
+                        s.ready_line = ready_tx;
+                        s.generation += 1;
+                        s.log_file.set_generation(s.generation);
+                        // Separate this run's output from the previous one's.
+                        if !s.recent_logs.is_empty() {
+                            let marker = format!("----- sidecar run {} -----", s.generation);
+                            s.push_log("supervisor", marker);
+                        }
+                        s.token = Some(token.clone());
+                        s.last_pong = 0;
+                        s.resources.clear();
+                        s.last_latency_ms = None;
+                        if let Err(e) = s.forward_log_level() {
+                            warn!("Could not send the log level to the sidecar: {e}");
+                        }
+                        s.startup_stderr.clear();
+                        s.stop_requested = false;
+                        s.transition(SidecarStatus::Starting, "spawned");
+                        s.started_at = Some(Instant::now());
+                        s.generation
+                    }
+                    Err(_) => return Err(SidecarError::LockPoisoned),
+                };
+
+                // Non-fatal: a sidecar at normal priority still works.
+                if let Some(niceness) = app_handle.state::<SidecarConfig>().niceness {
+                    if let Err(e) = priority::lower(pid, niceness) {
+                        warn!("Could not lower sidecar priority (pid {pid}): {e}");
+                    }
+                }
+
+                // Consume the event receiver in a background task to keep the
+                // channel alive and log sidecar output.
+                let handle = app_handle.clone();
+                let (stop_tx, stop_rx) = oneshot::channel();
+                let task = tauri::async_runtime::spawn(async move {
+                    drain_sidecar_events(&handle, rx, generation, exit_tx, stop_rx).await;
+                });
+                if let Ok(mut s) = state.lock() {
+                    if s.generation == generation {
+                        s.drain = Some(DrainTask {
+                            stop: stop_tx,
+                            task,
+                        });
+                    }
+                }
+
+                // Poll health in the background, emit the event, then keep
+                // monitoring so a wedged sidecar gets restarted.
+                let config = app_handle.state::<SidecarConfig>();
+                let (deadline, backoff) = (config.startup_timeout, config.startup_backoff);
+                let probes = config.startup_probes();
+                let prefer_ipv6 = config.prefer_ipv6;
+                let initial_delay = if handshake {
+                    Duration::ZERO
+                } else {
+                    config.health_initial_delay
+                };
+
+                let handle = app_handle.clone();
+                let spawned = endpoint.clone();
+                tauri::async_runtime::spawn(async move {
+                    let started = Instant::now();
+                    let endpoint = match port_rx {
+                        None => endpoint,
+                        Some(port_rx) => match tokio::time::timeout(deadline, port_rx).await {
+                            Ok(Ok(port)) => endpoint.with_port(port),
+                            // The process exited or was replaced before announcing.
+                            Ok(Err(_)) => return,
+                            Err(_) => {
+                                if is_current(&handle, generation) {
+                                    let after_ms = deadline.as_millis();
+                                    let error = SidecarError::PortNotAnnounced { after_ms };
+                                    retry_after_startup_timeout(&handle, error.into()).await;
+                                }
+                                return;
+                            }
+                        },
+                    };
+                    let deadline = deadline.saturating_sub(started.elapsed());
+                    let mut attempts = 0;
+                    let record = |r| {
+                        attempts += 1;
+                        record_health(&handle, r);
+                    };
+                    // Give the process a moment to bind before the first check.
+                    let initial_delay = initial_delay.min(deadline);
+                    let health = async {
+                        tokio::time::sleep(initial_delay).await;
+                        mark_startup(&handle, Milestone::FirstHealthAttempt);
+                        poll_health(
+                            &endpoint,
+                            prefer_ipv6,
+                            &probes,
+                            Some(&token),
+                            deadline - initial_delay,
+                            backoff,
+                            record,
+                        )
+                        .await
+                    }
+                    .instrument(info_span!("health_poll", generation, %endpoint));
+                    let result =
+                        await_ready(ready_signal, health, ready_rx, &endpoint, deadline).await;
+                    let startup = spawned_at.elapsed();
+                    if result.is_ok() {
+                        ready_grace(&handle).await;
+                    }
+                    // A port-conflict retry or restart may have replaced this process.
+                    if !is_current(&handle, generation) {
+                        return;
+                    }
+                    match result {
+                        Ok((endpoint, checks)) => {
+                            store_checks(&handle, &checks);
+                            // Whatever the first checks spoke is what this child speaks.
+                            if let Some(pin) = endpoint.tls() {
+                                pin.settle();
+                                if pin.is_plain() {
+                                    warn!("The sidecar doesn't serve HTTPS; using plain HTTP");
+                                }
+                            }
+                            if let Ok(mut s) = handle.state::<Mutex<SidecarState>>().lock() {
+                                // Keep using whichever IP family answered.
+                                s.endpoint = Some(endpoint.clone());
+                                s.transition(SidecarStatus::Ready, "health check passed");
+                                s.port_retries = 0;
+                                s.startup_retries = 0;
+                                s.startup_stderr = Vec::new();
+                            };
+                            let message = format!(
+                                "Sidecar ready on {endpoint} ({}ms after spawn)",
+                                startup.as_millis()
+                            );
+                            log_lifecycle(&handle, &message);
+                            if let Some(port) = endpoint.port() {
+                                remember_port(&handle, port);
+                            }
+                            write_discovery_file(&handle);
+                            let payload = SidecarReadyPayload::new(
+                                &endpoint,
+                                checks,
+                                requested_port,
+                                Some(startup),
+                            );
+                            announce_ready(&handle, generation, payload);
+                            report_slow_start(&handle, attempts, startup);
+                            if let Some(port) = endpoint.port().filter(|_| bind_lan) {
+                                warn_lan_exposed(&handle, endpoint.url().scheme, port);
+                            }
+                            if handle.state::<SidecarConfig>().heartbeat {
+                                let handle = handle.clone();
+                                tauri::async_runtime::spawn(async move {
+                                    heartbeat(&handle, generation).await;
+                                });
+                            }
+                            if handle.state::<SidecarConfig>().resource_sampling {
+                                let handle = handle.clone();
+                                tauri::async_runtime::spawn(async move {
+                                    sample_resources(&handle, generation).await;
+                                });
+                            }
+                            monitor_health(&handle, generation).await;
+                        }
+                        Err(e) => {
+                            check_blocked(&handle, &endpoint).await;
+                            retry_after_startup_timeout(&handle, e).await;
+                        }
+                    }
+                });
+
+                // Spawn succeeded — break out of the retry loop.
+                return Ok(spawned);
+            }
+            Err(e) => {
+                warn!("Failed to spawn sidecar (attempt {attempt}): {e}");
+            }
+        }
+    }
+
+    match port_error {
+        Some(e) if !reached_spawn => Err(e),
+        _ => Err(SidecarError::Spawn { attempts }),
+    }
+}
+
+/// Flag a sidecar that came up healthy but needed more than
+/// `slow_start_attempts` health checks to get there, which points at
+/// something on the machine slowing startup down.
+fn report_slow_start(app_handle: &AppHandle, attempts: u32, elapsed: Duration) {
+    if attempts <= app_handle.state::<SidecarConfig>().slow_start_attempts {
+        return;
+    }
+    let elapsed_ms = elapsed.as_millis() as u64;
+    warn!("Sidecar needed {attempts} health checks ({elapsed_ms}ms) to start");
+    let payload = SidecarSlowStartPayload {
+        attempts,
+        elapsed_ms,
+    };
+    let _ = app_handle.emit("sidecar-slow-start", payload);
+}
+
+/// Wait for what `signal` says makes a spawned sidecar ready: the startup
+/// `health` poll, the `READY` line reported through `line`, or both, all
+/// within `deadline`. Ready on the line alone means no dependency checks.
+async fn await_ready(
+    signal: ReadySignal,
+    health: impl Future<Output = Result<(SidecarEndpoint, Vec<DependencyCheck>), ProbeError>>,
+    line: Option<oneshot::Receiver<()>>,
+    endpoint: &SidecarEndpoint,
+    deadline: Duration,
+) -> Result<(SidecarEndpoint, Vec<DependencyCheck>), ProbeError> {
+    let line = async {
+        // A child that exits first is reported by the drain loop; run out
+        // the clock like the health poll does.
+        let printed = async {
+            if let Some(line) = line {
+                if line.await.is_ok() {
+                    return;
+                }
+            }
+            std::future::pending::<()>().await
+        };
+        let after_ms = deadline.as_millis();
+        tokio::time::timeout(deadline, printed)
+            .await
+            .map_err(|_| ProbeError::from(SidecarError::ReadyLineMissing { after_ms }))
+    };
+    let line_only = || (endpoint.clone(), Vec::new());
+    match signal {
+        ReadySignal::Health => health.await,
+        ReadySignal::Line => line.await.map(|()| line_only()),
+        ReadySignal::Both => tokio::try_join!(health, line).map(|(ready, ())| ready),
+        ReadySignal::Either => {
+            tokio::pin!(health, line);
+            tokio::select! {
+                ready = &mut health => match ready {
+                    Ok(ready) => Ok(ready),
+                    Err(e) => line.await.map(|()| line_only()).map_err(|_| e),
+                },
+                printed = &mut line => match printed {
+                    Ok(()) => Ok(line_only()),
+                    Err(_) => health.await,
+                },
+            }
+        }
+    }
+}
+
+/// Relay the sidecar's server-sent events as `sidecar-event` for as long as the
+/// app runs, reconnecting with backoff whenever the stream drops, restarts
+/// included. Idles while disabled or while no sidecar is ready.
+async fn relay_events(app_handle: AppHandle) {
+    const IDLE_POLL: Duration = Duration::from_millis(500);
+    let config = app_handle.state::<SidecarConfig>();
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let mut enabled = app_handle.state::<EventRelay>().subscribe();
+    let mut timer = TokioTimer::new();
+    let mut failures = 0u32;
+    let mut last_error = None;
+    // Generation known not to serve a stream, so we don't keep asking it.
+    let mut unsupported = None;
+
+    loop {
+        if enabled.wait_for(|on| *on).await.is_err() {
+            return;
+        }
+        let target = match state.lock() {
+            Ok(s) if s.ready_url().is_some() && unsupported != Some(s.generation) => {
+                s.endpoint.clone().map(|e| (e, s.token.clone(), s.generation))
+            }
+            Ok(_) => None,
+            Err(_) => return,
+        };
+        let Some((endpoint, token, generation)) = target else {
+            tokio::time::sleep(IDLE_POLL).await;
+            continue;
+        };
+
+        let emit = |event| {
+            let _ = app_handle.emit("sidecar-event", event);
+        };
+        let keep_going = || *enabled.borrow() && is_current(&app_handle, generation);
+        let path = &config.event_stream_path;
+        match relay::stream(&endpoint, path, token.as_ref(), emit, keep_going).await {
+            Ok(()) => {
+                failures = 0;
+                last_error = None;
+            }
+            Err(StreamError::NotFound) => {
+                info!("Sidecar serves no event stream at {path}; relay idle until restart");
+                unsupported = Some(generation);
+                continue;
+            }
+            Err(StreamError::Failed(e)) => {
+                failures += 1;
+                // Log each distinct failure once rather than on every retry.
+                if last_error.as_ref() != Some(&e) {
+                    warn!("Sidecar event relay: {e}; reconnecting");
+                    last_error = Some(e);
+                }
+            }
+        }
+        let delay = relay::RECONNECT_BACKOFF.delay(failures.max(1), timer.jitter_sample());
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Hold back `sidecar-ready` for the configured grace period after the first
+/// passing health check.
+async fn ready_grace(app_handle: &AppHandle) {
+    let grace = app_handle.state::<SidecarConfig>().ready_grace_period;
+    if !grace.is_zero() {
+        tokio::time::sleep(grace).await;
+    }
+}
+
+/// Echo sidecar output to the console. Structured lines go by their level, so
+/// a `sidecar=warn` filter still shows a warning logged on stdout.
+fn echo_sidecar_line(entry: &LogLine) {
+    let level = entry.severity();
+    let text = match entry.level {
+        Some(level) => format!("[sidecar] {}: {}", level.as_str(), entry.message()),
+        None => format!("[sidecar] {}", entry.line),
+    };
+    match level {
+        LogLevel::Trace => trace!(target: SIDECAR_TARGET, "{text}"),
+        LogLevel::Debug => debug!(target: SIDECAR_TARGET, "{text}"),
+        LogLevel::Info => info!(target: SIDECAR_TARGET, "{text}"),
+        LogLevel::Warn => warn!(target: SIDECAR_TARGET, "{text}"),
+        LogLevel::Error => error!(target: SIDECAR_TARGET, "{text}"),
+    }
+}
+
+/// Send queued output to log subscribers every `FLUSH_INTERVAL`, or sooner
+/// once a batch fills up, until the last subscriber leaves.
+async fn stream_log_batches(app_handle: AppHandle, full: Arc<Notify>) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    loop {
+        let _ = tokio::time::timeout(logstream::FLUSH_INTERVAL, full.notified()).await;
+        let Some(batches) = state.lock().ok().and_then(|mut s| s.log_stream.take_batches()) else {
+            return;
+        };
+        let mut closed = Vec::new();
+        for (id, sink, batch) in batches {
+            match sink {
+                LogSink::Channel(channel) => {
+                    if channel.send(batch).is_err() {
+                        closed.push(id);
+                    }
+                }
+                LogSink::Event => {
+                    let _ = app_handle.emit("sidecar-log", batch);
+                }
+            }
+        }
+        if let (false, Ok(mut s)) = (closed.is_empty(), state.lock()) {
+            closed.into_iter().for_each(|id| s.log_stream.unsubscribe(id));
+        }
+        tokio::time::sleep(logstream::MIN_FLUSH_GAP).await;
+    }
+}
+
+/// Handle one complete line of sidecar stdout: pongs, port announcements and
+/// port conflicts, and the log.
+fn handle_stdout_line(app_handle: &AppHandle, generation: u64, line: Line) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let pong = line.text.strip_prefix("pong ").and_then(|n| n.trim_end().parse::<u64>().ok());
+    if let Some(seq) = pong {
+        if let Ok(mut s) = state.lock() {
+            if s.generation == generation {
+                s.last_pong = s.last_pong.max(seq);
+            }
+        }
+        return;
+    }
+    if let Some((seq, result)) = reload::parse_ack(&line.text) {
+        if let Ok(mut s) = state.lock() {
+            let waiting = s.pending_reload.as_ref().is_some_and(|(n, _)| *n == seq);
+            if s.generation == generation && waiting {
+                if let Some((_, tx)) = s.pending_reload.take() {
+                    let _ = tx.send(result);
+                }
+            }
+        }
+    }
+    let mut assigned = None;
+    if let Ok(mut s) = state.lock() {
+        let entry = s.push_output("stdout", line);
+        echo_sidecar_line(&entry);
+        let line = entry.line;
+        if let Some(port) = announced_port(&line) {
+            assigned = s.accept_announced_port(generation, port);
+        }
+        if is_ready_line(&line) {
+            s.accept_ready_line(generation);
+        }
+        // Bound somewhere other than where we'll probe: whatever answers on
+        // our port isn't this sidecar. Port 0 means it hasn't announced its
+        // own port yet.
+        let expected = s.endpoint.as_ref().and_then(SidecarEndpoint::port);
+        let moved =
+            listening_port(&line).is_some_and(|p| Some(p) != expected && expected != Some(0));
+        if (moved || is_port_conflict(&line)) && s.claim_port_conflict(generation) {
+            let handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                retry_after_port_conflict(&handle).await;
+            });
+        }
+    }
+    if let Some(assigned) = assigned {
+        let _ = app_handle.emit("sidecar-port-assigned", assigned);
+    }
+}
+
+/// Handle one complete line of sidecar stderr.
+fn handle_stderr_line(app_handle: &AppHandle, generation: u64, line: Line) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let Ok(mut s) = state.lock() else {
+        return;
+    };
+    let entry = s.push_output("stderr", line);
+    echo_sidecar_line(&entry);
+    let line = entry.line;
+    s.capture_startup_stderr(generation, &line);
+    if is_port_conflict(&line) && s.claim_port_conflict(generation) {
+        let handle = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            retry_after_port_conflict(&handle).await;
+        });
+    }
+}
+
+/// Read sidecar stdout/stderr and log it. Runs until the process terminates,
+/// then hands an unexpected exit to the restart supervisor, or until `stop`
+/// fires on app exit. Output arrives in chunks that needn't end on a line, so
+/// each stream is reassembled into lines first.
+async fn drain_sidecar_events(
+    app_handle: &AppHandle,
+    mut rx: tokio::sync::mpsc::Receiver<CommandEvent>,
+    generation: u64,
+    exited: oneshot::Sender<()>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let max_line = app_handle.state::<SidecarConfig>().log_max_line_bytes;
+    let mut stdout = LineBuffer::new(max_line);
+    let mut stderr = LineBuffer::new(max_line);
+    loop {
+        let event = tokio::select! {
+            // Output that already arrived is written before stopping.
+            biased;
+            event = rx.recv() => event,
+            Ok(()) = &mut stop => {
+                if let Some(line) = stdout.finish() {
+                    handle_stdout_line(app_handle, generation, line);
+                }
+                if let Some(line) = stderr.finish() {
+                    handle_stderr_line(app_handle, generation, line);
+                }
+                if let Ok(mut s) = state.lock() {
+                    s.flush_log();
+                }
+                None
+            }
+        };
+        let Some(event) = event else { break };
+        match event {
+            CommandEvent::Stdout(chunk) => {
+                for line in stdout.push(&chunk) {
+                    handle_stdout_line(app_handle, generation, line);
+                }
+            }
+            CommandEvent::Stderr(chunk) => {
+                for line in stderr.push(&chunk) {
+                    handle_stderr_line(app_handle, generation, line);
+                }
+            }
+            CommandEvent::Terminated(payload) => {
+                // Whatever the sidecar printed last, without a newline.
+                if let Some(line) = stdout.finish() {
+                    handle_stdout_line(app_handle, generation, line);
+                }
+                if let Some(line) = stderr.finish() {
+                    handle_stderr_line(app_handle, generation, line);
+                }
+                let (code, signal) = (payload.code, payload.signal);
+                let message = format!("Sidecar terminated: code={code:?} signal={signal:?}");
+                log_lifecycle(app_handle, &message);
+                let mut startup_stderr = None;
+                let crashed = match state.lock() {
+                    Ok(mut s) if s.generation == generation => {
+                        if matches!(s.status, SidecarStatus::Starting) && !s.stop_requested {
+                            startup_stderr = Some(std::mem::take(&mut s.startup_stderr));
+                        }
+                        s.last_exit = Some(ExitInfo {
+                            code: payload.code,
+                            signal: payload.signal,
+                            at_unix_ms: now_unix_ms(),
+                        });
+                        s.started_at = None;
+                        // The process is gone; drop the handle so nobody signals a reused pid.
+                        s.child = None;
+                        s.exited = None;
+                        s.drain = None;
+                        s.remove_discovery_file();
+                        s.port_announced = None;
+                        s.ready_line = None;
+                        s.pending_reload = None;
+                        s.flush_log();
+                        // A child claimed by claim_port_conflict may exit before we stop it.
+                        if s.stop_requested || matches!(s.status, SidecarStatus::Restarting) {
+                            if !matches!(
+                                s.status,
+                                SidecarStatus::Failed { .. }
+                                    | SidecarStatus::Degraded { .. }
+                                    | SidecarStatus::Restarting
+                            ) {
+                                s.transition(SidecarStatus::Stopped, "exited");
+                            }
+                            false
+                        } else {
+                            true
+                        }
+                    }
+                    _ => false,
+                };
+                let _ = exited.send(());
+                if let Some(stderr) = startup_stderr {
+                    report_early_exit(app_handle, code, signal, stderr);
+                }
+                if crashed {
+                    let reason = format!(
+                        "Sidecar exited unexpectedly (code={:?} signal={:?})",
+                        payload.code, payload.signal
+                    );
+                    auto_restart_sidecar(app_handle, reason).await;
+                }
+                break;
+            }
+            CommandEvent::Error(msg) => {
+                if let Ok(mut s) = state.lock() {
+                    let msg = s.redact(msg);
+                    error!(target: SIDECAR_TARGET, "[sidecar] error: {msg}");
+                    s.push_log("error", msg);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Tauri command: return the current sidecar port (`None` if not yet
+/// assigned, or if the sidecar listens on a socket).
+#[tauri::command]
+fn get_sidecar_port(state: tauri::State<'_, Mutex<SidecarState>>) -> Option<u16> {
+    state
+        .lock()
+        .ok()
+        .and_then(|s| s.endpoint.as_ref().and_then(SidecarEndpoint::port))
+}
+
+/// Tauri command: describe whichever transport the sidecar is using.
+#[tauri::command]
+fn get_sidecar_endpoint(state: tauri::State<'_, Mutex<SidecarState>>) -> Option<SidecarEndpoint> {
+    state.lock().ok().and_then(|s| s.endpoint.clone())
+}
+
+/// Tauri command: the sidecar's base URL, or `None` until it has passed its
+/// startup health check so callers never get an address that isn't serving.
+#[tauri::command]
+fn get_sidecar_url(state: tauri::State<'_, Mutex<SidecarState>>) -> Option<SidecarUrl> {
+    state.lock().ok()?.ready_url()
+}
+
+/// Tauri command: the bearer token the running sidecar expects in
+/// `Authorization` headers. Changes with every restart; never logged.
+#[tauri::command]
+fn get_sidecar_token(state: tauri::State<'_, Mutex<SidecarState>>) -> Option<String> {
+    let s = state.lock().ok()?;
+    s.token.as_ref().map(|t| t.as_str().to_string())
+}
+
+/// Tauri command: URLs other devices on the LAN can use to reach the sidecar.
+/// Empty unless LAN binding is on and the sidecar is ready.
+#[tauri::command]
+fn get_lan_endpoints(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Vec<String> {
+    let url = state
+        .lock()
+        .ok()
+        .filter(|s| s.bind_lan)
+        .and_then(|s| s.ready_url());
+    match url.and_then(|url| Some((url.scheme, url.port?))) {
+        Some((scheme, port)) if lan_binding_applies(&app_handle) => lan_urls(scheme, port),
+        _ => Vec::new(),
+    }
+}
+
+/// Tauri command: everything external tooling docs need to reach the sidecar.
+/// The URL is `None` until ready; the discovery file path is where it will be
+/// written once the sidecar is ready.
+#[tauri::command]
+fn get_sidecar_connection_info(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<ConnectionInfo, SidecarError> {
+    let discovery_file = app_handle
+        .try_state::<AppDirs>()
+        .map(|dirs| discovery::discovery_path(&dirs.data));
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    let pin = s.ready_url().and(s.endpoint.as_ref()).and_then(SidecarEndpoint::tls);
+    Ok(ConnectionInfo {
+        url: s.ready_url(),
+        pid: s.child.as_ref().map(CommandChild::pid),
+        discovery_file,
+        tls_fingerprint: pin.and_then(tls::Pin::fingerprint).map(str::to_string),
+        tls_certificate: pin
+            .filter(|pin| !pin.is_plain())
+            .and(s.certificate.as_ref())
+            .map(|c| c.pem().to_string()),
+    })
+}
+
+/// Tauri command: forward an HTTP request to the sidecar with the auth token
+/// attached, for when the webview can't reach the sidecar itself. Bodies and
+/// responses are size-limited; this is not for streaming endpoints.
+#[tauri::command]
+async fn proxy_request(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    request: ProxyRequest,
+) -> Result<ProxyResponse, SidecarError> {
+    ensure_enabled(&app_handle)?;
+    let (endpoint, token) = {
+        let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        s.ready_url().ok_or(SidecarError::NotReady)?;
+        (s.endpoint.clone().ok_or(SidecarError::NotReady)?, s.token.clone())
+    };
+    proxy::forward(&endpoint, token.as_ref(), request).await
+}
+
+/// Tauri command: turn the `sidecar-event` relay on or off for this session,
+/// for frontends that would rather hold their own connection to the stream.
+#[tauri::command]
+fn set_event_relay_enabled(relay: tauri::State<'_, EventRelay>, enabled: bool) {
+    relay.set_enabled(enabled);
+}
+
+/// Tauri command: stream sidecar output in batches, to `channel` if given and
+/// otherwise as global `sidecar-log` events. A batch goes out every 250ms, or
+/// sooner once 50 lines queue up; lines that outrun the stream are dropped and
+/// counted in the next batch's `dropped`. Only lines at `min_level` or above
+/// that contain `matches` (a regex if `regex` is set) are sent. Returns an id
+/// for `set_sidecar_log_filter` and `unsubscribe_sidecar_logs`.
+#[tauri::command]
+fn subscribe_sidecar_logs(
+    app_handle: AppHandle,
+    webview: tauri::Webview<SidecarRuntime>,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    channel: Option<JavaScriptChannelId>,
+    min_level: Option<String>,
+    matches: Option<String>,
+    regex: Option<bool>,
+) -> Result<u32, SidecarError> {
+    let filter = line_filter(min_level, matches, regex)?;
+    let sink = match channel {
+        Some(id) => LogSink::Channel(id.channel_on::<_, LogBatch>(webview)),
+        None => LogSink::Event,
+    };
+    let (id, start, full) = {
+        let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        let (id, start) = s.log_stream.subscribe(sink, filter);
+        (id, start, s.log_stream.full.clone())
+    };
+    if start {
+        tauri::async_runtime::spawn(stream_log_batches(app_handle, full));
+    }
+    Ok(id)
+}
+
+/// Tauri command: replace the filter of a `subscribe_sidecar_logs`
+/// subscription, taking effect from its next batch. Returns whether the
+/// subscription still exists.
+#[tauri::command]
+fn set_sidecar_log_filter(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    id: u32,
+    min_level: Option<String>,
+    matches: Option<String>,
+    regex: Option<bool>,
+) -> Result<bool, SidecarError> {
+    let filter = line_filter(min_level, matches, regex)?;
+    let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(s.log_stream.set_filter(id, filter))
+}
+
+fn line_filter(
+    min_level: Option<String>,
+    matches: Option<String>,
+    regex: Option<bool>,
+) -> Result<LineFilter, SidecarError> {
+    let min_level = match min_level {
+        Some(level) => Some(LogLevel::parse(&level).ok_or_else(|| {
+            SidecarError::InvalidLogFilter(format!("unknown log level {level:?}"))
+        })?),
+        None => None,
+    };
+    LineFilter::new(min_level, matches, regex.unwrap_or(false))
+        .map_err(SidecarError::InvalidLogFilter)
+}
+
+/// Tauri command: end a `subscribe_sidecar_logs` subscription. Batching stops
+/// once nobody is subscribed.
+#[tauri::command]
+fn unsubscribe_sidecar_logs(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    id: u32,
+) -> Result<(), SidecarError> {
+    let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    s.log_stream.unsubscribe(id);
+    Ok(())
+}
+
+/// `get_sidecar_resources`: whether sampling is on and what it has found.
+#[derive(Serialize)]
+struct ResourcesReport {
+    enabled: bool,
+    /// The sampled child; `None` while none is running.
+    pid: Option<u32>,
+    /// Oldest first, current child only.
+    samples: Vec<ResourceSample>,
+}
+
+/// Tauri command: recent CPU, memory and open file samples of the sidecar,
+/// for a status bar readout. Empty with `resource_sampling` off.
+#[tauri::command]
+fn get_sidecar_resources(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    config: tauri::State<'_, SidecarConfig>,
+) -> Result<ResourcesReport, SidecarError> {
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(ResourcesReport {
+        enabled: config.resource_sampling,
+        pid: s.child.as_ref().map(CommandChild::pid),
+        samples: s.resources.samples(),
+    })
+}
+
+/// Tauri command: health check results, oldest first, optionally only those
+/// recorded at or after `since` (Unix milliseconds).
+#[tauri::command]
+fn get_health_history(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    since: Option<u64>,
+) -> Vec<HealthRecord> {
+    let since = since.unwrap_or(0);
+    state
+        .lock()
+        .map(|s| {
+            s.health_history
+                .iter()
+                .filter(|r| r.timestamp >= since)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Tauri command: the last `sidecar-error` sent this session, including the
+/// stderr and exit status of a sidecar that died while starting.
+#[tauri::command]
+fn get_last_sidecar_error(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<Option<SidecarErrorPayload>, SidecarError> {
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(s.last_error.clone())
+}
+
+/// `get_sidecar_status`: the status fields plus the current child and the
+/// watchdog's latest reading.
+#[derive(Serialize)]
+struct StatusReport {
+    #[serde(flatten)]
+    status: SidecarStatus,
+    /// The current or last child's port; `None` in socket mode or before one
+    /// is known.
+    port: Option<u16>,
+    /// `None` while no child is running.
+    pid: Option<u32>,
+    /// Since the current child last became ready.
+    uptime_ms: Option<u64>,
+    /// Automatic and explicit restarts this session.
+    restart_count: u32,
+    /// Round trip of the last passing background health check; `None`
+    /// before the first one on the current child.
+    last_latency_ms: Option<u64>,
+}
+
+/// Tauri command: the sidecar's lifecycle status, including why it failed,
+/// with its port, pid, uptime and restart count, and the latency of its last
+/// passing watchdog check, for spotting a slowdown before it turns into
+/// failures.
+#[tauri::command]
+fn get_sidecar_status(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<StatusReport, SidecarError> {
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(StatusReport {
+        status: s.status.clone(),
+        port: s.endpoint.as_ref().and_then(SidecarEndpoint::port),
+        pid: s.child.as_ref().map(CommandChild::pid),
+        uptime_ms: s.started_at.map(|t| t.elapsed().as_millis() as u64),
+        restart_count: s.restart_count,
+        last_latency_ms: s.last_latency_ms,
+    })
+}
+
+/// What `ping_sidecar` found.
+#[derive(Serialize)]
+struct PingResult {
+    healthy: bool,
+    /// Round trip of a passing check; `None` if it failed or couldn't run.
+    latency_ms: Option<u64>,
+}
+
+/// Tauri command: run one health check now, e.g. when the user clicks
+/// "reconnect", rather than waiting for the next watchdog tick. Updates the
+/// stored status like a watchdog check would, but doesn't count towards its
+/// failure threshold or move its schedule.
+#[tauri::command]
+async fn ping_sidecar(app_handle: AppHandle) -> Result<PingResult, SidecarError> {
+    ensure_enabled(&app_handle)?;
+    let config = app_handle.state::<SidecarConfig>();
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let target = {
+        let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        s.ready_url()
+            .and(s.endpoint.clone())
+            .map(|e| (e, s.token.clone(), s.generation))
+    };
+    let Some((endpoint, token, generation)) = target else {
+        return Ok(PingResult {
+            healthy: false,
+            latency_ms: None,
+        });
+    };
+
+    let started = Instant::now();
+    let probe = &config.health_probe;
+    let result = check_ready(&endpoint, probe, token.as_ref(), config.health_timeout).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let record = HealthRecord {
+        timestamp: now_unix_ms(),
+        ok: result.is_ok(),
+        latency_ms,
+        error: result.as_ref().err().map(ProbeError::to_string),
+    };
+    if let Ok(mut s) = state.lock() {
+        // Leave the status alone if the sidecar restarted or stopped meanwhile.
+        if s.generation == generation
+            && matches!(s.status, SidecarStatus::Ready | SidecarStatus::Unhealthy)
+        {
+            s.push_health(record);
+            match &result {
+                Ok(checks) => {
+                    s.transition(SidecarStatus::Ready, "health check passed");
+                    s.checks = checks.clone();
+                    s.last_latency_ms = Some(latency_ms);
+                }
+                Err(_) => s.transition(SidecarStatus::Unhealthy, "health check failed"),
+            }
+        }
+    };
+    match result {
+        Ok(_) => {
+            debug!("Manual ping answered in {latency_ms}ms");
+            Ok(PingResult {
+                healthy: true,
+                latency_ms: Some(latency_ms),
+            })
+        }
+        Err(e) => {
+            warn!("Manual ping failed: {e}");
+            Ok(PingResult {
+                healthy: false,
+                latency_ms: None,
+            })
+        }
+    }
+}
+
+/// Effective log levels, for `get_log_level`.
+#[derive(Serialize)]
+struct LogLevels {
+    /// The Rust-side filter directive, e.g. `debug` or `info,sidecar=warn`.
+    app: String,
+    /// The level last forwarded to the sidecar; `None` means its own default.
+    sidecar: Option<&'static str>,
+}
+
+/// Tauri command: set the log level for the app and the sidecar at runtime,
+/// e.g. when support asks for debug logs. Applied to the running child and
+/// every later one, and saved so it survives app restarts.
+#[tauri::command]
+fn set_log_level(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    level: String,
+) -> Result<LogLevels, SidecarError> {
+    let level: logging::LogLevel = level.parse().map_err(SidecarError::InvalidLogLevel)?;
+    let filter = logging::current_filter().unwrap_or_default().with_default(level);
+    logging::set_filter(&filter);
+    {
+        let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        s.sidecar_log_level = Some(level);
+        if let Err(e) = s.forward_log_level() {
+            warn!("Could not send the log level to the sidecar: {e}");
+        }
+    }
+    match paths::resolve_app_dirs(&app_handle) {
+        Ok(dirs) => {
+            if let Err(e) = logging::save_level(&dirs.config, level) {
+                warn!("Could not save log level {}: {e}", level.as_str());
+            }
+        }
+        Err(e) => warn!("Could not save log level {}: {e}", level.as_str()),
+    }
+    log_lifecycle(&app_handle, &format!("Log level set to {}", level.as_str()));
+    Ok(LogLevels {
+        app: filter.to_string(),
+        sidecar: Some(level.as_str()),
+    })
 }
 
-/// Payload emitted to the frontend when the sidecar is healthy.
-#[derive(Clone, Serialize)]
-struct SidecarReadyPayload {
-    port: u16,
+/// Tauri command: the log levels in effect for the app and the sidecar.
+#[tauri::command]
+fn get_log_level(state: tauri::State<'_, Mutex<SidecarState>>) -> Result<LogLevels, SidecarError> {
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(LogLevels {
+        app: logging::current_filter().unwrap_or_default().to_string(),
+        sidecar: s.sidecar_log_level.map(logging::LogLevel::as_str),
+    })
 }
 
-/// Bind to 127.0.0.1:0 and let the OS assign an available port.
-fn find_free_port() -> Result<u16, String> {
-    let listener =
-        TcpListener::bind("127.0.0.1:0").map_err(|e| format!("Failed to bind TCP: {e}"))?;
-    let port = listener
-        .local_addr()
-        .map_err(|e| format!("Failed to get local addr: {e}"))?
-        .port();
-    Ok(port)
+/// Tauri command: whether the app is running without a sidecar because it
+/// couldn't be started, so the UI can show limited mode and a retry button.
+#[tauri::command]
+fn is_sidecar_degraded(state: tauri::State<'_, Mutex<SidecarState>>) -> bool {
+    state.lock().is_ok_and(|s| matches!(s.status, SidecarStatus::Degraded { .. }))
 }
 
-/// Poll the sidecar health endpoint via raw TCP connect.
-/// We only check that a TCP connection succeeds (not full HTTP) to keep
-/// dependencies minimal on the Rust side.
-async fn poll_health(port: u16, max_attempts: u32) -> Result<(), String> {
-    use tokio::net::TcpStream;
-    use tokio::time::{sleep, Duration};
+/// Tauri command: whether the sidecar is healthy and `sidecar-ready` has been
+/// emitted for it, for guard code that doesn't need the full status.
+#[tauri::command]
+fn is_sidecar_ready(state: tauri::State<'_, Mutex<SidecarState>>) -> bool {
+    state.lock().is_ok_and(|s| s.announced_ready().is_some())
+}
 
-    for attempt in 1..=max_attempts {
-        match TcpStream::connect(format!("127.0.0.1:{port}")).await {
-            Ok(_) => {
-                println!("Sidecar healthy on port {port} (attempt {attempt}/{max_attempts})");
-                return Ok(());
+/// Tauri command: wait for the sidecar to be ready and return what
+/// `sidecar-ready` carried, at once if it already is. Doesn't rely on
+/// catching the event, so a listener attached late can't miss readiness.
+/// Fails early while disabled or once the supervisor has given up, and after
+/// `timeout_ms` (30s by default).
+#[tauri::command]
+async fn wait_for_sidecar_ready(
+    app_handle: AppHandle,
+    timeout_ms: Option<u64>,
+) -> Result<SidecarReadyPayload, SidecarError> {
+    let wait = timeout_ms.map_or(READY_WAIT_TIMEOUT, Duration::from_millis);
+    let deadline = tokio::time::Instant::now() + wait;
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    // Subscribed before the first check, so no change can slip in between.
+    let mut changed = state
+        .lock()
+        .map_err(|_| SidecarError::LockPoisoned)?
+        .ready_changed
+        .subscribe();
+    loop {
+        {
+            let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+            if let Some(payload) = s.announced_ready() {
+                return Ok(payload.clone());
             }
-            Err(_) => {
-                if attempt < max_attempts {
-                    sleep(Duration::from_millis(200)).await;
+            match s.status {
+                SidecarStatus::Disabled => return Err(SidecarError::Disabled),
+                SidecarStatus::Failed { .. } | SidecarStatus::Degraded { .. } => {
+                    return Err(SidecarError::NotRunning)
                 }
+                _ => {}
             }
         }
+        if !matches!(tokio::time::timeout_at(deadline, changed.changed()).await, Ok(Ok(()))) {
+            return Err(SidecarError::ReadyTimeout {
+                after_ms: wait.as_millis(),
+            });
+        }
     }
-    Err(format!(
-        "Sidecar failed to become healthy after {max_attempts} attempts on port {port}"
-    ))
 }
 
-/// Spawn the sidecar binary and wait for it to become healthy.
-/// In dev mode we skip spawning and assume port 9876.
-fn spawn_sidecar(app_handle: &AppHandle) {
-    if cfg!(debug_assertions) {
-        // Dev mode: sidecar runs externally on the default port.
-        let port: u16 = 9876;
-        println!("Dev mode: assuming sidecar on port {port}");
+/// Tauri command: how many times the sidecar was restarted this session,
+/// automatically or on request.
+#[tauri::command]
+fn get_restart_count(state: tauri::State<'_, Mutex<SidecarState>>) -> Result<u32, SidecarError> {
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(s.restart_count)
+}
 
-        let state = app_handle.state::<Mutex<SidecarState>>();
-        if let Ok(mut s) = state.lock() {
-            s.port = port;
-        }
+/// Tauri command: success ratio and latency percentiles over the last five
+/// minutes, plus session-wide restart and failure info.
+#[tauri::command]
+fn get_sidecar_metrics(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<SidecarMetrics, SidecarError> {
+    let (samples, restart_count) = {
+        let s = state
+            .lock()
+            .map_err(|_| SidecarError::LockPoisoned)?;
+        let samples: Vec<HealthSample> = s.health_history.iter().map(HealthSample::from).collect();
+        (samples, s.restart_count)
+    };
+    Ok(SidecarMetrics {
+        health: HealthStats::compute(&samples, now_unix_ms(), METRICS_WINDOW),
+        restart_count,
+    })
+}
 
-        let handle = app_handle.clone();
-        tauri::async_runtime::spawn(async move {
-            // Give the external sidecar a moment, then emit ready
-            if poll_health(port, 30).await.is_ok() {
-                let _ = handle.emit("sidecar-ready", SidecarReadyPayload { port });
-            } else {
-                eprintln!("Dev sidecar not reachable on port {port} -- frontend will retry");
-            }
-        });
-        return;
-    }
+/// Tauri command: the dependency checks the sidecar reported when it last
+/// answered a readiness probe.
+#[tauri::command]
+fn get_sidecar_checks(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<Vec<DependencyCheck>, SidecarError> {
+    let s = state
+        .lock()
+        .map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(s.checks.clone())
+}
 
-    // Release mode: find a free port, spawn the bundled binary via Tauri shell plugin.
-    // Retry up to 3 times to handle TOCTOU races where the port gets claimed
-    // between find_free_port() and the sidecar binding to it.
-    for attempt in 1..=3u32 {
-        let port = match find_free_port() {
-            Ok(p) => p,
-            Err(e) => {
-                eprintln!("Could not find free port (attempt {attempt}): {e}");
-                continue;
-            }
-        };
+/// Tauri command: the log files of this and earlier sessions, newest first,
+/// with which one is being written to.
+#[tauri::command]
+fn list_log_files(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<Vec<LogFileInfo>, SidecarError> {
+    let active = current_log_path(&app_handle, &state).ok();
+    logs::list_files(&log_dir(&app_handle)?, active.as_deref())
+        .map_err(|e| SidecarError::LogFile(e.into()))
+}
 
-        println!("Spawning sidecar on port {port} (attempt {attempt})");
+/// Tauri command: the end of a log file from `list_log_files`, by default
+/// this session's: its last `tail_lines` lines if given, and never more
+/// than `max_bytes` (256 KiB by default, at most 16 MiB). Comes with its
+/// path so the UI can reveal it.
+#[tauri::command]
+fn read_log_file(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    name: Option<String>,
+    tail_lines: Option<usize>,
+    max_bytes: Option<u64>,
+) -> Result<LogTail, SidecarError> {
+    let path = match name {
+        Some(name) => logs::resolve_log_file(&log_dir(&app_handle)?, &name)
+            .ok_or(SidecarError::UnknownLogFile(name))?,
+        None => current_log_path(&app_handle, &state)?,
+    };
+    let max_bytes = max_bytes.unwrap_or(DEFAULT_LOG_TAIL_BYTES).min(MAX_LOG_TAIL_BYTES);
+    let tail = logs::read_tail(&path, max_bytes).map_err(|e| SidecarError::LogFile(e.into()))?;
+    Ok(match tail_lines {
+        Some(lines) => tail.last_lines(lines),
+        None => tail,
+    })
+}
 
-        // Use the Tauri shell plugin's sidecar API, which handles path resolution
-        // and target-triple binary naming automatically.
-        let sidecar_command = match app_handle
-            .shell()
-            .sidecar("claudetini-sidecar")
-        {
-            Ok(cmd) => cmd.args(["--port", &port.to_string()]),
-            Err(e) => {
-                eprintln!("Failed to create sidecar command: {e}");
-                return;
-            }
-        };
+/// Tauri command: how much disk this session's log and older ones take up,
+/// next to the configured limits, how many lines a slow disk cost and how
+/// many were cut short by the line cap.
+#[tauri::command]
+fn get_log_usage(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<LogUsage, SidecarError> {
+    let path = current_log_path(&app_handle, &state)?;
+    let rotation = app_handle.state::<SidecarConfig>().log_rotation;
+    let mut usage = logs::usage(&path, rotation).map_err(|e| SidecarError::LogFile(e.into()))?;
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    usage.dropped_lines = s.log_file.dropped();
+    usage.truncated_lines = s.truncated_lines;
+    Ok(usage)
+}
 
-        match sidecar_command.spawn() {
-            Ok((rx, child)) => {
-                println!("Sidecar process spawned, polling health...");
+/// Tauri command: where this session's output is being written. Copies
+/// rotated out of it sit beside it with `.1`, `.2` and so on appended.
+#[tauri::command]
+fn get_log_path(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<PathBuf, SidecarError> {
+    current_log_path(&app_handle, &state)
+}
 
-                // Store the child handle in managed state so it lives for the
-                // app's lifetime and can be killed on shutdown.
-                let state = app_handle.state::<Mutex<SidecarState>>();
-                if let Ok(mut s) = state.lock() {
-                    s.port = port;
-                    s.child = Some(child);
-                }
+fn current_log_path(
+    app_handle: &AppHandle,
+    state: &Mutex<SidecarState>,
+) -> Result<PathBuf, SidecarError> {
+    let log_file = state
+        .lock()
+        .map_err(|_| SidecarError::LockPoisoned)?
+        .log_file
+        .clone();
+    if let Some(path) = log_file.path() {
+        return Ok(path);
+    }
+    // Not logging to disk this session: the newest file is the best guess.
+    let log_dir = log_dir(app_handle)?;
+    let newest = logs::list_files(&log_dir, None).ok().and_then(|files| files.into_iter().next());
+    match newest {
+        Some(file) => Ok(log_dir.join(file.name)),
+        None => Err(SidecarError::LogFileMissing(log_dir)),
+    }
+}
 
-                // Consume the event receiver in a background task to keep the
-                // channel alive and log sidecar output.
-                tauri::async_runtime::spawn(async move {
-                    drain_sidecar_events(rx).await;
-                });
+fn log_dir(app_handle: &AppHandle) -> Result<PathBuf, SidecarError> {
+    match app_handle.try_state::<AppDirs>() {
+        Some(dirs) => Ok(dirs.logs.clone()),
+        None => paths::resolve_app_dirs(app_handle)
+            .map(|dirs| dirs.logs)
+            .map_err(|e| SidecarError::AppDirs(Arc::new(e))),
+    }
+}
 
-                // Poll health in the background, then emit event.
-                let handle = app_handle.clone();
-                tauri::async_runtime::spawn(async move {
-                    match poll_health(port, 30).await {
-                        Ok(()) => {
-                            let _ = handle.emit("sidecar-ready", SidecarReadyPayload { port });
-                        }
-                        Err(e) => {
-                            eprintln!("Sidecar health poll failed: {e}");
-                        }
-                    }
-                });
+/// Where crash reports go, under the app data dir.
+fn crash_dir(app_handle: &AppHandle) -> Result<PathBuf, SidecarError> {
+    let data = match app_handle.try_state::<AppDirs>() {
+        Some(dirs) => dirs.data.clone(),
+        None => paths::resolve_app_dirs(app_handle)
+            .map(|dirs| dirs.data)
+            .map_err(|e| SidecarError::AppDirs(Arc::new(e)))?,
+    };
+    Ok(data.join(crash::CRASH_DIR))
+}
 
-                // Spawn succeeded — break out of the retry loop.
-                return;
+/// Write a crash report to `dir` whenever anything panics, background tasks
+/// included, then run the previous hook as usual.
+fn install_panic_hook(app_handle: &AppHandle, dir: PathBuf) {
+    let handle = app_handle.clone();
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let mut report = CrashReport::new(info, now_unix_ms());
+        report.app_version = handle.package_info().version.to_string();
+        // Not `lock`: the panicking thread may be the one holding it.
+        if let Ok(s) = handle.state::<Mutex<SidecarState>>().try_lock() {
+            report.sidecar_status = serde_json::to_value(&s.status).unwrap_or_default();
+            report.recent_logs = s.recent_logs.tail(crash::REPORT_LOG_LINES, None);
+        }
+        // Straight to stderr: the subscriber may be what panicked.
+        match crash::write(&dir, &report) {
+            Ok(path) => {
+                let _ = writeln!(std::io::stderr(), "Crash report written to {}", path.display());
             }
             Err(e) => {
-                eprintln!("Failed to spawn sidecar (attempt {attempt}): {e}");
+                let _ = writeln!(std::io::stderr(), "Could not write a crash report: {e}");
             }
         }
+        previous(info);
+    }));
+}
+
+/// Emit `sidecar-state-changed` for every status change, held back until the
+/// frontend has loaded.
+async fn relay_state_changes(
+    app_handle: AppHandle,
+    mut changes: mpsc::UnboundedReceiver<SidecarStateChangedPayload>,
+) {
+    let queue = app_handle.state::<StateChangeQueue>();
+    while let Some(change) = changes.recv().await {
+        // Emitting under the lock keeps live changes behind a replay.
+        let Ok(mut queue) = queue.lock() else { return };
+        if let Some(change) = queue.offer(change) {
+            emit_state_change(&app_handle, change);
+        }
     }
+}
 
-    eprintln!("All 3 sidecar spawn attempts failed");
+/// Replay the status changes a freshly loaded frontend missed, once per
+/// launch. A frontend reloaded later asks `get_sidecar_status` instead.
+fn replay_state_changes(app_handle: &AppHandle) {
+    let Some(queue) = app_handle.try_state::<StateChangeQueue>() else {
+        return;
+    };
+    let Ok(mut queue) = queue.lock() else { return };
+    for change in queue.release() {
+        emit_state_change(app_handle, change);
+    }
 }
 
-/// Read sidecar stdout/stderr and log it. Runs until the process terminates.
-async fn drain_sidecar_events(mut rx: tokio::sync::mpsc::Receiver<CommandEvent>) {
-    while let Some(event) = rx.recv().await {
-        match event {
-            CommandEvent::Stdout(line) => {
-                println!("[sidecar] {}", String::from_utf8_lossy(&line));
-            }
-            CommandEvent::Stderr(line) => {
-                eprintln!("[sidecar] {}", String::from_utf8_lossy(&line));
-            }
-            CommandEvent::Terminated(payload) => {
-                eprintln!("Sidecar terminated: code={:?} signal={:?}", payload.code, payload.signal);
-                break;
-            }
-            CommandEvent::Error(msg) => {
-                eprintln!("[sidecar] error: {msg}");
+fn emit_state_change(app_handle: &AppHandle, change: SidecarStateChangedPayload) {
+    if let Err(e) = app_handle.emit("sidecar-state-changed", change) {
+        warn!("Could not emit sidecar-state-changed: {e}");
+    }
+}
+
+/// Tell a freshly loaded frontend about crash reports it hasn't seen, with
+/// `app-crash-detected`, once per launch.
+fn announce_crashes(app_handle: &AppHandle) {
+    let Some(reports) = app_handle.try_state::<PendingCrashes>().and_then(|p| p.take()) else {
+        return;
+    };
+    warn!("{} unread crash report(s) from earlier sessions", reports.len());
+    let _ = app_handle.emit("app-crash-detected", AppCrashDetectedPayload { reports: &reports });
+    if let Err(e) = crash::mark_read(&reports) {
+        warn!("Could not mark crash reports read: {e}");
+    }
+}
+
+/// Tauri command: crash reports from this and earlier sessions, newest first.
+#[tauri::command]
+fn list_crash_reports(app_handle: AppHandle) -> Result<Vec<CrashReportInfo>, SidecarError> {
+    crash::list(&crash_dir(&app_handle)?).map_err(|e| SidecarError::CrashReports(e.into()))
+}
+
+/// Tauri command: panic in a background task, to check crash reporting end
+/// to end. Debug builds only.
+#[tauri::command]
+fn trigger_test_panic() -> Result<(), SidecarError> {
+    if !cfg!(debug_assertions) {
+        return Err(SidecarError::DebugOnly);
+    }
+    tauri::async_runtime::spawn(async {
+        panic!("Test panic requested with trigger_test_panic");
+    });
+    Ok(())
+}
+
+/// Tauri command: show the app log directory in the OS file manager,
+/// creating it first if nothing has been logged yet, so support can point
+/// users at a button instead of a path.
+#[tauri::command]
+fn open_log_folder(app_handle: AppHandle) -> Result<(), SidecarError> {
+    let log_dir = log_dir(&app_handle)?;
+    paths::ensure_dir(&log_dir).map_err(|e| SidecarError::AppDirs(Arc::new(e)))?;
+    app_handle
+        .opener()
+        .open_path(log_dir.to_string_lossy(), None::<&str>)
+        .map_err(|e| SidecarError::Open(e.to_string()))
+}
+
+/// Tauri command: show the app log directory in the OS file manager. Fails if
+/// nothing has created it yet rather than opening a freshly made empty folder;
+/// `open_log_folder` creates it instead.
+#[tauri::command]
+fn open_logs_folder(app_handle: AppHandle) -> Result<(), SidecarError> {
+    let log_dir = log_dir(&app_handle)?;
+    if !log_dir.is_dir() {
+        return Err(SidecarError::LogDirMissing(log_dir));
+    }
+    app_handle
+        .opener()
+        .open_path(log_dir.to_string_lossy(), None::<&str>)
+        .map_err(|e| SidecarError::Open(e.to_string()))
+}
+
+/// Tauri command: show the current log file selected in the OS file manager.
+#[tauri::command]
+fn reveal_log_file(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<(), SidecarError> {
+    let path = current_log_path(&app_handle, &state)?;
+    if !path.is_file() {
+        return Err(SidecarError::LogFileMissing(path));
+    }
+    app_handle
+        .opener()
+        .reveal_item_in_dir(&path)
+        .map_err(|e| SidecarError::Open(e.to_string()))
+}
+
+/// Tauri command: the app's version and what it was built from, all known at
+/// compile time, so it works before the sidecar is up.
+#[tauri::command]
+fn get_app_info(app_handle: AppHandle) -> AppInfo {
+    let info = app_handle.package_info();
+    AppInfo {
+        app_name: info.name.clone(),
+        app_version: info.version.to_string(),
+        git_commit: Some(env!("CLAUDETINI_GIT_COMMIT")).filter(|c| !c.is_empty()),
+        build_timestamp: env!("CLAUDETINI_BUILD_TIMESTAMP").parse().ok(),
+        tauri_version: tauri::VERSION,
+        target: env!("CLAUDETINI_TARGET"),
+        debug: cfg!(debug_assertions),
+    }
+}
+
+/// Tauri command: whether the sidecar binary a spawn would use exists, and
+/// where it was looked for, so setup can flag an incomplete installation.
+#[tauri::command]
+fn sidecar_binary_available(
+    config: tauri::State<'_, SidecarConfig>,
+) -> Result<SidecarBinary, SidecarError> {
+    if let Some(path) = &config.custom_binary {
+        return Ok(SidecarBinary {
+            available: validate_sidecar_binary(path).is_ok(),
+            path: path.clone(),
+            custom: true,
+        });
+    }
+    let path = bundled_sidecar_path(&config.sidecar_name)
+        .map_err(|e| SidecarError::Command(e.to_string()))?;
+    Ok(SidecarBinary {
+        available: path.is_file(),
+        path,
+        custom: false,
+    })
+}
+
+/// Tauri command: whether `port` can currently be bound on the sidecar host,
+/// for validating a fixed port in settings.
+#[tauri::command]
+fn check_port_available(config: tauri::State<'_, SidecarConfig>, port: u16) -> bool {
+    port != 0 && bind_port(&config.host, port).is_ok()
+}
+
+/// Tauri command: change how often the running watchdog checks the sidecar,
+/// effective from its next check. Session-only; returns the applied interval in ms.
+#[tauri::command]
+fn set_watchdog_interval(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    ms: u64,
+) -> Result<u64, SidecarError> {
+    let interval = Duration::from_millis(ms).max(MIN_WATCHDOG_INTERVAL);
+    let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    s.health_interval = interval;
+    Ok(interval.as_millis() as u64)
+}
+
+/// Tauri command: intentionally stop the sidecar. Unlike a restart, this also
+/// clears the health history since there is nothing left to monitor.
+#[tauri::command]
+async fn stop_sidecar(app_handle: AppHandle) -> Result<(), SidecarError> {
+    ensure_enabled(&app_handle)?;
+    terminate_sidecar(&app_handle).await;
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let mut s = state
+        .lock()
+        .map_err(|_| SidecarError::LockPoisoned)?;
+    s.transition(SidecarStatus::Stopped, "stop requested");
+    s.health_history.clear();
+    Ok(())
+}
+
+/// Tauri command: relaunch the whole app, e.g. to apply settings read at
+/// startup. The sidecar is stopped first, gracefully where possible, so the
+/// relaunch can't orphan it. An external dev sidecar is left running.
+#[tauri::command]
+async fn relaunch_app(app_handle: AppHandle) {
+    log_lifecycle(&app_handle, "Relaunching app");
+    terminate_sidecar(&app_handle).await;
+    if let Ok(mut s) = app_handle.state::<Mutex<SidecarState>>().lock() {
+        s.transition(SidecarStatus::Stopped, "relaunching the app");
+        s.remove_discovery_file();
+        s.flush_log();
+    }
+    app_handle.restart()
+}
+
+/// Tauri command: send signal `signum` to a spawned sidecar (Unix only), for
+/// sidecars that reload config on e.g. SIGUSR1 without a restart. Only
+/// SIGHUP, SIGUSR1 and SIGUSR2 are allowed.
+#[tauri::command]
+fn signal_sidecar(app_handle: AppHandle, signum: i32) -> Result<(), SidecarError> {
+    ensure_enabled(&app_handle)?;
+    if uses_external_sidecar(&app_handle) {
+        return Err(SidecarError::ExternalSidecar);
+    }
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let pid = {
+        let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        s.child.as_ref().map(CommandChild::pid).ok_or(SidecarError::NotRunning)?
+    };
+    signal::send(pid, signum)
+}
+
+/// Tauri command: write `data` to a spawned sidecar's stdin as one line, for
+/// sidecars that take commands there (a REPL, say) rather than over HTTP. A
+/// trailing newline is added if missing. The pipe is unbuffered, so the
+/// sidecar can read the line as soon as this returns. The data isn't logged.
+#[tauri::command]
+fn write_sidecar_stdin(app_handle: AppHandle, data: String) -> Result<(), SidecarError> {
+    ensure_enabled(&app_handle)?;
+    if uses_external_sidecar(&app_handle) {
+        return Err(SidecarError::ExternalSidecar);
+    }
+    let mut line = data;
+    if !line.ends_with('\n') {
+        line.push('\n');
+    }
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    let child = s.child.as_mut().ok_or(SidecarError::NotRunning)?;
+    match child.write(line.as_bytes()) {
+        Ok(()) => {
+            debug!("Wrote {} bytes to sidecar stdin", line.len());
+            Ok(())
+        }
+        Err(tauri_plugin_shell::Error::Io(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+            Err(SidecarError::StdinClosed)
+        }
+        Err(tauri_plugin_shell::Error::Io(e)) => Err(SidecarError::Stdin(e.into())),
+        Err(e) => Err(SidecarError::Stdin(std::io::Error::other(e.to_string()).into())),
+    }
+}
+
+/// Tauri command: ask a spawned sidecar to reload its settings without a
+/// restart, over the stdin control channel, and wait for its answer. The
+/// result says whether it accepted and which settings still need a restart.
+/// A sidecar that doesn't answer in time likely doesn't support reloading.
+#[tauri::command]
+async fn reload_sidecar_config(app_handle: AppHandle) -> Result<ReloadResult, SidecarError> {
+    ensure_restartable(&app_handle)?;
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let (seq, ack) = {
+        let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        s.reload_seq += 1;
+        let seq = s.reload_seq;
+        let child = s.child.as_mut().ok_or(SidecarError::NotRunning)?;
+        child
+            .write(reload::request(seq).as_bytes())
+            .map_err(|e| SidecarError::Command(e.to_string()))?;
+        // A newer request supersedes one still waiting, which then fails.
+        let (tx, rx) = oneshot::channel();
+        s.pending_reload = Some((seq, tx));
+        (seq, rx)
+    };
+    info!("Asked the sidecar to reload its settings (request {seq})");
+    let result = match tokio::time::timeout(RELOAD_ACK_TIMEOUT, ack).await {
+        Ok(Ok(result)) => result,
+        // The child exited, or a newer reload replaced this one.
+        Ok(Err(_)) => return Err(SidecarError::NotRunning),
+        Err(_) => {
+            if let Ok(mut s) = state.lock() {
+                if s.pending_reload.as_ref().is_some_and(|(n, _)| *n == seq) {
+                    s.pending_reload = None;
+                }
             }
-            _ => {}
+            let after_ms = RELOAD_ACK_TIMEOUT.as_millis();
+            return Err(SidecarError::ReloadTimeout { after_ms });
+        }
+    };
+    if !result.accepted {
+        let reason = result.message.as_deref().unwrap_or("no reason given");
+        warn!("Sidecar refused to reload: {reason}");
+    } else if result.restart_required.is_empty() {
+        info!("Sidecar reloaded its settings");
+    } else {
+        let pending = result.restart_required.join(", ");
+        info!("Sidecar reloaded its settings; {pending} need a restart");
+    }
+    Ok(result)
+}
+
+fn restart_gate(s: &mut SidecarState) -> &mut RestartGate {
+    &mut s.restart_gate
+}
+
+/// Tauri command: stop and respawn the sidecar, returning the new port.
+/// Calls made while a restart is already running share its result.
+#[tauri::command]
+async fn restart_sidecar(app_handle: AppHandle) -> RestartResult {
+    ensure_restartable(&app_handle)?;
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    restart::debounced(&state, restart_gate, || {
+        restart_explicitly(&app_handle, "Restart requested")
+    })
+    .await
+}
+
+/// Tauri command: restart the sidecar with `args` replacing the current extra
+/// arguments, in one step so nothing can spawn with a half-applied change.
+/// Refused while another restart is running, since joining it would silently
+/// drop the new arguments.
+#[tauri::command]
+async fn restart_sidecar_with_args(app_handle: AppHandle, args: Vec<String>) -> RestartResult {
+    ensure_restartable(&app_handle)?;
+    validate_extra_args(&args)?;
+    let lease = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let mut s = state
+            .lock()
+            .map_err(|_| SidecarError::LockPoisoned)?;
+        let Turn::Lead(lease) = s.restart_gate.begin() else {
+            return Err(SidecarError::RestartInProgress);
+        };
+        s.extra_args = args;
+        lease
+    };
+    let result = restart_explicitly(&app_handle, "Restart requested with new arguments").await;
+    lease.finish(&result);
+    result
+}
+
+/// Tauri command: turn LAN binding on or off for the session. A change
+/// restarts the sidecar so it rebinds; turning it off always drops back to
+/// loopback only. Returns the port once ready, as `restart_sidecar` does.
+#[tauri::command]
+async fn set_lan_binding(app_handle: AppHandle, enabled: bool) -> RestartResult {
+    ensure_restartable(&app_handle)?;
+    let lease = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let mut s = state
+            .lock()
+            .map_err(|_| SidecarError::LockPoisoned)?;
+        if s.bind_lan == enabled {
+            return Ok(s.endpoint.as_ref().and_then(SidecarEndpoint::port));
+        }
+        let Turn::Lead(lease) = s.restart_gate.begin() else {
+            return Err(SidecarError::RestartInProgress);
+        };
+        s.bind_lan = enabled;
+        lease
+    };
+    let reason = if enabled {
+        "LAN binding enabled"
+    } else {
+        "LAN binding disabled"
+    };
+    let result = restart_explicitly(&app_handle, reason).await;
+    lease.finish(&result);
+    result
+}
+
+/// Tauri command: change only the sidecar's log level and restart it, so the
+/// level applies from its first line even if it ignores `log-level` on stdin.
+/// Returns the new port, as `restart_sidecar` does. Refused while another
+/// restart is running, which may already have spawned with the old level.
+#[tauri::command]
+async fn set_sidecar_log_level(app_handle: AppHandle, level: String) -> RestartResult {
+    let level: logging::LogLevel = level.parse().map_err(SidecarError::InvalidLogLevel)?;
+    ensure_restartable(&app_handle)?;
+    let lease = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let mut s = state
+            .lock()
+            .map_err(|_| SidecarError::LockPoisoned)?;
+        let Turn::Lead(lease) = s.restart_gate.begin() else {
+            return Err(SidecarError::RestartInProgress);
+        };
+        s.sidecar_log_level = Some(level);
+        lease
+    };
+    let reason = format!("Sidecar log level set to {}", level.as_str());
+    let result = restart_explicitly(&app_handle, &reason).await;
+    lease.finish(&result);
+    result
+}
+
+/// Tauri command: replace this install's sidecar certificate and restart the
+/// sidecar to serve the new one, so clients pinned to the old one stop
+/// trusting it. Returns the new fingerprint. Refused with TLS off or while
+/// another restart is running.
+#[tauri::command]
+async fn rotate_sidecar_cert(app_handle: AppHandle) -> Result<String, SidecarError> {
+    ensure_restartable(&app_handle)?;
+    if !tls_applies(&app_handle) {
+        return Err(SidecarError::TlsDisabled);
+    }
+    let dirs =
+        paths::resolve_app_dirs(&app_handle).map_err(|e| SidecarError::AppDirs(Arc::new(e)))?;
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let lease = {
+        let mut s = state
+            .lock()
+            .map_err(|_| SidecarError::LockPoisoned)?;
+        let Turn::Lead(lease) = s.restart_gate.begin() else {
+            return Err(SidecarError::RestartInProgress);
+        };
+        lease
+    };
+    let certificate = match tls::Certificate::generate(&dirs.data.join(tls::DIR_NAME)) {
+        Ok(certificate) => certificate,
+        Err(e) => {
+            let error = SidecarError::Tls(Arc::new(e));
+            lease.finish(&Err(error.clone()));
+            return Err(error);
         }
+    };
+    let fingerprint = certificate.fingerprint();
+    if let Ok(mut s) = state.lock() {
+        s.certificate = Some(certificate);
     }
+    let reason = format!("Sidecar certificate rotated, now {fingerprint}");
+    let result = restart_explicitly(&app_handle, &reason).await;
+    lease.finish(&result);
+    result.map(|_| fingerprint)
 }
 
-/// Tauri command: return the current sidecar port (0 if not yet assigned).
+/// Tauri command: the newest `limit` buffered output lines (all of them by
+/// default), oldest first, optionally only those from `stream_filter`
+/// (`stdout`, `stderr`, `error` or `supervisor`). Reads memory only, and
+/// covers every run this session with a marker line between runs.
 #[tauri::command]
-fn get_sidecar_port(state: tauri::State<'_, Mutex<SidecarState>>) -> Option<u16> {
-    state.lock().ok().map(|s| s.port).filter(|&p| p != 0)
+fn get_sidecar_logs(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    limit: Option<usize>,
+    stream_filter: Option<String>,
+) -> Result<Vec<LogLine>, SidecarError> {
+    let s = state
+        .lock()
+        .map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(s.recent_logs.tail(limit.unwrap_or(usize::MAX), stream_filter.as_deref()))
+}
+
+/// Tauri command: bundle port, status, version, uptime, and recent logs
+/// into a single blob the frontend can attach to a bug report.
+#[tauri::command]
+fn get_diagnostics(
+    app_handle: AppHandle,
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    lines: Option<usize>,
+) -> Result<Diagnostics, SidecarError> {
+    let s = state
+        .lock()
+        .map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(diagnostics(&app_handle, &s, lines.unwrap_or(DEFAULT_DIAGNOSTIC_LINES)))
+}
+
+fn diagnostics(app_handle: &AppHandle, s: &SidecarState, lines: usize) -> Diagnostics {
+    Diagnostics {
+        app_version: app_handle.package_info().version.to_string(),
+        port: s.endpoint.as_ref().and_then(SidecarEndpoint::port),
+        status: s.status.clone(),
+        uptime_ms: s.started_at.map(|t| t.elapsed().as_millis() as u64),
+        restart_count: s.restart_count,
+        last_exit: s.last_exit.clone(),
+        replaced_bytes: s.replaced_bytes,
+        recent_logs: s
+            .recent_logs
+            .tail(lines, None)
+            .into_iter()
+            .map(|l| format!("[{}] {}", l.stream, l.line))
+            .collect(),
+    }
+}
+
+/// Tauri command: a short plain-text summary for pasting into a bug report,
+/// copied to the clipboard and returned for the frontend to preview. Works
+/// with the sidecar down; whatever can't be found out reads "unavailable".
+#[tauri::command]
+async fn copy_diagnostics_summary(app_handle: AppHandle) -> Result<String, SidecarError> {
+    let mut summary = Summary {
+        app_version: app_handle.package_info().version.to_string(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        ..Summary::default()
+    };
+    let reachable = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let s = state.lock().ok();
+        s.and_then(|s| {
+            summary.status = serde_json::to_value(&s.status)
+                .ok()
+                .and_then(|v| v["state"].as_str().map(str::to_string));
+            summary.uptime_ms = s.started_at.map(|t| t.elapsed().as_millis() as u64);
+            summary.restart_count = Some(s.restart_count);
+            summary.last_error = s.last_error.as_ref().map(|e| s.redact(e.message.clone()));
+            summary.recent_logs = s
+                .recent_logs
+                .tail(SUMMARY_LOG_LINES, None)
+                .into_iter()
+                .map(|l| s.redact(format!("[{}] {}", l.stream, l.line)))
+                .collect();
+            s.ready_url().and(s.endpoint.clone()).map(|e| (e, s.token.clone()))
+        })
+    };
+    if let Some((endpoint, token)) = reachable {
+        let timeout = app_handle.state::<SidecarConfig>().health_timeout;
+        summary.sidecar_version =
+            health::sidecar_version(&endpoint, token.as_ref(), timeout).await.ok();
+    }
+    let text = summary.render();
+    app_handle
+        .clipboard()
+        .write_text(text.clone())
+        .map_err(|e| SidecarError::Clipboard(e.to_string()))?;
+    Ok(text)
+}
+
+/// Tauri command: a snapshot of the supervisor's state, counters and the
+/// config in effect, with secrets redacted.
+#[tauri::command]
+fn dump_sidecar_state(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    config: tauri::State<'_, SidecarConfig>,
+) -> Result<SidecarStateDump, SidecarError> {
+    let config = serde_json::to_value(&*config).unwrap_or_default();
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(SidecarStateDump {
+        status: s.status.clone(),
+        generation: s.generation,
+        endpoint: s.endpoint.clone(),
+        pid: s.child.as_ref().map(CommandChild::pid),
+        uptime_ms: s.started_at.map(|t| t.elapsed().as_millis() as u64),
+        ready_announced: s.ready_announced == Some(s.generation),
+        stop_requested: s.stop_requested,
+        restart_count: s.restart_count,
+        recent_restarts: s.crash_budget.recent(),
+        port_retries: s.port_retries,
+        startup_retries: s.startup_retries,
+        last_exit: s.last_exit.clone(),
+        last_pong: s.last_pong,
+        last_latency_ms: s.last_latency_ms,
+        health_interval_ms: s.health_interval.as_millis() as u64,
+        health_checks_recorded: s.health_history.len(),
+        replaced_bytes: s.replaced_bytes,
+        bind_lan: s.bind_lan,
+        extra_args: s.extra_args.iter().map(|arg| s.redact(arg.clone())).collect(),
+        sidecar_log_level: s.sidecar_log_level,
+        config: s.redactor.redact_json(config),
+    })
+}
+
+/// Tauri command: milliseconds from app setup to the port being settled, the
+/// sidecar spawning, its first health check and the first `sidecar-ready`.
+/// Steps not reached yet are `null`; restarts don't change them.
+#[tauri::command]
+fn get_startup_timings(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+) -> Result<StartupTimingsReport, SidecarError> {
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    Ok(s.startup_timings.report())
+}
+
+/// Tauri command: every sidecar setting in effect and whether it came from
+/// the defaults, `sidecar.json` or a `CLAUDETINI_*` variable, secrets redacted.
+#[tauri::command]
+fn get_effective_config(
+    state: tauri::State<'_, Mutex<SidecarState>>,
+    config: tauri::State<'_, SidecarConfig>,
+) -> Result<BTreeMap<String, EffectiveSetting>, SidecarError> {
+    let mut effective = config.effective();
+    let s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+    for setting in effective.values_mut() {
+        setting.value = s.redactor.redact_json(std::mem::take(&mut setting.value));
+    }
+    Ok(effective)
+}
+
+/// Tauri command: write a support bundle to `destination`, a zip path or a
+/// folder to put a timestamped one in, and return where it was written. It
+/// holds the log files, health history, status, sanitized settings and
+/// system details, but never the auth token or API keys. Built on a
+/// blocking thread and capped at `bundle::MAX_BUNDLE_BYTES`.
+#[tauri::command]
+async fn export_diagnostics(
+    app_handle: AppHandle,
+    destination: PathBuf,
+) -> Result<PathBuf, SidecarError> {
+    let path = if destination.is_dir() {
+        destination.join(format!("claudetini-diagnostics-{}.zip", now_unix_ms()))
+    } else {
+        destination
+    };
+    let handle = app_handle.clone();
+    let written = path.clone();
+    tauri::async_runtime::spawn_blocking(move || write_diagnostics(&handle, &written))
+        .await
+        .map_err(|e| SidecarError::Diagnostics(Arc::new(std::io::Error::other(e))))??;
+    log_lifecycle(&app_handle, &format!("Wrote diagnostics bundle {}", path.display()));
+    Ok(path)
+}
+
+fn write_diagnostics(app_handle: &AppHandle, path: &Path) -> Result<(), SidecarError> {
+    let mut bundle = Bundle::new();
+    {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        s.flush_log();
+        bundle.add_json("status.json", &diagnostics(app_handle, &s, DEFAULT_DIAGNOSTIC_LINES));
+        bundle.add_json("health_history.json", &s.health_history);
+        bundle.add_json("checks.json", &s.checks);
+    }
+    let info = app_handle.package_info();
+    let system = SystemInfo {
+        app_name: info.name.clone(),
+        app_version: info.version.to_string(),
+        tauri_version: tauri::VERSION,
+        os: std::env::consts::OS,
+        os_family: std::env::consts::FAMILY,
+        arch: std::env::consts::ARCH,
+    };
+    bundle.add_json("system.json", &system);
+    let settings_file = app_handle
+        .path()
+        .app_config_dir()
+        .ok()
+        .and_then(|dir| std::fs::read(dir.join(CONFIG_FILE_NAME)).ok())
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .map(bundle::sanitize_settings);
+    let settings = serde_json::json!({ "file": settings_file, "env": bundle::app_env() });
+    bundle.add_json("settings.json", &settings);
+
+    // Newest log first, so the cap cuts the oldest history. Lines were masked
+    // when written; this pass catches anything from before that.
+    let config = app_handle.state::<SidecarConfig>();
+    let redactor = Redactor::new(&config.redact_patterns);
+    let log_dir = log_dir(app_handle)?;
+    for file in logs::list_files(&log_dir, None).unwrap_or_default() {
+        if bundle.remaining() == 0 {
+            break;
+        }
+        let Ok(tail) = logs::read_tail(&log_dir.join(&file.name), bundle.remaining()) else {
+            continue;
+        };
+        if !tail.exists {
+            continue;
+        }
+        let content: Vec<_> = tail.content.lines().map(|line| redactor.redact(line)).collect();
+        bundle.add(format!("logs/{}", file.name), content.join("\n").into_bytes());
+    }
+    // Their output lines were masked when buffered.
+    let crashes = crash_dir(app_handle).ok().and_then(|dir| crash::list(&dir).ok());
+    for report in crashes.unwrap_or_default() {
+        if let Ok(data) = std::fs::read(&report.path) {
+            bundle.add(format!("crashes/{}", report.name), data);
+        }
+    }
+    bundle.write(path).map_err(|e| SidecarError::Diagnostics(e.into()))
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let app = tauri::Builder::default()
+    let log_file = logging::init();
+    let no_sidecar = std::env::args().skip(1).any(|arg| arg == "--no-sidecar");
+    let app = tauri::Builder::<SidecarRuntime>::new()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
-        .manage(Mutex::new(SidecarState {
-            port: 0,
-            child: None,
-        }))
-        .invoke_handler(tauri::generate_handler![get_sidecar_port])
-        .setup(|app| {
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(Visibility::new())
+        .invoke_handler(tauri::generate_handler![
+            get_sidecar_port,
+            get_sidecar_endpoint,
+            get_sidecar_url,
+            get_sidecar_token,
+            get_lan_endpoints,
+            set_lan_binding,
+            rotate_sidecar_cert,
+            proxy_request,
+            get_sidecar_connection_info,
+            get_diagnostics,
+            get_app_info,
+            copy_diagnostics_summary,
+            dump_sidecar_state,
+            get_effective_config,
+            get_startup_timings,
+            export_diagnostics,
+            get_health_history,
+            get_sidecar_metrics,
+            get_restart_count,
+            get_sidecar_status,
+            ping_sidecar,
+            get_last_sidecar_error,
+            is_sidecar_ready,
+            is_sidecar_degraded,
+            get_sidecar_checks,
+            get_sidecar_logs,
+            read_log_file,
+            get_log_usage,
+            get_log_path,
+            list_log_files,
+            set_log_level,
+            set_sidecar_log_level,
+            wait_for_sidecar_ready,
+            get_log_level,
+            subscribe_sidecar_logs,
+            set_sidecar_log_filter,
+            unsubscribe_sidecar_logs,
+            set_event_relay_enabled,
+            open_logs_folder,
+            open_log_folder,
+            reveal_log_file,
+            sidecar_binary_available,
+            check_port_available,
+            set_watchdog_interval,
+            stop_sidecar,
+            relaunch_app,
+            signal_sidecar,
+            reload_sidecar_config,
+            write_sidecar_stdin,
+            get_sidecar_resources,
+            restart_sidecar,
+            restart_sidecar_with_args,
+            list_crash_reports,
+            trigger_test_panic
+        ])
+        .on_page_load(|webview, payload| {
+            if payload.event() == PageLoadEvent::Finished {
+                replay_state_changes(webview.app_handle());
+                announce_crashes(webview.app_handle());
+            }
+        })
+        .on_window_event(|window, event| {
+            if matches!(event, WindowEvent::Focused(_) | WindowEvent::Resized(_)) {
+                visibility::refresh(window.app_handle());
+            }
+        })
+        .setup(move |app| {
+            let setup_started = Instant::now();
             // Updater disabled until a signing keypair is generated.
             // To enable: run `tauri signer generate`, set pubkey in tauri.conf.json,
             // and uncomment the line below.
             // #[cfg(desktop)]
             // app.handle().plugin(tauri_plugin_updater::Builder::new().build())?;
 
-            spawn_sidecar(app.handle());
+            // The settings file lives in the app config dir, which is only
+            // known once the app exists.
+            let config_dir = app.path().app_config_dir().ok();
+            let mut config = SidecarConfig::load(config_dir.as_deref());
+            if no_sidecar {
+                config.disabled = true;
+                config.sources.insert("disabled".into(), ConfigSource::Cli);
+            }
+            logging::set_filter(&config.log_filter);
+            let state_changes_rx =
+                manage_supervisor(app.handle(), config, log_file.clone(), setup_started);
+            if let Ok(dir) = crash_dir(app.handle()) {
+                let unread = match crash::list(&dir) {
+                    Ok(reports) => reports.into_iter().filter(|r| !r.read).collect(),
+                    Err(e) => {
+                        warn!("Could not check for crash reports: {e}");
+                        Vec::new()
+                    }
+                };
+                app.manage(PendingCrashes::new(unread));
+                install_panic_hook(app.handle(), dir);
+            }
+
+            // A failed start was already reported as `sidecar-error`; the
+            // window still opens so the frontend can show it.
+            if app.state::<SidecarConfig>().disabled {
+                disable_sidecar(app.handle());
+            } else {
+                let _ = spawn_sidecar(app.handle());
+            }
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(relay_state_changes(handle, state_changes_rx));
+            tauri::async_runtime::spawn(relay_events(app.handle().clone()));
+            tauri::async_runtime::spawn(watch_network(app.handle().clone()));
             Ok(())
         })
         .build(tauri::generate_context!())
@@ -203,16 +3994,84 @@ pub fn run() {
     // Kill the sidecar gracefully when the app exits.
     app.run(|app_handle, event| {
         if let RunEvent::Exit = event {
-            let child = {
-                let state = app_handle.state::<Mutex<SidecarState>>();
-                state.lock().ok().and_then(|mut s| s.child.take())
-            };
-            if let Some(child) = child {
-                println!("Killing sidecar on app exit");
-                if let Err(e) = child.kill() {
-                    eprintln!("Failed to kill sidecar: {e}");
-                }
-            }
+            shutdown_sidecar(app_handle);
+        }
+    });
+}
+
+/// Register the supervisor's state with the app. Returns the receiving end
+/// of its status changes, for `relay_state_changes`.
+fn manage_supervisor(
+    app_handle: &AppHandle,
+    config: SidecarConfig,
+    log_file: LogWriter,
+    setup_started: Instant,
+) -> mpsc::UnboundedReceiver<SidecarStateChangedPayload> {
+    let (state_changes, state_changes_rx) = mpsc::unbounded_channel();
+    app_handle.manage(Mutex::new(SidecarState::new(
+        &config,
+        log_file,
+        setup_started,
+        state_changes,
+    )));
+    app_handle.manage(StateChangeQueue::new(ReplayQueue::new(STATE_CHANGE_REPLAY)));
+    app_handle.manage(EventRelay::new(config.event_relay));
+    app_handle.manage(config);
+    state_changes_rx
+}
+
+/// Kill the sidecar as the app exits, after its output has been written out.
+fn shutdown_sidecar(app_handle: &AppHandle) {
+    let (child, drain) = {
+        let state = app_handle.state::<Mutex<SidecarState>>();
+        state.lock().ok().map_or((None, None), |mut s| {
+            s.stop_requested = true;
+            s.remove_discovery_file();
+            (s.child.take(), s.drain.take())
+        })
+    };
+    if let Some(child) = child {
+        log_lifecycle(app_handle, "Killing sidecar on app exit");
+        match child.kill() {
+            // Its Terminated event won't be handled before we exit.
+            Ok(()) => log_lifecycle(app_handle, "Sidecar killed on app exit"),
+            Err(e) => warn!("Failed to kill sidecar: {e}"),
         }
+    }
+    if let Some(drain) = drain {
+        stop_drain(drain);
+    }
+    close_log_file(app_handle);
+}
+
+/// Have the output task write what it has and wait for it, so quitting
+/// doesn't cut a line off halfway into the buffer or the log file.
+fn stop_drain(drain: DrainTask) {
+    let _ = drain.stop.send(());
+    // The timer has to be made inside the runtime; the exit handler isn't in it.
+    let finished = tauri::async_runtime::block_on(async {
+        tokio::time::timeout(DRAIN_STOP_TIMEOUT, drain.task).await
+    });
+    if finished.is_err() {
+        warn!("Timed out waiting for sidecar output to be written");
+    }
+}
+
+/// Flush and close the log file on exit so the final lines, often the ones
+/// that explain a crash, aren't lost in the write buffer. Best effort: a
+/// stuck disk mustn't hold up quitting.
+fn close_log_file(app_handle: &AppHandle) {
+    let state = app_handle.state::<Mutex<SidecarState>>();
+    let Some(log_file) = state.lock().ok().map(|s| s.log_file.clone()) else {
+        return;
+    };
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        log_file.close();
+        let _ = done_tx.send(());
     });
+    if done_rx.recv_timeout(LOG_CLOSE_TIMEOUT).is_err() {
+        // Not through tracing: the line would only queue behind the stuck file.
+        let _ = writeln!(std::io::stderr(), "Timed out closing the sidecar log file");
+    }
 }
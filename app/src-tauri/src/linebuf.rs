@@ -0,0 +1,181 @@
+/// A complete line of output, without its terminator.
+#[derive(Debug)]
+pub(crate) struct Line {
+    pub text: String,
+    /// Bytes that weren't valid UTF-8 and were replaced with U+FFFD in `text`.
+    pub replaced: usize,
+    /// The bytes as received, only kept when some were replaced.
+    pub raw: Option<Vec<u8>>,
+    /// Bytes cut off the end of a line longer than the cap; `text` then ends
+    /// with `…[truncated N bytes]`.
+    pub truncated: usize,
+}
+
+/// Reassembles lines from output chunks that needn't end on a line, or even
+/// a character, boundary. Bytes are only decoded once a line is complete, so
+/// a multi-byte character split across chunks survives intact.
+///
+/// Lines are capped at `max_line` bytes before anything else sees them, so a
+/// sidecar printing a huge blob, or never printing a newline, costs at most
+/// that much here and downstream. The rest of such a line is only counted.
+pub(crate) struct LineBuffer {
+    /// The current line so far, kept to `max_line` bytes plus one, which
+    /// tells whether the cut falls inside a character.
+    pending: Vec<u8>,
+    /// Bytes of the current line dropped beyond `pending`.
+    dropped: usize,
+    max_line: usize,
+}
+
+impl LineBuffer {
+    pub fn new(max_line: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            dropped: 0,
+            max_line,
+        }
+    }
+
+    /// Add `chunk` and return the lines it completes, without their `\n` or
+    /// `\r\n` terminators.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            let line = &rest[..end];
+            self.take(line.strip_suffix(b"\r").unwrap_or(line));
+            lines.push(self.complete());
+            rest = &rest[end + 1..];
+        }
+        self.take(rest);
+        lines
+    }
+
+    /// The unterminated last line, if any, once the stream has ended.
+    pub fn finish(&mut self) -> Option<Line> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        Some(self.complete())
+    }
+
+    /// Add part of the current line, keeping no more than `pending` holds.
+    fn take(&mut self, bytes: &[u8]) {
+        let room = (self.max_line + 1).saturating_sub(self.pending.len());
+        let kept = bytes.len().min(room);
+        self.pending.extend_from_slice(&bytes[..kept]);
+        self.dropped += bytes.len() - kept;
+    }
+
+    /// The current line, truncated if it went over the cap, and start the next.
+    fn complete(&mut self) -> Line {
+        if self.pending.len() <= self.max_line {
+            self.dropped = 0;
+            let line = decode(&self.pending);
+            self.pending.clear();
+            return line;
+        }
+        let cut = char_boundary(&self.pending, 0, self.max_line);
+        let truncated = std::mem::take(&mut self.dropped) + self.pending.len() - cut;
+        let mut line = decode(&self.pending[..cut]);
+        line.text.push_str(&format!("…[truncated {truncated} bytes]"));
+        line.truncated = truncated;
+        self.pending.clear();
+        line
+    }
+}
+
+/// `end`, moved back so it doesn't fall inside a UTF-8 sequence (unless the
+/// bytes aren't UTF-8 anyway).
+fn char_boundary(bytes: &[u8], start: usize, end: usize) -> usize {
+    let mut cut = end;
+    while cut > start && end - cut < 4 && is_continuation(bytes[cut]) {
+        cut -= 1;
+    }
+    if cut == start || end - cut == 4 {
+        end
+    } else {
+        cut
+    }
+}
+
+fn is_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+/// Decode like `String::from_utf8_lossy`, but count what was replaced.
+fn decode(line: &[u8]) -> Line {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let mut text = String::with_capacity(line.len());
+    let mut replaced = 0;
+    for chunk in line.utf8_chunks() {
+        text.push_str(chunk.valid());
+        if !chunk.invalid().is_empty() {
+            text.push(char::REPLACEMENT_CHARACTER);
+            replaced += chunk.invalid().len();
+        }
+    }
+    Line {
+        text,
+        replaced,
+        raw: (replaced > 0).then(|| line.to_vec()),
+        truncated: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: Vec<Line>) -> Vec<String> {
+        lines.into_iter().map(|l| l.text).collect()
+    }
+
+    #[test]
+    fn joins_chunks_split_inside_a_character() {
+        let mut buf = LineBuffer::new(1024);
+        let bytes = "héllo wörld\r\nnext\n".as_bytes();
+        // Split inside the two-byte 'é'.
+        assert!(buf.push(&bytes[..2]).is_empty());
+        assert_eq!(texts(buf.push(&bytes[2..])), vec!["héllo wörld", "next"]);
+        assert!(buf.finish().is_none());
+    }
+
+    #[test]
+    fn flushes_a_line_without_trailing_newline() {
+        let mut buf = LineBuffer::new(1024);
+        assert_eq!(texts(buf.push(b"one\ntwo")), vec!["one"]);
+        assert_eq!(buf.finish().unwrap().text, "two");
+        assert!(buf.finish().is_none());
+    }
+
+    #[test]
+    fn truncates_long_lines_on_character_boundaries() {
+        let mut buf = LineBuffer::new(4);
+        assert_eq!(texts(buf.push(b"abcd\r\nabcde\n")), vec!["abcd", "abcd…[truncated 1 bytes]"]);
+
+        // 'é' would straddle the 4-byte cut, so it goes too.
+        assert!(buf.push("abcé".as_bytes()).is_empty());
+        let lines = buf.push(b"fghijk\r\n");
+        assert_eq!(lines[0].text, "abc…[truncated 8 bytes]");
+        assert_eq!(lines[0].truncated, 8);
+
+        // Only the cap is held on to while a line never ends.
+        for _ in 0..1000 {
+            assert!(buf.push(b"0123456789").is_empty());
+        }
+        assert!(buf.pending.len() <= 5);
+        assert_eq!(buf.finish().unwrap().text, "0123…[truncated 9996 bytes]");
+        assert!(buf.finish().is_none());
+    }
+
+    #[test]
+    fn counts_replaced_bytes_and_keeps_them_raw() {
+        let mut buf = LineBuffer::new(1024);
+        let lines = buf.push(b"ok\nbin \xff\xfe\xc3 end\n");
+        assert_eq!((lines[0].replaced, lines[0].raw.is_none()), (0, true));
+        assert_eq!(lines[1].text, "bin \u{fffd}\u{fffd}\u{fffd} end");
+        assert_eq!(lines[1].replaced, 3);
+        assert_eq!(lines[1].raw.as_deref(), Some(&b"bin \xff\xfe\xc3 end"[..]));
+    }
+}
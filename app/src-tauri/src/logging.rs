@@ -0,0 +1,435 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+use serde::{Serialize, Serializer};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+
+use crate::logwriter::LogWriter;
+use crate::now_unix_ms;
+
+/// Filter directive for Rust-side diagnostics, e.g. `debug` or
+/// `info,claudetini_app_lib::health=trace,sidecar=warn`.
+pub(crate) const FILTER_ENV: &str = "CLAUDETINI_LOG";
+/// Level the sidecar is spawned with, so it logs at it from the first line
+/// rather than only once `log-level` arrives on stdin.
+pub(crate) const SIDECAR_LEVEL_ENV: &str = "CLAUDETINI_SIDECAR_LOG_LEVEL";
+/// Target for the sidecar's own output, echoed as `[sidecar] ...`.
+pub(crate) const SIDECAR_TARGET: &str = "sidecar";
+/// Target for supervisor lifecycle lines, which also go to the log buffer.
+pub(crate) const SUPERVISOR_TARGET: &str = "supervisor";
+/// File in the app config dir holding the level picked with `set_log_level`.
+const LEVEL_FILE_NAME: &str = "log-level";
+
+thread_local! {
+    /// Spans entered on this thread, innermost last.
+    static CURRENT_SPANS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Which levels to log: a default plus per-target overrides, where a target
+/// also covers its `::` children.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LogFilter {
+    default: LevelFilter,
+    /// Most specific target first.
+    targets: Vec<(String, LevelFilter)>,
+}
+
+/// Serialized as its directive string, e.g. `info,sidecar=warn`.
+impl Serialize for LogFilter {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            default: LevelFilter::INFO,
+            targets: Vec::new(),
+        }
+    }
+}
+
+/// Parses comma-separated `level` and `target=level` directives.
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level = |raw: &str| {
+            raw.trim().parse::<LevelFilter>().map_err(|_| {
+                format!(
+                    "{raw:?} is not a log level (expected off, error, warn, info, debug or trace)"
+                )
+            })
+        };
+        let mut filter = LogFilter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, raw)) if !target.trim().is_empty() => {
+                    filter.targets.push((target.trim().to_string(), level(raw)?));
+                }
+                Some(_) => return Err(format!("{directive:?} is missing a target")),
+                None => filter.default = level(directive)?,
+            }
+        }
+        filter.targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.default.to_string().to_ascii_lowercase())?;
+        for (target, level) in &self.targets {
+            write!(f, ",{target}={}", level.to_string().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+impl LogFilter {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= &self.level_for(metadata.target())
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|(_, level)| *level).fold(self.default, LevelFilter::max)
+    }
+
+    /// This filter with `level` as the default; per-target overrides stay.
+    pub fn with_default(mut self, level: LogLevel) -> Self {
+        self.default = level.into();
+        self
+    }
+}
+
+/// A level the user can pick for both the app and the sidecar.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!(
+                "{s:?} is not a log level (expected error, warn, info, debug or trace)"
+            )),
+        }
+    }
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// The level saved by `set_log_level`, if any. An unreadable file is ignored.
+pub(crate) fn read_saved_level(config_dir: &Path) -> Option<LogLevel> {
+    let path = config_dir.join(LEVEL_FILE_NAME);
+    let raw = fs::read_to_string(&path).ok()?;
+    match raw.parse() {
+        Ok(level) => Some(level),
+        Err(e) => {
+            tracing::warn!("Ignoring {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+pub(crate) fn save_level(config_dir: &Path, level: LogLevel) -> io::Result<()> {
+    fs::write(config_dir.join(LEVEL_FILE_NAME), format!("{}\n", level.as_str()))
+}
+
+struct SpanData {
+    name: &'static str,
+    level: tracing::Level,
+    fields: String,
+    started: Instant,
+    refs: usize,
+}
+
+/// Writes events to stderr and the sidecar log file, with the names and
+/// fields of the enclosing spans, and reports how long each span was open.
+struct Logger {
+    filter: RwLock<LogFilter>,
+    file: LogWriter,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+}
+
+impl Logger {
+    /// `outer{field=1}:inner: ` for the spans entered on this thread.
+    fn context(&self) -> String {
+        let Ok(spans) = self.spans.lock() else {
+            return String::new();
+        };
+        CURRENT_SPANS.with(|stack| {
+            let mut context = String::new();
+            for span in stack.borrow().iter().filter_map(|id| spans.get(id)) {
+                let _ = write!(context, "{}", span.name);
+                if !span.fields.is_empty() {
+                    let _ = write!(context, "{{{}}}", span.fields.trim_start());
+                }
+                context.push_str(": ");
+            }
+            context
+        })
+    }
+
+    /// Supervisor lines are persisted with the sidecar's output, so those
+    /// are only echoed.
+    fn emit(&self, level: &tracing::Level, target: &str, line: &str) {
+        let _ = writeln!(io::stderr().lock(), "{level:>5} {line}");
+        if target != SUPERVISOR_TARGET {
+            self.file.append("app", now_unix_ms(), &format!("{level} {line}"));
+        }
+    }
+}
+
+impl Subscriber for Logger {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if self.enabled(metadata) {
+            Interest::always()
+        } else {
+            Interest::never()
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.filter.read().is_ok_and(|filter| filter.enabled(metadata))
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.filter.read().ok().map(|filter| filter.max_level())
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let data = SpanData {
+            name: attrs.metadata().name(),
+            level: *attrs.metadata().level(),
+            fields: fields.rest,
+            started: Instant::now(),
+            refs: 1,
+        };
+        if let Ok(mut spans) = self.spans.lock() {
+            spans.insert(id, data);
+        }
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        if let Ok(mut spans) = self.spans.lock() {
+            if let Some(data) = spans.get_mut(&span.into_u64()) {
+                data.fields.push_str(&fields.rest);
+            }
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let metadata = event.metadata();
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        // Sidecar output is already in the log file; only echo it, prefix intact.
+        if metadata.target() == SIDECAR_TARGET {
+            let _ = writeln!(io::stderr().lock(), "{}", fields.message);
+            return;
+        }
+        let line = format!("{}{}{}", self.context(), fields.message, fields.rest);
+        self.emit(metadata.level(), metadata.target(), &line);
+    }
+
+    fn enter(&self, span: &Id) {
+        CURRENT_SPANS.with(|stack| stack.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        CURRENT_SPANS.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            if let Some(at) = stack.iter().rposition(|id| *id == span.into_u64()) {
+                stack.remove(at);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Ok(mut spans) = self.spans.lock() {
+            if let Some(data) = spans.get_mut(&span.into_u64()) {
+                data.refs += 1;
+            }
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let closed = {
+            let Ok(mut spans) = self.spans.lock() else {
+                return false;
+            };
+            let Some(data) = spans.get_mut(&span.into_u64()) else {
+                return false;
+            };
+            data.refs -= 1;
+            if data.refs > 0 {
+                return false;
+            }
+            spans.remove(&span.into_u64())
+        };
+        if let Some(data) = closed {
+            let took = data.started.elapsed().as_millis();
+            let line = format!("{}{}{} took {took}ms", self.context(), data.name, data.fields);
+            self.emit(&data.level, "", &line);
+        }
+        true
+    }
+}
+
+/// Splits an event's or span's fields into its message and the rest.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.rest, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// Install the global subscriber, filtered by `CLAUDETINI_LOG` until the
+/// settings file has been read. Returns the log file slot it writes to.
+pub(crate) fn init() -> LogWriter {
+    let filter = std::env::var(FILTER_ENV)
+        .ok()
+        .and_then(|raw| raw.parse().ok())
+        .unwrap_or_default();
+    let file = LogWriter::spawn();
+    let logger = Logger {
+        filter: RwLock::new(filter),
+        file: file.clone(),
+        spans: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+    };
+    if tracing::subscriber::set_global_default(logger).is_err() {
+        let _ = writeln!(io::stderr().lock(), "A tracing subscriber is already installed");
+    }
+    file
+}
+
+/// The filter the subscriber is using right now.
+pub(crate) fn current_filter() -> Option<LogFilter> {
+    tracing::dispatcher::get_default(|dispatch| {
+        let logger = dispatch.downcast_ref::<Logger>()?;
+        logger.filter.read().ok().map(|filter| filter.clone())
+    })
+}
+
+/// Replace the active filter; takes effect for every callsite immediately.
+pub(crate) fn set_filter(filter: &LogFilter) {
+    tracing::dispatcher::get_default(|dispatch| {
+        if let Some(mut current) =
+            dispatch.downcast_ref::<Logger>().and_then(|logger| logger.filter.write().ok())
+        {
+            *current = filter.clone();
+        }
+    });
+    tracing::callsite::rebuild_interest_cache();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_directives_and_matches_targets() {
+        let filter: LogFilter = "warn, claudetini_app_lib=debug,claudetini_app_lib::health=off"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.level_for("claudetini_app_lib::health"), LevelFilter::OFF);
+        assert_eq!(filter.level_for("claudetini_app_lib::ports"), LevelFilter::DEBUG);
+        assert_eq!(filter.level_for("claudetini_app_lib_other"), LevelFilter::WARN);
+        assert_eq!(filter.max_level(), LevelFilter::DEBUG);
+        assert_eq!(
+            filter.to_string(),
+            "warn,claudetini_app_lib::health=off,claudetini_app_lib=debug"
+        );
+
+        assert_eq!("".parse::<LogFilter>(), Ok(LogFilter::default()));
+        assert!("verbose".parse::<LogFilter>().is_err());
+        assert!("=debug".parse::<LogFilter>().is_err());
+    }
+
+    #[test]
+    fn picked_level_keeps_target_overrides() {
+        let filter: LogFilter = "info,sidecar=warn".parse().unwrap();
+        let level: LogLevel = "DEBUG".parse().unwrap();
+        assert_eq!(filter.with_default(level).to_string(), "debug,sidecar=warn");
+        assert!("off".parse::<LogLevel>().is_err());
+    }
+}
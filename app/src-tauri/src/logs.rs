@@ -0,0 +1,755 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone};
+use serde::Serialize;
+
+/// Each app launch logs to its own file in the app log dir, named for when
+/// it started: `claudetini-2024-05-30T101502.log`.
+const SESSION_PREFIX: &str = "claudetini-";
+const SESSION_SUFFIX: &str = ".log";
+const SESSION_STAMP: &str = "%Y-%m-%dT%H%M%S";
+/// The single log file used before there was one per launch. Leftover
+/// copies are listed and pruned as older than any session.
+const LEGACY_LOG_NAME: &str = "sidecar.log";
+
+/// Where sidecar output is persisted for the session started at `started`.
+pub(crate) fn session_log_path(log_dir: &Path, started: DateTime<Local>) -> PathBuf {
+    let stamp = started.format(SESSION_STAMP);
+    log_dir.join(format!("{SESSION_PREFIX}{stamp}{SESSION_SUFFIX}"))
+}
+
+/// The session a log file name belongs to (its start timestamp, empty for
+/// the legacy log) and its rotation number, 0 for the session's own file.
+/// `None` for anything that isn't one of our log files.
+fn parse_log_name(name: &str) -> Option<(&str, u32)> {
+    let (base, n) = match name.rsplit_once('.') {
+        Some((base, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => {
+            (base, n.parse().ok().filter(|&n| n > 0)?)
+        }
+        _ => (name, 0),
+    };
+    if base == LEGACY_LOG_NAME {
+        return Some(("", n));
+    }
+    let stamp = base.strip_prefix(SESSION_PREFIX)?.strip_suffix(SESSION_SUFFIX)?;
+    NaiveDateTime::parse_from_str(stamp, SESSION_STAMP).ok()?;
+    Some((stamp, n))
+}
+
+/// Persisted lines look like
+///
+/// ```text
+/// 2026-10-14T09:21:07.123+02:00 out g3 INFO:     Uvicorn running on http://127.0.0.1:8123
+/// ```
+///
+/// The first three space-separated fields are stable, for the diagnostics
+/// bundle and anything else that parses the file: RFC 3339 local time with
+/// milliseconds and the UTC offset; the stream tag from `stream_tag`; and `g`
+/// with the sidecar generation, bumped on every spawn (`g0` before the
+/// first). The rest is the line exactly as received, after secrets were
+/// masked; app lines start with their level.
+pub(crate) fn format_line(timestamp_ms: u64, tag: &str, generation: u64, line: &str) -> String {
+    let at = Local
+        .timestamp_millis_opt(timestamp_ms as i64)
+        .single()
+        .unwrap_or_else(Local::now);
+    let at = at.to_rfc3339_opts(SecondsFormat::Millis, false);
+    format!("{at} {tag} g{generation} {line}")
+}
+
+/// `out` and `err` for the sidecar's stdout and stderr (shell plugin errors
+/// count as stderr), `app` for the supervisor and the app's own logging.
+pub(crate) fn stream_tag(stream: &str) -> &'static str {
+    match stream {
+        "stdout" => "out",
+        "stderr" | "error" => "err",
+        _ => "app",
+    }
+}
+
+/// `<file>.<n>`, the `n`th most recent rotated-out copy of `path`.
+pub(crate) fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+/// When the log file is rotated and how much old output is kept.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub(crate) struct LogRotation {
+    /// Size at which the active file is rotated out.
+    pub max_bytes: u64,
+    /// Older files kept beside the active one, counting earlier sessions'
+    /// files and copies rotated out of any of them; the oldest beyond that
+    /// are deleted.
+    pub keep_files: u32,
+    /// Budget for the active file and its rotated copies together; the
+    /// oldest copies are deleted to stay within it.
+    pub max_total_bytes: u64,
+}
+
+/// A log file found in the log dir.
+struct FoundFile {
+    session: String,
+    n: u32,
+    name: String,
+    path: PathBuf,
+    len: u64,
+    modified_ms: Option<u64>,
+}
+
+/// Every log file in `dir`, newest first: sessions by when they started,
+/// each one's own file before the copies rotated out of it. Anything else in
+/// the directory is left alone, and a missing directory has no files.
+fn log_files(dir: &Path) -> io::Result<Vec<FoundFile>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else { continue };
+        let Some((session, n)) = parse_log_name(&name) else { continue };
+        let session = session.to_string();
+        let Ok(metadata) = entry.metadata() else { continue };
+        if metadata.is_file() {
+            let modified_ms = metadata.modified().ok().and_then(|t| {
+                t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_millis() as u64)
+            });
+            files.push(FoundFile {
+                session,
+                n,
+                name,
+                path: entry.path(),
+                len: metadata.len(),
+                modified_ms,
+            });
+        }
+    }
+    files.sort_by(|a, b| b.session.cmp(&a.session).then(a.n.cmp(&b.n)));
+    Ok(files)
+}
+
+/// A log file as `list_log_files` shows it.
+#[derive(Debug, Serialize)]
+pub(crate) struct LogFileInfo {
+    pub name: String,
+    pub size_bytes: u64,
+    /// Last modified, in Unix milliseconds.
+    pub modified_ms: Option<u64>,
+    /// Whether this session is writing to it.
+    pub active: bool,
+}
+
+/// The log files in `dir`, newest first, marking `active` if it's among them.
+pub(crate) fn list_files(dir: &Path, active: Option<&Path>) -> io::Result<Vec<LogFileInfo>> {
+    Ok(log_files(dir)?
+        .into_iter()
+        .map(|f| LogFileInfo {
+            active: active.is_some_and(|p| p == f.path),
+            name: f.name,
+            size_bytes: f.len,
+            modified_ms: f.modified_ms,
+        })
+        .collect())
+}
+
+/// `name` in `dir`, if it's one of the log files there. Anything else, a
+/// path with separators or `..` or a link out of the directory included, is
+/// refused, so callers can't be talked into reading arbitrary files.
+pub(crate) fn resolve_log_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let mut components = Path::new(name).components();
+    if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+        return None;
+    }
+    parse_log_name(name)?;
+    let path = dir.join(name);
+    let resolved = path.canonicalize().ok()?;
+    resolved.starts_with(dir.canonicalize().ok()?).then_some(path)
+}
+
+/// How much disk the sidecar log is using, for `get_log_usage`.
+#[derive(Debug, Serialize)]
+pub(crate) struct LogUsage {
+    pub path: PathBuf,
+    pub active_bytes: u64,
+    /// Earlier sessions' files and copies rotated out of this one's.
+    pub rotated_files: usize,
+    /// The active file plus every older one.
+    pub total_bytes: u64,
+    pub keep_files: u32,
+    pub max_total_bytes: u64,
+    /// Lines never written because the disk couldn't keep up, this session.
+    pub dropped_lines: u64,
+    /// Lines of sidecar output cut short for being over the line cap, this
+    /// session.
+    pub truncated_lines: u64,
+}
+
+pub(crate) fn usage(path: &Path, rotation: LogRotation) -> io::Result<LogUsage> {
+    let active_bytes = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+    let older = older_files(path)?;
+    Ok(LogUsage {
+        path: path.to_path_buf(),
+        active_bytes,
+        rotated_files: older.len(),
+        total_bytes: active_bytes + older.iter().map(|f| f.len).sum::<u64>(),
+        keep_files: rotation.keep_files,
+        max_total_bytes: rotation.max_total_bytes,
+        dropped_lines: 0,
+        truncated_lines: 0,
+    })
+}
+
+/// Every log file beside the active one at `path`, newest first.
+fn older_files(path: &Path) -> io::Result<Vec<FoundFile>> {
+    let Some(dir) = path.parent() else {
+        return Ok(Vec::new());
+    };
+    let mut files = log_files(dir)?;
+    files.retain(|f| f.path != path);
+    Ok(files)
+}
+
+/// Appends sidecar output to the log file, so history outlives the in-memory
+/// buffer and app restarts. Writes are buffered; call `flush` once the
+/// sidecar exits so the last lines reach disk.
+pub(crate) struct LogFile {
+    path: PathBuf,
+    /// `None` only while rotating, between closing the old file and opening
+    /// the new one.
+    writer: Option<BufWriter<File>>,
+    /// Size of the active file, including bytes still in the buffer.
+    len: u64,
+    rotation: LogRotation,
+}
+
+impl LogFile {
+    pub fn open(path: &Path, rotation: LogRotation) -> io::Result<Self> {
+        let file = open_append(path)?;
+        let len = file.metadata()?.len();
+        let log = Self {
+            path: path.to_path_buf(),
+            writer: Some(BufWriter::new(file)),
+            len,
+            rotation,
+        };
+        // Limits may have shrunk since the last run. Best effort: a copy that
+        // can't be deleted now is retried at the next rotation.
+        let _ = log.prune();
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&mut self, line: &str) -> io::Result<()> {
+        let size = line.len() as u64 + 1;
+        if self.len > 0 && self.len + size > self.rotation.max_bytes {
+            self.rotate()?;
+        }
+        let writer = self.writer.as_mut().ok_or_else(closed)?;
+        writeln!(writer, "{line}")?;
+        self.len += size;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().ok_or_else(closed)?.flush()
+    }
+
+    /// Flush and sync to disk, for when the app is about to exit.
+    pub fn close(mut self) -> io::Result<()> {
+        let writer = self.writer.take().ok_or_else(closed)?;
+        writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()
+    }
+
+    /// Shift the session's `.<n>` copies up by one, dropping the oldest, and
+    /// start a fresh active file.
+    fn rotate(&mut self) -> io::Result<()> {
+        // Close the active file first; Windows won't rename an open file.
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        let keep = self.rotation.keep_files;
+        if keep == 0 {
+            remove_if_exists(&self.path)?;
+        } else {
+            remove_if_exists(&rotated_path(&self.path, keep))?;
+            for n in (1..keep).rev() {
+                rename_if_exists(&rotated_path(&self.path, n), &rotated_path(&self.path, n + 1))?;
+            }
+            rename_if_exists(&self.path, &rotated_path(&self.path, 1))?;
+        }
+        self.writer = Some(BufWriter::new(open_append(&self.path)?));
+        self.len = 0;
+        self.prune()
+    }
+
+    /// Delete older files, earlier sessions' included, beyond the newest
+    /// `keep_files`, and then, oldest first, any that don't fit in
+    /// `max_total_bytes` beside the active file. The active file itself is
+    /// never deleted.
+    fn prune(&self) -> io::Result<()> {
+        let mut total = self.len;
+        let mut over_budget = false;
+        for (kept, file) in older_files(&self.path)?.into_iter().enumerate() {
+            over_budget = over_budget
+                || kept >= self.rotation.keep_files as usize
+                || total + file.len > self.rotation.max_total_bytes;
+            if over_budget {
+                remove_if_exists(&file.path)?;
+            } else {
+                total += file.len;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn closed() -> io::Error {
+    io::Error::other("log file was not reopened after rotating")
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Keys a structured sidecar log line may carry its level and message under.
+const LEVEL_KEYS: &[&str] = &["level", "levelname", "severity"];
+const MESSAGE_KEYS: &[&str] = &["msg", "message", "event"];
+
+/// Severity of a structured sidecar log line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn parse(level: &str) -> Option<Self> {
+        match level.to_ascii_lowercase().as_str() {
+            "trace" => Some(LogLevel::Trace),
+            "debug" => Some(LogLevel::Debug),
+            "info" | "notice" => Some(LogLevel::Info),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "error" | "err" | "critical" | "fatal" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+/// One line of sidecar output, kept in the session buffer and streamed to
+/// `subscribe_sidecar_logs` subscribers.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LogLine {
+    /// `stdout`, `stderr`, `error` (from the shell plugin) or `supervisor`.
+    pub stream: &'static str,
+    /// The line exactly as received, after secrets were masked.
+    pub line: String,
+    pub timestamp: u64,
+    /// Level of a structured line, when it named one we recognize.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<LogLevel>,
+    /// Every field of a line that was a JSON object, e.g. ndjson logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Bytes that weren't valid UTF-8 and show up as U+FFFD in `line`.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub replaced_bytes: usize,
+    /// Such a line as received, hex-encoded, when `log_raw_bytes` is on.
+    /// Secrets in its valid parts were masked too.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "as_hex")]
+    pub raw: Option<Vec<u8>>,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+fn as_hex<S: serde::Serializer>(raw: &Option<Vec<u8>>, s: S) -> Result<S::Ok, S::Error> {
+    let hex: Option<String> =
+        raw.as_ref().map(|bytes| bytes.iter().map(|b| format!("{b:02x}")).collect());
+    hex.serialize(s)
+}
+
+impl LogLine {
+    /// Wrap `line`, parsing it as a structured record if it is a JSON object;
+    /// anything else, malformed JSON included, stays plain text.
+    pub fn new(stream: &'static str, line: String, timestamp: u64) -> Self {
+        let fields = line
+            .trim_start()
+            .starts_with('{')
+            .then(|| serde_json::from_str(&line).ok())
+            .flatten()
+            .and_then(|value| match value {
+                serde_json::Value::Object(fields) => Some(fields),
+                _ => None,
+            });
+        let level = fields
+            .as_ref()
+            .and_then(|f| LEVEL_KEYS.iter().find_map(|k| f.get(*k)?.as_str()))
+            .and_then(LogLevel::parse);
+        Self {
+            stream,
+            line,
+            timestamp,
+            level,
+            fields,
+            replaced_bytes: 0,
+            raw: None,
+        }
+    }
+
+    /// The level it was logged at, or for a plain line, info on stdout and
+    /// warn anywhere else.
+    pub fn severity(&self) -> LogLevel {
+        self.level.unwrap_or(if self.stream == "stdout" {
+            LogLevel::Info
+        } else {
+            LogLevel::Warn
+        })
+    }
+
+    /// What the line costs `LogBuffer`.
+    fn size(&self) -> usize {
+        self.line.len() + self.raw.as_ref().map_or(0, Vec::len)
+    }
+
+    /// The human-readable part: the message of a structured line, otherwise
+    /// the whole line.
+    pub fn message(&self) -> &str {
+        self.fields
+            .as_ref()
+            .and_then(|f| MESSAGE_KEYS.iter().find_map(|k| f.get(*k)?.as_str()))
+            .unwrap_or(&self.line)
+    }
+}
+
+/// How much recent output `LogBuffer` holds.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub(crate) struct LogBufferLimits {
+    pub max_lines: usize,
+    /// Total bytes of line text and raw bytes; a single oversized line is
+    /// still kept.
+    pub max_bytes: usize,
+}
+
+/// Recent output in memory, oldest first, for diagnostics and the log panel.
+pub(crate) struct LogBuffer {
+    lines: VecDeque<LogLine>,
+    bytes: usize,
+    limits: LogBufferLimits,
+}
+
+impl LogBuffer {
+    pub fn new(limits: LogBufferLimits) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            bytes: 0,
+            limits,
+        }
+    }
+
+    /// Append `line`, dropping the oldest lines until both limits hold.
+    pub fn push(&mut self, line: LogLine) {
+        self.bytes += line.size();
+        self.lines.push_back(line);
+        while self.lines.len() > self.limits.max_lines
+            || (self.bytes > self.limits.max_bytes && self.lines.len() > 1)
+        {
+            if let Some(old) = self.lines.pop_front() {
+                self.bytes -= old.size();
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The newest `limit` lines from `stream` (any stream if `None`), oldest first.
+    pub fn tail(&self, limit: usize, stream: Option<&str>) -> Vec<LogLine> {
+        let mut tail: Vec<LogLine> = self
+            .lines
+            .iter()
+            .rev()
+            .filter(|l| stream.is_none_or(|s| l.stream == s))
+            .take(limit)
+            .cloned()
+            .collect();
+        tail.reverse();
+        tail
+    }
+}
+
+/// The end of the log file, as returned to the frontend.
+#[derive(Debug, Serialize)]
+pub(crate) struct LogTail {
+    pub path: PathBuf,
+    /// False if nothing has been logged yet; `content` is then empty.
+    pub exists: bool,
+    /// Whether earlier content was cut off to respect the byte or line cap.
+    pub truncated: bool,
+    pub content: String,
+}
+
+impl LogTail {
+    /// Cut the tail down to its last `lines` lines.
+    pub fn last_lines(mut self, lines: usize) -> Self {
+        let count = self.content.split_inclusive('\n').count();
+        if count > lines {
+            self.content = self.content.split_inclusive('\n').skip(count - lines).collect();
+            self.truncated = true;
+        }
+        self
+    }
+}
+
+/// Read at most `max_bytes` from the end of the file at `path`. When cut
+/// short, the partial first line is dropped so the tail starts cleanly.
+pub(crate) fn read_tail(path: &Path, max_bytes: u64) -> io::Result<LogTail> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(LogTail {
+                path: path.to_path_buf(),
+                exists: false,
+                truncated: false,
+                content: String::new(),
+            });
+        }
+        Err(e) => return Err(e),
+    };
+
+    let len = fs::metadata(path)?.len();
+    let start = len.saturating_sub(max_bytes);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::with_capacity((len - start) as usize);
+    file.take(max_bytes).read_to_end(&mut bytes)?;
+
+    let truncated = start > 0;
+    if truncated {
+        let line_start = bytes.iter().position(|&b| b == b'\n').map_or(0, |i| i + 1);
+        bytes.drain(..line_start);
+    }
+    Ok(LogTail {
+        path: path.to_path_buf(),
+        exists: true,
+        truncated,
+        content: String::from_utf8_lossy(&bytes).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    fn session(stamp: &str) -> String {
+        format!("claudetini-{stamp}.log")
+    }
+
+    #[test]
+    fn formats_persisted_lines() {
+        let line = format_line(1_700_000_000_123, stream_tag("stderr"), 3, "Traceback: x y");
+        let (at, rest) = line.split_once(' ').unwrap();
+        let at = chrono::DateTime::parse_from_rfc3339(at).unwrap();
+        assert_eq!(at.timestamp_millis(), 1_700_000_000_123);
+        assert_eq!(rest, "err g3 Traceback: x y");
+        assert_eq!(stream_tag("supervisor"), "app");
+    }
+
+    #[test]
+    fn missing_file_is_an_empty_tail() {
+        let dir = scratch_dir("never-written");
+        let tail = read_tail(&dir.join("sidecar.log"), 1024).unwrap();
+        assert!(!tail.exists);
+        assert!(tail.content.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    const NO_ROTATION: LogRotation = LogRotation {
+        max_bytes: u64::MAX,
+        keep_files: 0,
+        max_total_bytes: u64::MAX,
+    };
+
+    #[test]
+    fn tail_respects_cap_and_starts_on_a_line() {
+        let dir = scratch_dir("logs-tail");
+        let path = dir.join(session("2024-05-30T101502"));
+        let mut log = LogFile::open(&path, NO_ROTATION).unwrap();
+        for i in 0..100 {
+            log.append(&format!("[stdout] line {i}")).unwrap();
+        }
+        log.flush().unwrap();
+
+        let tail = read_tail(&path, 40).unwrap();
+        assert!(tail.truncated);
+        assert!(tail.content.len() <= 40);
+        assert!(tail.content.starts_with("[stdout]"));
+        assert!(tail.content.ends_with("line 99\n"));
+
+        let whole = read_tail(&path, u64::MAX).unwrap();
+        assert!(!whole.truncated);
+        assert!(whole.content.starts_with("[stdout] line 0\n"));
+
+        let last = read_tail(&path, u64::MAX).unwrap().last_lines(2);
+        assert!(last.truncated);
+        assert_eq!(last.content, "[stdout] line 98\n[stdout] line 99\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn buffer_caps_lines_and_bytes() {
+        let line = |stream, text: &str| LogLine::new(stream, text.to_string(), 0);
+        let mut buffer = LogBuffer::new(LogBufferLimits {
+            max_lines: 3,
+            max_bytes: 10,
+        });
+        for text in ["aa", "bb", "cc", "dd"] {
+            buffer.push(line("stdout", text));
+        }
+        buffer.push(line("stderr", "eeee"));
+        let texts = |b: &LogBuffer| b.tail(usize::MAX, None).into_iter().map(|l| l.line);
+        assert!(texts(&buffer).eq(["cc", "dd", "eeee"]));
+
+        buffer.push(line("stderr", "ffffffff"));
+        assert!(texts(&buffer).eq(["ffffffff"]));
+
+        buffer.push(line("stdout", "g"));
+        let tail = buffer.tail(5, Some("stderr"));
+        assert_eq!(tail.len(), 1);
+        assert_eq!(tail[0].line, "ffffffff");
+    }
+
+    #[test]
+    fn parses_structured_lines_and_passes_others_through() {
+        let raw = r#"{"level":"WARNING","msg":"disk low","pct":91}"#;
+        let json = LogLine::new("stderr", raw.into(), 0);
+        assert_eq!(json.level, Some(LogLevel::Warn));
+        assert_eq!(json.message(), "disk low");
+        assert_eq!(json.fields.unwrap()["pct"], 91);
+
+        for plain in ["INFO: started", r#"{"level":"info","#, "[1, 2]"] {
+            let line = LogLine::new("stdout", plain.into(), 0);
+            assert!(line.level.is_none() && line.fields.is_none());
+            assert_eq!(line.message(), plain);
+        }
+    }
+
+    #[test]
+    fn rotation_keeps_the_newest_copies() {
+        let dir = scratch_dir("logs-rotate");
+        let path = dir.join(session("2024-05-30T101502"));
+        let rotation = LogRotation {
+            max_bytes: 20,
+            keep_files: 2,
+            max_total_bytes: u64::MAX,
+        };
+        let mut log = LogFile::open(&path, rotation).unwrap();
+        // Each line is 10 bytes with its newline, so every file holds two.
+        for i in 0..8 {
+            log.append(&format!("line {i:04}")).unwrap();
+        }
+        log.flush().unwrap();
+
+        let read = |p: &Path| fs::read_to_string(p).unwrap();
+        assert_eq!(read(&path), "line 0006\nline 0007\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "line 0004\nline 0005\n");
+        assert_eq!(read(&rotated_path(&path, 2)), "line 0002\nline 0003\n");
+        assert!(!rotated_path(&path, 3).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn startup_prunes_across_sessions_and_spares_other_files() {
+        let dir = scratch_dir("logs-prune");
+        let older = [
+            session("2024-05-29T080000"),
+            session("2024-05-29T080000") + ".1",
+            session("2024-05-28T080000"),
+            "sidecar.log".to_string(),
+        ];
+        for name in &older {
+            fs::write(dir.join(name), "123456789\n").unwrap();
+        }
+        let strays = ["sidecar.log.bak", "claudetini-notes.log", "notes.txt"];
+        for name in strays {
+            fs::write(dir.join(name), "keep me\n").unwrap();
+        }
+
+        let path = session_log_path(&dir, Local.with_ymd_and_hms(2024, 5, 30, 10, 15, 2).unwrap());
+        assert_eq!(path, dir.join(session("2024-05-30T101502")));
+        let rotation = LogRotation {
+            max_bytes: 1024,
+            keep_files: 3,
+            max_total_bytes: 25,
+        };
+        let _log = LogFile::open(&path, rotation).unwrap();
+        let files = list_files(&dir, Some(&path)).unwrap();
+        let names: Vec<_> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, [session("2024-05-30T101502"), older[0].clone(), older[1].clone()]);
+        assert!(files[0].active && !files[1].active);
+        assert!(strays.iter().all(|name| dir.join(name).exists()));
+
+        let usage = usage(&path, rotation).unwrap();
+        assert_eq!((usage.active_bytes, usage.rotated_files, usage.total_bytes), (0, 2, 20));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_only_log_files_inside_the_dir() {
+        let dir = scratch_dir("logs-resolve");
+        let name = session("2024-05-30T101502");
+        fs::write(dir.join(&name), "hello\n").unwrap();
+        fs::write(dir.join("notes.txt"), "private\n").unwrap();
+
+        assert_eq!(resolve_log_file(&dir, &name), Some(dir.join(&name)));
+        let sneaky = format!("../{}/{name}", dir.file_name().unwrap().to_string_lossy());
+        for name in [sneaky.as_str(), "notes.txt", "/etc/passwd", "..", "sidecar.log"] {
+            assert_eq!(resolve_log_file(&dir, name), None, "{name}");
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
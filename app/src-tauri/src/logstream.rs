@@ -0,0 +1,253 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Serialize;
+use tauri::ipc::Channel;
+use tokio::sync::Notify;
+
+use crate::logs::{LogLevel, LogLine};
+
+/// Longest a line waits before its batch is sent.
+pub(crate) const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+/// Queued lines that trigger an early send.
+const FLUSH_LINES: usize = 50;
+/// Minimum spacing between batches, so bursty output can't flood the IPC bridge.
+pub(crate) const MIN_FLUSH_GAP: Duration = Duration::from_millis(50);
+/// Lines queued between sends; anything beyond is dropped and counted.
+const MAX_PENDING_LINES: usize = 500;
+
+/// Lines sent to log subscribers in one go.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LogBatch {
+    pub lines: Vec<LogLine>,
+    /// Lines dropped since the previous batch because output outran the stream.
+    pub dropped: u64,
+}
+
+/// Where a subscriber wants its batches.
+#[derive(Clone)]
+pub(crate) enum LogSink {
+    Channel(Channel<LogBatch>),
+    /// The global `sidecar-log` event.
+    Event,
+}
+
+/// Which lines a subscriber wants. The default passes everything.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LineFilter {
+    /// Skip lines below this `LogLine::severity`.
+    min_level: Option<LogLevel>,
+    pattern: Option<Pattern>,
+}
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl LineFilter {
+    /// Lines at `min_level` or above that contain `matches`, or match it as
+    /// a regex when `regex` is set.
+    pub fn new(
+        min_level: Option<LogLevel>,
+        matches: Option<String>,
+        regex: bool,
+    ) -> Result<Self, String> {
+        let pattern = match matches.filter(|m| !m.is_empty()) {
+            Some(m) if regex => {
+                let re = Regex::new(&m).map_err(|e| format!("invalid log filter regex: {e}"))?;
+                Some(Pattern::Regex(re))
+            }
+            Some(m) => Some(Pattern::Substring(m)),
+            None => None,
+        };
+        Ok(Self { min_level, pattern })
+    }
+
+    pub fn accepts(&self, line: &LogLine) -> bool {
+        if self.min_level.is_some_and(|min| line.severity() < min) {
+            return false;
+        }
+        match &self.pattern {
+            None => true,
+            Some(Pattern::Substring(s)) => line.line.contains(s.as_str()),
+            Some(Pattern::Regex(re)) => re.is_match(&line.line),
+        }
+    }
+}
+
+struct Subscriber {
+    id: u32,
+    sink: LogSink,
+    filter: LineFilter,
+}
+
+/// Live log subscribers and the lines queued for them. Lines are only queued
+/// while someone wants them, and the batching task only runs while someone
+/// is subscribed. Each subscriber's filter applies before batching.
+pub(crate) struct LogStream {
+    subscribers: Vec<Subscriber>,
+    next_id: u32,
+    pending: Vec<LogLine>,
+    dropped: u64,
+    running: bool,
+    /// Wakes the batching task when a batch fills up early.
+    pub full: Arc<Notify>,
+}
+
+impl LogStream {
+    pub fn new() -> Self {
+        Self {
+            subscribers: Vec::new(),
+            next_id: 1,
+            pending: Vec::new(),
+            dropped: 0,
+            running: false,
+            full: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Add a subscriber. Returns its id, and whether the caller must start the
+    /// batching task because none is running.
+    pub fn subscribe(&mut self, sink: LogSink, filter: LineFilter) -> (u32, bool) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.subscribers.push(Subscriber { id, sink, filter });
+        let start = !std::mem::replace(&mut self.running, true);
+        (id, start)
+    }
+
+    pub fn unsubscribe(&mut self, id: u32) {
+        self.subscribers.retain(|sub| sub.id != id);
+    }
+
+    /// Filter subscriber `id`'s batches with `filter` from the next one on.
+    /// Returns whether there is such a subscriber.
+    pub fn set_filter(&mut self, id: u32, filter: LineFilter) -> bool {
+        match self.subscribers.iter_mut().find(|sub| sub.id == id) {
+            Some(sub) => {
+                sub.filter = filter;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_watched(&self) -> bool {
+        !self.subscribers.is_empty()
+    }
+
+    /// Queue `line` for the next batch, waking the task if the batch is full.
+    pub fn push(&mut self, line: LogLine) {
+        if !self.subscribers.iter().any(|sub| sub.filter.accepts(&line)) {
+            return;
+        }
+        if self.pending.len() >= MAX_PENDING_LINES {
+            self.dropped += 1;
+            return;
+        }
+        self.pending.push(line);
+        if self.pending.len() == FLUSH_LINES {
+            self.full.notify_one();
+        }
+    }
+
+    /// Each subscriber's share of the queued lines, skipping those with
+    /// nothing to send. Event subscribers share the global event, so it
+    /// carries the lines any of them wants, under the first one's id. `None`
+    /// once nobody is subscribed, which also marks the batching task as stopped.
+    pub fn take_batches(&mut self) -> Option<Vec<(u32, LogSink, LogBatch)>> {
+        if !self.is_watched() {
+            self.running = false;
+            self.pending.clear();
+            self.dropped = 0;
+            return None;
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let dropped = std::mem::take(&mut self.dropped);
+        let batch = |wants: &dyn Fn(&LogLine) -> bool| LogBatch {
+            lines: pending.iter().filter(|line| wants(line)).cloned().collect(),
+            dropped,
+        };
+        let mut batches = Vec::new();
+        let mut events = Vec::new();
+        for sub in &self.subscribers {
+            match &sub.sink {
+                LogSink::Channel(_) => {
+                    batches.push((sub.id, sub.sink.clone(), batch(&|l| sub.filter.accepts(l))))
+                }
+                LogSink::Event => events.push(sub),
+            }
+        }
+        if let Some(first) = events.first() {
+            let any = |l: &LogLine| events.iter().any(|sub| sub.filter.accepts(l));
+            batches.push((first.id, LogSink::Event, batch(&any)));
+        }
+        batches.retain(|(_, _, b)| !b.lines.is_empty() || b.dropped > 0);
+        Some(batches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(n: usize) -> LogLine {
+        LogLine::new("stdout", n.to_string(), 0)
+    }
+
+    #[test]
+    fn queues_only_while_watched_and_counts_drops() {
+        let mut stream = LogStream::new();
+        stream.push(line(0));
+        let (id, start) = stream.subscribe(LogSink::Event, LineFilter::default());
+        assert!(start);
+        assert!(!stream.subscribe(LogSink::Event, LineFilter::default()).1);
+
+        for n in 0..MAX_PENDING_LINES + 7 {
+            stream.push(line(n));
+        }
+        let batches = stream.take_batches().unwrap();
+        // Both event subscribers share one global event.
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0].2;
+        assert_eq!(batch.lines.len(), MAX_PENDING_LINES);
+        assert_eq!(batch.lines[0].line, "0");
+        assert_eq!(batch.dropped, 7);
+        assert!(stream.take_batches().unwrap().is_empty());
+
+        stream.unsubscribe(id);
+        stream.unsubscribe(id + 1);
+        assert!(stream.take_batches().is_none());
+        assert!(stream.subscribe(LogSink::Event, LineFilter::default()).1);
+    }
+
+    #[test]
+    fn filters_by_level_and_pattern() {
+        let warn = LogLine::new("stdout", r#"{"level":"warn","msg":"disk low"}"#.into(), 0);
+        let debug = LogLine::new("stdout", r#"{"level":"debug","msg":"tick"}"#.into(), 0);
+        let stderr = LogLine::new("stderr", "Traceback (most recent call last)".into(), 0);
+
+        let warnings = LineFilter::new(Some(LogLevel::Warn), None, false).unwrap();
+        assert!(warnings.accepts(&warn) && warnings.accepts(&stderr));
+        assert!(!warnings.accepts(&debug) && !warnings.accepts(&line(1)));
+
+        let disk = LineFilter::new(None, Some("disk".into()), false).unwrap();
+        assert!(disk.accepts(&warn) && !disk.accepts(&stderr));
+        let regex = LineFilter::new(None, Some("^Trace(back)?".into()), true).unwrap();
+        assert!(regex.accepts(&stderr) && !regex.accepts(&warn));
+        assert!(LineFilter::new(None, Some("(".into()), true).is_err());
+
+        // A line nobody wants isn't queued at all.
+        let mut stream = LogStream::new();
+        let (id, _) = stream.subscribe(LogSink::Event, warnings);
+        stream.push(debug.clone());
+        assert!(stream.pending.is_empty());
+        assert!(stream.set_filter(id, LineFilter::default()));
+        stream.push(debug);
+        assert_eq!(stream.pending.len(), 1);
+        assert!(!stream.set_filter(id + 1, LineFilter::default()));
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::{self, Write as _};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use crate::logs::{self, LogFile};
+use crate::now_unix_ms;
+
+/// Lines that can wait for the writer thread before new ones are dropped.
+const QUEUE_LINES: usize = 4096;
+
+enum Command {
+    Line(String),
+    /// Flush now rather than once the queue runs dry.
+    Flush,
+    /// Write out what's queued, sync and close the file, then acknowledge.
+    Close(mpsc::Sender<()>),
+}
+
+struct Shared {
+    /// `None` until the log dir is known, after a write fails, or once closed.
+    file: Mutex<Option<LogFile>>,
+    /// Sidecar generation new lines are tagged with.
+    generation: AtomicU64,
+    /// Lines dropped because the queue was full, this session.
+    dropped: AtomicU64,
+}
+
+/// The rotating sidecar log, shared between the sidecar state (child output)
+/// and the subscriber (everything else). Lines are queued for a writer
+/// thread, so a slow disk never holds up the drain loop or a log call; when
+/// the queue is full they are dropped and counted instead of blocking.
+#[derive(Clone)]
+pub(crate) struct LogWriter {
+    shared: Arc<Shared>,
+    tx: SyncSender<Command>,
+}
+
+impl LogWriter {
+    /// Start the writer thread. Lines are discarded until `install`.
+    pub fn spawn() -> Self {
+        let (writer, rx) = Self::new(QUEUE_LINES);
+        let shared = writer.shared.clone();
+        let spawned = thread::Builder::new()
+            .name("log-writer".into())
+            .spawn(move || run(&shared, rx));
+        if let Err(e) = spawned {
+            let _ = writeln!(io::stderr(), "Could not start the log writer: {e}");
+        }
+        writer
+    }
+
+    fn new(capacity: usize) -> (Self, Receiver<Command>) {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let shared = Arc::new(Shared {
+            file: Mutex::new(None),
+            generation: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        });
+        (Self { shared, tx }, rx)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.shared.file.lock().is_ok_and(|slot| slot.is_some())
+    }
+
+    /// Start writing to `file`.
+    pub fn install(&self, file: LogFile) {
+        if let Ok(mut slot) = self.shared.file.lock() {
+            *slot = Some(file);
+        }
+    }
+
+    pub fn path(&self) -> Option<PathBuf> {
+        let slot = self.shared.file.lock().ok()?;
+        slot.as_ref().map(|file| file.path().to_path_buf())
+    }
+
+    /// Tag lines appended from now on with sidecar `generation`.
+    pub fn set_generation(&self, generation: u64) {
+        self.shared.generation.store(generation, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Queue `line` in the `logs::format_line` format. Never blocks, and
+    /// never logs: this runs inside the subscriber.
+    pub fn append(&self, tag: &str, timestamp_ms: u64, line: &str) {
+        let generation = self.shared.generation.load(Ordering::Relaxed);
+        let line = logs::format_line(timestamp_ms, tag, generation, line);
+        if self.tx.try_send(Command::Line(line)).is_err() {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Ask for queued lines to reach disk now, e.g. once the sidecar exits.
+    /// The writer also flushes whenever its queue runs dry, so skipping this
+    /// while the queue is full loses nothing.
+    pub fn flush(&self) {
+        let _ = self.tx.try_send(Command::Flush);
+    }
+
+    /// Write out everything queued, then sync and close the file; later lines
+    /// are discarded. Blocks until done, so callers on the exit path should
+    /// bound the wait.
+    pub fn close(&self) {
+        let (done_tx, done_rx) = mpsc::channel();
+        if self.tx.send(Command::Close(done_tx)).is_ok() {
+            let _ = done_rx.recv();
+        }
+    }
+}
+
+fn run(shared: &Shared, rx: Receiver<Command>) {
+    let mut reported = 0;
+    while let Ok(first) = rx.recv() {
+        let mut next = Some(first);
+        while let Some(command) = next.take() {
+            match command {
+                Command::Line(line) => write(&mut lock(shared), &line),
+                Command::Flush => flush(&mut lock(shared)),
+                Command::Close(done) => {
+                    close(&mut lock(shared));
+                    let _ = done.send(());
+                }
+            }
+            next = rx.try_recv().ok();
+        }
+        // Caught up: note any lines lost meanwhile, then flush.
+        let dropped = shared.dropped.load(Ordering::Relaxed);
+        let mut slot = lock(shared);
+        if dropped > reported {
+            let generation = shared.generation.load(Ordering::Relaxed);
+            let message = format!(
+                "WARN {} log lines dropped because the disk couldn't keep up",
+                dropped - reported
+            );
+            write(&mut slot, &logs::format_line(now_unix_ms(), "app", generation, &message));
+            reported = dropped;
+        }
+        flush(&mut slot);
+    }
+}
+
+fn lock(shared: &Shared) -> MutexGuard<'_, Option<LogFile>> {
+    shared.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Failures disable the file and are reported straight to stderr, since
+/// logging them would only queue more lines for it.
+fn write(slot: &mut Option<LogFile>, line: &str) {
+    if let Some(Err(e)) = slot.as_mut().map(|f| f.append(line)) {
+        *slot = None;
+        let _ = writeln!(io::stderr(), "Failed to write sidecar log file, disabling it: {e}");
+    }
+}
+
+fn flush(slot: &mut Option<LogFile>) {
+    if let Some(Err(e)) = slot.as_mut().map(LogFile::flush) {
+        *slot = None;
+        let _ = writeln!(io::stderr(), "Failed to flush sidecar log file, disabling it: {e}");
+    }
+}
+
+fn close(slot: &mut Option<LogFile>) {
+    if let Some(Err(e)) = slot.take().map(LogFile::close) {
+        let _ = writeln!(io::stderr(), "Failed to close sidecar log file: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::logs::LogRotation;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn writes_in_order_and_closes() {
+        // A directory of its own: opening the file prunes what's beside it.
+        let dir = scratch_dir("writer");
+        let path = dir.join("sidecar.log");
+        let rotation = LogRotation {
+            max_bytes: u64::MAX,
+            keep_files: 0,
+            max_total_bytes: u64::MAX,
+        };
+        let writer = LogWriter::spawn();
+        writer.install(LogFile::open(&path, rotation).unwrap());
+        writer.set_generation(2);
+        for i in 0..3 {
+            writer.append("out", 0, &format!("line {i}"));
+        }
+        writer.close();
+        assert!(!writer.is_open());
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = content.lines().map(|l| l.split_once(' ').unwrap().1).collect();
+        assert_eq!(lines, ["out g2 line 0", "out g2 line 1", "out g2 line 2"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn counts_lines_dropped_while_the_queue_is_full() {
+        let (writer, _rx) = LogWriter::new(1);
+        writer.append("app", 0, "kept");
+        writer.append("app", 0, "dropped");
+        writer.append("app", 0, "dropped too");
+        assert_eq!(writer.dropped(), 2);
+    }
+}
@@ -0,0 +1,100 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use crate::transport::SidecarEndpoint;
+
+/// How often the route to a remote sidecar is re-checked.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The local address the OS would use to reach `endpoint`, as a cheap stand-in
+/// for network-change notifications: it changes when a VPN comes up or drops
+/// or the machine switches networks. `None` when there is no route (or for a
+/// socket endpoint). Connecting a UDP socket only picks a route; nothing is sent.
+pub(crate) async fn route_to(endpoint: &SidecarEndpoint) -> Option<IpAddr> {
+    let SidecarEndpoint::Tcp { host, port, .. } = endpoint else {
+        return None;
+    };
+    let target = tokio::net::lookup_host((host.as_str(), *port)).await.ok()?.next()?;
+    let unspecified = match target {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((unspecified, 0)).await.ok()?;
+    socket.connect(target).await.ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// This machine's IPv4 addresses that other devices on the LAN could use to
+/// reach a sidecar bound to `0.0.0.0`.
+#[cfg(unix)]
+pub(crate) fn lan_addresses() -> Vec<Ipv4Addr> {
+    let mut addrs = Vec::new();
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs hands us a linked list that we only read, checking
+    // every pointer before use, and free exactly once.
+    unsafe {
+        if libc::getifaddrs(&mut list) != 0 {
+            return addrs;
+        }
+        let mut cursor = list;
+        while let Some(ifa) = cursor.as_ref() {
+            if let Some(sa) = ifa.ifa_addr.as_ref() {
+                if i32::from(sa.sa_family) == libc::AF_INET {
+                    let sin = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                    addrs.push(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)));
+                }
+            }
+            cursor = ifa.ifa_next;
+        }
+        libc::freeifaddrs(list);
+    }
+    addrs.retain(|&a| is_lan_address(a));
+    addrs.sort();
+    addrs.dedup();
+    addrs
+}
+
+/// This machine's IPv4 address on its default route, which other devices on
+/// the LAN could use to reach a sidecar bound to `0.0.0.0`.
+#[cfg(windows)]
+pub(crate) fn lan_addresses() -> Vec<Ipv4Addr> {
+    // Connecting a UDP socket only picks a route; nothing is sent.
+    let route = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).map(|()| socket))
+        .and_then(|socket| socket.local_addr());
+    match route {
+        Ok(SocketAddr::V4(addr)) if is_lan_address(*addr.ip()) => vec![*addr.ip()],
+        _ => Vec::new(),
+    }
+}
+
+fn is_lan_address(addr: Ipv4Addr) -> bool {
+    !addr.is_loopback() && !addr.is_unspecified() && !addr.is_link_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn loopback_routes_through_loopback() {
+        let endpoint = SidecarEndpoint::tcp("127.0.0.1", 9876);
+        assert_eq!(route_to(&endpoint).await, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+
+        let socket = SidecarEndpoint::Socket {
+            path: PathBuf::from("/tmp/sidecar.sock"),
+        };
+        assert_eq!(route_to(&socket).await, None);
+    }
+
+    #[test]
+    fn lan_addresses_skip_loopback_and_link_local() {
+        assert!(is_lan_address(Ipv4Addr::new(192, 168, 1, 20)));
+        assert!(!is_lan_address(Ipv4Addr::LOCALHOST));
+        assert!(!is_lan_address(Ipv4Addr::new(169, 254, 3, 4)));
+        assert!(!lan_addresses().contains(&Ipv4Addr::LOCALHOST));
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tauri::Manager;
+
+use crate::AppHandle;
+
+/// App directories that sidecar features (log files, working dir, sockets) rely on.
+pub(crate) struct AppDirs {
+    pub data: PathBuf,
+    pub logs: PathBuf,
+    /// Where settings and small bits of remembered state live.
+    pub config: PathBuf,
+}
+
+/// Why an app directory couldn't be made usable.
+#[derive(Debug)]
+pub(crate) enum DirError {
+    /// Tauri couldn't tell us where the directory should live.
+    Resolve { kind: &'static str, source: tauri::Error },
+    /// The directory couldn't be created, or exists but isn't writable.
+    Create { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for DirError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DirError::Resolve { kind, source } => {
+                write!(f, "Could not determine the app {kind} directory: {source}")
+            }
+            DirError::Create { path, source } => write!(
+                f,
+                "Could not create or write to {}: {source}. \
+                 Check that your user account has write permission to this folder.",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DirError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DirError::Resolve { source, .. } => Some(source),
+            DirError::Create { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Resolve the app data, log and config directories, creating them if needed.
+pub(crate) fn resolve_app_dirs(app_handle: &AppHandle) -> Result<AppDirs, DirError> {
+    let path = app_handle.path();
+    let data = path
+        .app_data_dir()
+        .map_err(|source| DirError::Resolve { kind: "data", source })?;
+    let logs = path
+        .app_log_dir()
+        .map_err(|source| DirError::Resolve { kind: "log", source })?;
+    let config = path
+        .app_config_dir()
+        .map_err(|source| DirError::Resolve { kind: "config", source })?;
+    ensure_dir(&data)?;
+    ensure_dir(&logs)?;
+    ensure_dir(&config)?;
+    Ok(AppDirs { data, logs, config })
+}
+
+/// Create `dir` (and parents) and confirm we can write into it. Existing but
+/// read-only directories are caught here rather than at first use.
+pub(crate) fn ensure_dir(dir: &Path) -> Result<(), DirError> {
+    let create_err = |source| DirError::Create {
+        path: dir.to_path_buf(),
+        source,
+    };
+    fs::create_dir_all(dir).map_err(create_err)?;
+    let probe = dir.join(".write-test");
+    fs::write(&probe, b"").map_err(create_err)?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn ensure_dir_creates_nested_directories() {
+        let root = scratch_dir("paths-nested");
+        let target = root.join("a").join("b");
+        ensure_dir(&target).unwrap();
+        assert!(target.is_dir());
+        assert!(!target.join(".write-test").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn ensure_dir_reports_uncreatable_directory() {
+        // A regular file where a parent directory should be can't be worked
+        // around by permissions, so this fails even when tests run as root.
+        let root = scratch_dir("paths-blocked");
+        let blocker = root.join("not-a-dir");
+        fs::write(&blocker, b"").unwrap();
+        let target = blocker.join("logs");
+
+        let err = ensure_dir(&target).unwrap_err();
+        assert!(matches!(&err, DirError::Create { path, .. } if path == &target));
+        assert!(err.to_string().contains("write permission"));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::SidecarError;
+#[cfg(windows)]
+use crate::excluded_ports;
+use crate::transport;
+
+/// Ephemeral ports to try before giving up on one free on both IP families.
+const DUAL_STACK_BIND_ATTEMPTS: u32 = 5;
+/// File in the app config dir holding the last port a sidecar was healthy on.
+const LAST_PORT_FILE_NAME: &str = "last-port";
+
+/// Where the last known good port is remembered.
+pub(crate) fn last_port_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(LAST_PORT_FILE_NAME)
+}
+
+/// The remembered port, or `None` if nothing was remembered or the file is
+/// corrupt, in which case it is ignored.
+pub(crate) fn read_last_port(path: &Path) -> Option<u16> {
+    let text = fs::read_to_string(path).ok()?;
+    match text.trim().parse() {
+        Ok(port) if port != 0 => Some(port),
+        _ => {
+            warn!("Ignoring unreadable last-port file {}", path.display());
+            None
+        }
+    }
+}
+
+pub(crate) fn write_last_port(path: &Path, port: u16) -> io::Result<()> {
+    fs::write(path, format!("{port}\n"))
+}
+
+/// Inclusive range of ports a spawned sidecar may listen on, for firewalls
+/// that only allow loopback traffic on specific ports.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub(crate) struct PortRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+/// Parses `min-max`, e.g. `20000-20100`.
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{s:?} is not a port range like 20000-20100");
+        let (min, max) = s.split_once('-').ok_or_else(invalid)?;
+        let min: u16 = min.trim().parse().map_err(|_| invalid())?;
+        let max: u16 = max.trim().parse().map_err(|_| invalid())?;
+        if min == 0 || min > max {
+            return Err(invalid());
+        }
+        Ok(PortRange { min, max })
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.min, self.max)
+    }
+}
+
+/// Reserve a free port on `host`: the first one that binds within `range`, or
+/// whatever the OS assigns without one. The listeners are returned so the
+/// caller can keep the port reserved until just before the sidecar needs it.
+pub(crate) fn find_free_port(
+    host: &str,
+    range: Option<PortRange>,
+) -> Result<(u16, Vec<TcpListener>), SidecarError> {
+    let Some(range) = range else {
+        return ephemeral_port(host);
+    };
+    for port in range.min..=range.max {
+        #[cfg(windows)]
+        if excluded_ports::is_excluded(port) {
+            continue;
+        }
+        if let Ok(reserved) = bind_port(host, port) {
+            return Ok(reserved);
+        }
+    }
+    let e = io::Error::new(
+        io::ErrorKind::AddrInUse,
+        format!("no port in the configured range {range} is free"),
+    );
+    Err(SidecarError::PortBind(e.into()))
+}
+
+/// Bind to `host` port 0 and let the OS assign an available port.
+#[cfg(not(windows))]
+fn ephemeral_port(host: &str) -> Result<(u16, Vec<TcpListener>), SidecarError> {
+    bind_port(host, 0)
+}
+
+/// Bind to `host` port 0 and let the OS assign an available port, skipping
+/// ports inside a Windows excluded port range. Rejected ports stay bound until
+/// we return so the OS can't hand them out again.
+#[cfg(windows)]
+fn ephemeral_port(host: &str) -> Result<(u16, Vec<TcpListener>), SidecarError> {
+    let mut rejected = Vec::new();
+    for _ in 0..excluded_ports::MAX_ATTEMPTS {
+        let (port, listeners) = bind_port(host, 0)?;
+        if !excluded_ports::is_excluded(port) {
+            return Ok((port, listeners));
+        }
+        warn!("Port {port} is in a Windows excluded port range, picking another");
+        rejected.push(listeners);
+    }
+    let e = io::Error::other(format!(
+        "every port Windows offered in {} attempts was inside an excluded port range \
+         (see `netsh interface ipv4 show excludedportrange protocol=tcp`)",
+        excluded_ports::MAX_ATTEMPTS
+    ));
+    Err(SidecarError::PortBind(e.into()))
+}
+
+/// Bind `port` on `host` and, for a loopback host, on the other IP family
+/// too, so the sidecar can't end up sharing the port with a different process
+/// on `::1`. Hosts without an IPv6 stack only get the v4 listener.
+pub(crate) fn bind_port(host: &str, port: u16) -> Result<(u16, Vec<TcpListener>), SidecarError> {
+    for _ in 0..DUAL_STACK_BIND_ATTEMPTS {
+        let listener =
+            TcpListener::bind((host, port)).map_err(|e| SidecarError::PortBind(e.into()))?;
+        let bound = listener
+            .local_addr()
+            .map_err(|e| SidecarError::LocalAddr(e.into()))?
+            .port();
+        let Some(other) = transport::other_loopback(host) else {
+            return Ok((bound, vec![listener]));
+        };
+        match TcpListener::bind((other, bound)) {
+            Ok(twin) => return Ok((bound, vec![listener, twin])),
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                // Only taken on the other stack. An ephemeral pick can try
+                // again; a fixed port is simply unavailable.
+                if port != 0 {
+                    return Err(SidecarError::PortBind(e.into()));
+                }
+            }
+            Err(_) => return Ok((bound, vec![listener])),
+        }
+    }
+    let e = io::Error::new(
+        io::ErrorKind::AddrInUse,
+        "no port was free on both IPv4 and IPv6 loopback",
+    );
+    Err(SidecarError::PortBind(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+    use crate::transport::LOOPBACK_HOST;
+
+    #[test]
+    fn scans_the_range_and_errors_when_it_is_full() {
+        // Two adjacent ports, freed again so the range scan can take them.
+        let (base, held) = (0..20)
+            .find_map(|_| {
+                let (port, listeners) = ephemeral_port(LOOPBACK_HOST).ok()?;
+                let (_, next) = bind_port(LOOPBACK_HOST, port.checked_add(1)?).ok()?;
+                Some((port, (listeners, next)))
+            })
+            .expect("no two adjacent free ports");
+        drop(held);
+        let range = PortRange {
+            min: base,
+            max: base + 1,
+        };
+
+        let (first, first_held) = find_free_port(LOOPBACK_HOST, Some(range)).unwrap();
+        assert_eq!(first, base);
+        let (second, second_held) = find_free_port(LOOPBACK_HOST, Some(range)).unwrap();
+        assert_eq!(second, base + 1);
+        assert!(find_free_port(LOOPBACK_HOST, Some(range)).is_err());
+        drop((first_held, second_held));
+    }
+
+    #[test]
+    fn last_port_round_trips_and_ignores_corruption() {
+        let dir = scratch_dir("last-port");
+        let path = dir.join("last-port");
+        assert_eq!(read_last_port(&path), None);
+        write_last_port(&path, 41234).unwrap();
+        assert_eq!(read_last_port(&path), Some(41234));
+        fs::write(&path, "\u{0}garbage").unwrap();
+        assert_eq!(read_last_port(&path), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parses_port_ranges() {
+        assert_eq!("20000-20100".parse(), Ok(PortRange { min: 20000, max: 20100 }));
+        assert!("20100-20000".parse::<PortRange>().is_err());
+        assert!("0-10".parse::<PortRange>().is_err());
+        assert!("20000".parse::<PortRange>().is_err());
+    }
+}
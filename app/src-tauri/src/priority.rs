@@ -0,0 +1,41 @@
+use std::io;
+
+/// Lower the scheduling priority of process `pid` so its background work
+/// doesn't compete with the UI. `niceness` is the Unix nice value (1-19).
+#[cfg(unix)]
+pub(crate) fn lower(pid: u32, niceness: i32) -> io::Result<()> {
+    // SAFETY: setpriority(2) has no memory-safety preconditions.
+    let rc = unsafe { libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, niceness) };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Lower the scheduling priority of process `pid` so its background work
+/// doesn't compete with the UI. Windows has no nice values, so any
+/// `niceness` maps to the below-normal priority class.
+#[cfg(windows)]
+pub(crate) fn lower(pid: u32, _niceness: i32) -> io::Result<()> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, SetPriorityClass, BELOW_NORMAL_PRIORITY_CLASS, PROCESS_SET_INFORMATION,
+    };
+
+    // SAFETY: plain Win32 calls; the handle is checked before use and closed
+    // on every path after it was opened.
+    unsafe {
+        let handle = OpenProcess(PROCESS_SET_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let result = if SetPriorityClass(handle, BELOW_NORMAL_PRIORITY_CLASS) != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        };
+        CloseHandle(handle);
+        result
+    }
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::SidecarToken;
+use crate::error::SidecarError;
+use crate::health::with_timeout;
+use crate::http;
+use crate::transport::SidecarEndpoint;
+
+/// Largest request body the proxy will forward.
+const MAX_REQUEST_BODY_BYTES: usize = 4 * 1024 * 1024;
+/// Largest raw response (headers included) read back from the sidecar. The
+/// proxy buffers whole responses, so streaming endpoints must be called directly.
+const MAX_RESPONSE_BYTES: u64 = 16 * 1024 * 1024;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Headers the proxy sets itself; callers can't override them.
+const MANAGED_HEADERS: &[&str] = &[
+    "host",
+    "authorization",
+    "content-length",
+    "connection",
+    "transfer-encoding",
+    "keep-alive",
+    "upgrade",
+];
+
+/// An HTTP call the frontend wants forwarded to the sidecar.
+#[derive(Deserialize)]
+pub(crate) struct ProxyRequest {
+    pub method: String,
+    /// Path and query, starting with `/`.
+    pub path: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// UTF-8 request body, usually JSON.
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Per-request timeout, capped at two minutes.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// The sidecar's answer. Bodies that aren't valid UTF-8 come back base64-encoded.
+#[derive(Serialize)]
+pub(crate) struct ProxyResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub body_base64: bool,
+}
+
+/// Send `request` to the sidecar at `endpoint` and return its full response.
+pub(crate) async fn forward(
+    endpoint: &SidecarEndpoint,
+    token: Option<&SidecarToken>,
+    request: ProxyRequest,
+) -> Result<ProxyResponse, SidecarError> {
+    validate(&request)?;
+    let body = request.body.unwrap_or_default().into_bytes();
+    let raw = http::request(
+        &request.method.to_ascii_uppercase(),
+        &endpoint.host_header(),
+        &request.path,
+        &request.headers,
+        &body,
+        token.map(SidecarToken::as_str),
+    );
+    let timeout = request
+        .timeout_ms
+        .map_or(DEFAULT_TIMEOUT, Duration::from_millis)
+        .min(MAX_TIMEOUT);
+    // Read one byte past the limit so an exactly-full response isn't an error.
+    let reply = with_timeout(
+        endpoint,
+        timeout,
+        endpoint.round_trip_limited(&raw, MAX_RESPONSE_BYTES + 1),
+    )
+    .await?;
+    if reply.len() as u64 > MAX_RESPONSE_BYTES {
+        return Err(SidecarError::ResponseTooLarge {
+            limit: MAX_RESPONSE_BYTES,
+        });
+    }
+    let response =
+        http::parse_response(&reply).map_err(|reason| SidecarError::InvalidResponse {
+            endpoint: endpoint.to_string(),
+            reason,
+        })?;
+    let (body, body_base64) = match String::from_utf8(response.body) {
+        Ok(text) => (text, false),
+        Err(e) => (
+            base64::engine::general_purpose::STANDARD.encode(e.into_bytes()),
+            true,
+        ),
+    };
+    Ok(ProxyResponse {
+        status: response.status,
+        headers: response.headers,
+        body,
+        body_base64,
+    })
+}
+
+/// Reject anything that could smuggle a second request onto the connection
+/// or override what the proxy manages.
+fn validate(request: &ProxyRequest) -> Result<(), SidecarError> {
+    let invalid = |reason: String| Err(SidecarError::InvalidProxyRequest(reason));
+    if request.method.is_empty() || !request.method.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return invalid(format!("{:?} is not an HTTP method", request.method));
+    }
+    if !request.path.starts_with('/') || request.path.contains(|c: char| c.is_ascii_whitespace()) {
+        return invalid(format!("{:?} is not a request path", request.path));
+    }
+    for (name, value) in &request.headers {
+        let bad_name = name.is_empty() || !name.bytes().all(|b| b.is_ascii_graphic() && b != b':');
+        if bad_name || value.contains(['\r', '\n']) {
+            return invalid(format!("header {name:?} is malformed"));
+        }
+        if MANAGED_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+            return invalid(format!("header {name:?} is set by the proxy"));
+        }
+    }
+    let size = request.body.as_ref().map_or(0, String::len);
+    if size > MAX_REQUEST_BODY_BYTES {
+        return invalid(format!(
+            "body is {size} bytes; the limit is {MAX_REQUEST_BODY_BYTES}"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, headers: &[(&str, &str)]) -> ProxyRequest {
+        ProxyRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            body: None,
+            timeout_ms: None,
+        }
+    }
+
+    #[test]
+    fn rejects_smuggling_and_managed_headers() {
+        assert!(validate(&request("post", "/api/scan?x=1", &[("X-Id", "7")])).is_ok());
+        assert!(validate(&request("GET /x HTTP/1.1\r\n", "/", &[])).is_err());
+        assert!(validate(&request("GET", "/a b", &[])).is_err());
+        assert!(validate(&request("GET", "http://evil/", &[])).is_err());
+        assert!(validate(&request("GET", "/", &[("X-A", "1\r\nHost: x")])).is_err());
+        assert!(validate(&request("GET", "/", &[("Authorization", "Bearer x")])).is_err());
+
+        let mut big = request("POST", "/", &[]);
+        big.body = Some("x".repeat(MAX_REQUEST_BODY_BYTES + 1));
+        assert!(validate(&big).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+
+use regex::{Captures, Regex, RegexSet};
+use serde_json::Value;
+use tracing::warn;
+
+/// What a masked secret is replaced with.
+pub(crate) const MASK: &str = "***";
+
+/// Secrets the sidecar or its libraries are known to echo. When a pattern
+/// has a `secret` group only that part is masked, otherwise the whole match.
+const DEFAULT_PATTERNS: &[&str] = &[
+    r"(?i)\bbearer\s+(?P<secret>[A-Za-z0-9._~+/=-]{8,})",
+    r"\bsk-[A-Za-z0-9_-]{16,}",
+    r#"(?i)\b(?:api[_-]?key|x-api-key)["']?\s*[:=]\s*["']?(?P<secret>[^\s"',;]{8,})"#,
+];
+
+/// Masks secrets in log lines. A single `RegexSet` pass rules out the common
+/// case of a line without secrets before any pattern is applied.
+pub(crate) struct Redactor {
+    set: RegexSet,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    /// The default patterns plus `extra` ones from the config file; extras
+    /// that don't compile are skipped with a warning.
+    pub fn new(extra: &[String]) -> Self {
+        let mut patterns: Vec<Regex> = DEFAULT_PATTERNS
+            .iter()
+            .map(|p| Regex::new(p).expect("default redaction pattern is valid"))
+            .collect();
+        for pattern in extra {
+            match Regex::new(pattern) {
+                Ok(re) => patterns.push(re),
+                Err(e) => warn!("Ignoring redaction pattern {pattern:?}: {e}"),
+            }
+        }
+        let set = RegexSet::new(patterns.iter().map(Regex::as_str))
+            .expect("patterns compiled individually");
+        Self { set, patterns }
+    }
+
+    pub fn redact<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let matched = self.set.matches(line);
+        if !matched.matched_any() {
+            return Cow::Borrowed(line);
+        }
+        let mut out = line.to_string();
+        for i in matched.iter() {
+            if let Cow::Owned(replaced) = self.patterns[i].replace_all(&out, mask) {
+                out = replaced;
+            }
+        }
+        Cow::Owned(out)
+    }
+
+    /// `value` with every string in it redacted.
+    pub fn redact_json(&self, value: Value) -> Value {
+        match value {
+            Value::String(s) => Value::String(self.redact(&s).into_owned()),
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|v| self.redact_json(v)).collect())
+            }
+            Value::Object(fields) => Value::Object(
+                fields.into_iter().map(|(k, v)| (k, self.redact_json(v))).collect(),
+            ),
+            other => other,
+        }
+    }
+}
+
+fn mask(caps: &Captures<'_>) -> String {
+    let whole = &caps[0];
+    let Some(secret) = caps.name("secret") else {
+        return MASK.to_string();
+    };
+    let start = caps.get(0).map_or(0, |m| m.start());
+    let (from, to) = (secret.start() - start, secret.end() - start);
+    format!("{}{MASK}{}", &whole[..from], &whole[to..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_known_secrets_and_extra_patterns() {
+        let redactor = Redactor::new(&[r"ghp_[A-Za-z0-9]{8,}".to_string(), "(".to_string()]);
+        assert!(matches!(redactor.redact("GET /health 200"), Cow::Borrowed(_)));
+        assert_eq!(
+            redactor.redact("Authorization: Bearer abc.def-123456"),
+            "Authorization: Bearer ***"
+        );
+        assert_eq!(
+            redactor.redact("key=sk-ant-REDACTED ok"),
+            "key=*** ok"
+        );
+        assert_eq!(redactor.redact(r#"{"api_key": "s3cr3tvalue"}"#), r#"{"api_key": "***"}"#);
+        assert_eq!(redactor.redact("token ghp_abcdefgh12"), "token ***");
+        let args = serde_json::json!({ "args": ["--key", "sk-abcdefghijklmnop1234"], "n": 1 });
+        let redacted = serde_json::json!({ "args": ["--key", "***"], "n": 1 });
+        assert_eq!(redactor.redact_json(args), redacted);
+    }
+}
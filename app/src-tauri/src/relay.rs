@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::watch;
+
+use crate::auth::SidecarToken;
+use crate::health::Backoff;
+use crate::http;
+use crate::transport::SidecarEndpoint;
+
+/// Delays between reconnect attempts while the event stream keeps failing.
+pub(crate) const RECONNECT_BACKOFF: Backoff = Backoff {
+    initial: Duration::from_millis(250),
+    max: Duration::from_secs(30),
+    jitter: 0.2,
+};
+/// How often a quiet stream checks whether it should still be relaying.
+const READ_POLL: Duration = Duration::from_secs(1);
+/// Time allowed for the sidecar to send response headers.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest response head or single event we'll buffer.
+const MAX_BUFFERED_BYTES: usize = 1024 * 1024;
+
+/// Whether the `sidecar-event` relay runs. Frontends that keep their own
+/// connection to the stream can switch it off for the session.
+pub(crate) struct EventRelay {
+    enabled: watch::Sender<bool>,
+}
+
+impl EventRelay {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: watch::Sender::new(enabled),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.send_if_modified(|current| std::mem::replace(current, enabled) != enabled);
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.enabled.subscribe()
+    }
+}
+
+/// One server-sent event, re-emitted to the frontend as `sidecar-event`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct SidecarEvent {
+    /// The `event:` field, `message` when the sidecar didn't name it.
+    pub event: String,
+    /// The `data:` lines joined with newlines, parsed as JSON when possible.
+    pub data: serde_json::Value,
+    pub id: Option<String>,
+}
+
+/// Why the event stream ended with an error.
+#[derive(Debug)]
+pub(crate) enum StreamError {
+    /// The sidecar doesn't serve an event stream at the configured path.
+    NotFound,
+    Failed(String),
+}
+
+/// Incremental `text/event-stream` parser; bytes can be fed in any chunking.
+#[derive(Default)]
+pub(crate) struct SseParser {
+    buf: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+    id: Option<String>,
+}
+
+impl SseParser {
+    /// Consume `bytes` and return every event they completed.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<SidecarEvent> {
+        self.buf.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                events.extend(self.dispatch());
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                "id" => self.id = Some(value.to_string()),
+                // Comments (empty field name) keep the connection alive; we
+                // don't reconnect on the sidecar's `retry:` hint either.
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// Bytes of the event currently being assembled.
+    fn pending_len(&self) -> usize {
+        self.buf.len() + self.data.iter().map(String::len).sum::<usize>()
+    }
+
+    fn dispatch(&mut self) -> Option<SidecarEvent> {
+        let event = self.event.take().unwrap_or_else(|| "message".to_string());
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data).join("\n");
+        Some(SidecarEvent {
+            event,
+            data: serde_json::from_str(&data).unwrap_or(serde_json::Value::String(data)),
+            id: self.id.clone(),
+        })
+    }
+}
+
+/// Stream events from `path` on the sidecar into `emit` until the sidecar
+/// hangs up or `keep_going` returns false, which is checked at least once a
+/// second. Returns `Ok` when the stream ended without an error.
+pub(crate) async fn stream(
+    endpoint: &SidecarEndpoint,
+    path: &str,
+    token: Option<&SidecarToken>,
+    mut emit: impl FnMut(SidecarEvent),
+    mut keep_going: impl FnMut() -> bool,
+) -> Result<(), StreamError> {
+    let failed = |what: &str, e: std::io::Error| StreamError::Failed(format!("{what}: {e}"));
+    let mut conn = match tokio::time::timeout(HEADER_TIMEOUT, endpoint.connect()).await {
+        Ok(conn) => conn.map_err(|e| failed(&format!("connecting to {endpoint}"), e))?,
+        Err(_) => return Err(StreamError::Failed(format!("connecting to {endpoint} timed out"))),
+    };
+    let request =
+        http::event_stream_request(&endpoint.host_header(), path, token.map(SidecarToken::as_str));
+    conn.write_all(&request).await.map_err(|e| failed("sending request", e))?;
+
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let body_start = loop {
+        if let Some(split) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+            break split + 4;
+        }
+        if head.len() > MAX_BUFFERED_BYTES {
+            return Err(StreamError::Failed("response headers too large".into()));
+        }
+        let n = match tokio::time::timeout(HEADER_TIMEOUT, conn.read(&mut chunk)).await {
+            Ok(read) => read.map_err(|e| failed("reading headers", e))?,
+            Err(_) => return Err(StreamError::Failed("no response headers".into())),
+        };
+        if n == 0 {
+            return Err(StreamError::Failed("connection closed before headers".into()));
+        }
+        head.extend_from_slice(&chunk[..n]);
+    };
+    let response = http::parse_response(&head[..body_start]).map_err(StreamError::Failed)?;
+    match response.status {
+        200..=299 => {}
+        404 => return Err(StreamError::NotFound),
+        status => return Err(StreamError::Failed(format!("event stream returned HTTP {status}"))),
+    }
+
+    let mut parser = SseParser::default();
+    parser.feed(&head[body_start..]).into_iter().for_each(&mut emit);
+    loop {
+        if !keep_going() {
+            return Ok(());
+        }
+        let n = match tokio::time::timeout(READ_POLL, conn.read(&mut chunk)).await {
+            Ok(read) => read.map_err(|e| failed("reading events", e))?,
+            Err(_) => continue,
+        };
+        if n == 0 {
+            return Ok(());
+        }
+        parser.feed(&chunk[..n]).into_iter().for_each(&mut emit);
+        if parser.pending_len() > MAX_BUFFERED_BYTES {
+            return Err(StreamError::Failed("event larger than 1 MiB".into()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events_across_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b": keep-alive\n\nevent: progr").is_empty());
+        let events =
+            parser.feed(b"ess\r\nid: 7\r\ndata: {\"pct\":\r\ndata: 40}\r\n\r\ndata: hi\n\n");
+        assert_eq!(
+            events,
+            [
+                SidecarEvent {
+                    event: "progress".into(),
+                    data: serde_json::json!({ "pct": 40 }),
+                    id: Some("7".into()),
+                },
+                SidecarEvent {
+                    event: "message".into(),
+                    data: serde_json::Value::String("hi".into()),
+                    id: Some("7".into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn toggling_only_notifies_on_change() {
+        let relay = EventRelay::new(true);
+        let mut rx = relay.subscribe();
+        rx.mark_unchanged();
+        relay.set_enabled(true);
+        assert!(!rx.has_changed().unwrap());
+        relay.set_enabled(false);
+        assert!(rx.has_changed().unwrap());
+        assert!(!*rx.borrow());
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Serialize;
+
+/// How a sidecar answered `reload <seq>` on its stdin control channel:
+///
+/// - `reloaded <seq>`: every change was applied.
+/// - `reloaded <seq> restart=<setting>,<setting>`: applied, except for the
+///   listed settings, which only take effect after a restart.
+/// - `reload-failed <seq> <message>`: nothing was applied.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct ReloadResult {
+    pub accepted: bool,
+    /// Settings that need a restart to take effect, so the frontend can
+    /// offer one.
+    pub restart_required: Vec<String>,
+    /// Why the sidecar refused, when it did.
+    pub message: Option<String>,
+}
+
+/// The control line asking the sidecar to reload its settings.
+pub(crate) fn request(seq: u64) -> String {
+    format!("reload {seq}\n")
+}
+
+/// The sequence number and result of a reload acknowledgement, or `None` if
+/// `line` isn't one.
+pub(crate) fn parse_ack(line: &str) -> Option<(u64, ReloadResult)> {
+    let line = line.trim_end();
+    if let Some(rest) = line.strip_prefix("reloaded ") {
+        let (seq, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        let restart_required = match rest.trim() {
+            "" => Vec::new(),
+            rest => rest
+                .strip_prefix("restart=")?
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect(),
+        };
+        let result = ReloadResult {
+            accepted: true,
+            restart_required,
+            message: None,
+        };
+        return Some((seq.parse().ok()?, result));
+    }
+    let rest = line.strip_prefix("reload-failed ")?;
+    let (seq, message) = rest.split_once(' ').unwrap_or((rest, ""));
+    let result = ReloadResult {
+        accepted: false,
+        restart_required: Vec::new(),
+        message: Some(message.trim().to_string()).filter(|m| !m.is_empty()),
+    };
+    Some((seq.parse().ok()?, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_acknowledgements() {
+        let (seq, applied) = parse_ack("reloaded 3\r").unwrap();
+        assert_eq!(seq, 3);
+        assert!(applied.accepted && applied.restart_required.is_empty());
+
+        let (_, partial) = parse_ack("reloaded 4 restart=port, workers").unwrap();
+        assert!(partial.accepted);
+        assert_eq!(partial.restart_required, ["port", "workers"]);
+
+        let (seq, refused) = parse_ack("reload-failed 5 invalid value for workers").unwrap();
+        assert_eq!(seq, 5);
+        assert!(!refused.accepted);
+        assert_eq!(refused.message.as_deref(), Some("invalid value for workers"));
+
+        for other in ["reloaded", "reloaded x", "reloaded 6 later", "reload 7", "INFO: reloaded"] {
+            assert!(parse_ack(other).is_none(), "{other}");
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::VecDeque;
+
+/// Events held back until the frontend has loaded, so a slow-loading webview
+/// still hears what happened before it could listen. Once released, events
+/// pass straight through.
+pub(crate) struct ReplayQueue<T> {
+    loaded: bool,
+    held: VecDeque<T>,
+    /// Most events held; the oldest go first past it.
+    capacity: usize,
+}
+
+impl<T> ReplayQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            loaded: false,
+            held: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// `Some(event)` if it should be sent now, `None` if it was held.
+    pub fn offer(&mut self, event: T) -> Option<T> {
+        if self.loaded {
+            return Some(event);
+        }
+        if self.held.len() == self.capacity {
+            self.held.pop_front();
+        }
+        self.held.push_back(event);
+        None
+    }
+
+    /// Mark the frontend loaded and return what was held, oldest first. Empty
+    /// after the first call.
+    pub fn release(&mut self) -> Vec<T> {
+        self.loaded = true;
+        self.held.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_events_until_released() {
+        let mut queue = ReplayQueue::new(2);
+        assert_eq!(queue.offer(1), None);
+        assert_eq!(queue.offer(2), None);
+        assert_eq!(queue.offer(3), None);
+        assert_eq!(queue.release(), [2, 3]);
+        assert_eq!(queue.offer(4), Some(4));
+        assert!(queue.release().is_empty());
+    }
+}
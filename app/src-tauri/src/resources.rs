@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+/// Samples kept for `get_sidecar_resources`: five minutes at the default interval.
+const HISTORY_CAPACITY: usize = 60;
+/// Changes worth a `sidecar-resources` event straight away.
+const CPU_STEP: f32 = 5.0;
+const RSS_STEP: u64 = 8 * 1024 * 1024;
+const FD_STEP: usize = 16;
+/// Longest gap between events while nothing much changes.
+const EMIT_MAX_GAP: Duration = Duration::from_secs(30);
+
+/// One reading of the sidecar process.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct ResourceSample {
+    /// Unix milliseconds.
+    pub timestamp: u64,
+    /// Share of one core since the previous sample, so over 100 when several
+    /// are busy. 0 for a process's first sample.
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+    /// Open file descriptors (handles on Windows); `None` where they can't be
+    /// counted.
+    pub open_fds: Option<usize>,
+}
+
+/// Reads one process, known by its pid and start time so that a pid reused
+/// by something else after it exits is never sampled in its place.
+pub(crate) struct Sampler {
+    system: System,
+    pid: Pid,
+    start_time: Option<u64>,
+}
+
+impl Sampler {
+    pub fn new(pid: u32) -> Self {
+        Self {
+            system: System::new(),
+            pid: Pid::from_u32(pid),
+            start_time: None,
+        }
+    }
+
+    /// Read the process now; `None` once it's gone or its pid was reused.
+    pub fn sample(&mut self, timestamp: u64) -> Option<ResourceSample> {
+        let refresh = ProcessRefreshKind::nothing()
+            .with_cpu()
+            .with_memory()
+            .without_tasks();
+        let pids = [self.pid];
+        self.system
+            .refresh_processes_specifics(ProcessesToUpdate::Some(&pids), true, refresh);
+        let process = self.system.process(self.pid)?;
+        if *self.start_time.get_or_insert(process.start_time()) != process.start_time() {
+            return None;
+        }
+        Some(ResourceSample {
+            timestamp,
+            cpu_percent: process.cpu_usage(),
+            rss_bytes: process.memory(),
+            open_fds: process.open_files(),
+        })
+    }
+}
+
+/// The newest samples of the current child, oldest first.
+#[derive(Default)]
+pub(crate) struct ResourceHistory(VecDeque<ResourceSample>);
+
+impl ResourceHistory {
+    pub fn push(&mut self, sample: ResourceSample) {
+        if self.0.len() == HISTORY_CAPACITY {
+            self.0.pop_front();
+        }
+        self.0.push_back(sample);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn samples(&self) -> Vec<ResourceSample> {
+        self.0.iter().cloned().collect()
+    }
+}
+
+/// Picks the samples that become `sidecar-resources` events: any that moved
+/// noticeably since the last one sent, otherwise one every `EMIT_MAX_GAP`.
+#[derive(Default)]
+pub(crate) struct EmitThrottle {
+    last: Option<(Instant, ResourceSample)>,
+}
+
+impl EmitThrottle {
+    pub fn admit(&mut self, sample: &ResourceSample, now: Instant) -> bool {
+        let due = match &self.last {
+            None => true,
+            Some((at, last)) => {
+                let fds_moved = match (sample.open_fds, last.open_fds) {
+                    (Some(now), Some(then)) => now.abs_diff(then) >= FD_STEP,
+                    (now, then) => now != then,
+                };
+                now.duration_since(*at) >= EMIT_MAX_GAP
+                    || (sample.cpu_percent - last.cpu_percent).abs() >= CPU_STEP
+                    || sample.rss_bytes.abs_diff(last.rss_bytes) >= RSS_STEP
+                    || fds_moved
+            }
+        };
+        if due {
+            self.last = Some((now, sample.clone()));
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_a_running_process() {
+        let mut sampler = Sampler::new(std::process::id());
+        let sample = sampler.sample(42).unwrap();
+        assert_eq!(sample.timestamp, 42);
+        assert!(sample.rss_bytes > 0);
+        assert!(sampler.sample(43).is_some());
+    }
+
+    #[test]
+    fn emits_on_change_or_after_a_quiet_gap() {
+        let sample = ResourceSample {
+            timestamp: 0,
+            cpu_percent: 10.0,
+            rss_bytes: 100 << 20,
+            open_fds: Some(20),
+        };
+        let start = Instant::now();
+        let mut throttle = EmitThrottle::default();
+        assert!(throttle.admit(&sample, start));
+
+        let small = ResourceSample {
+            cpu_percent: 12.0,
+            open_fds: Some(25),
+            ..sample.clone()
+        };
+        assert!(!throttle.admit(&small, start + Duration::from_secs(5)));
+        let busy = ResourceSample {
+            cpu_percent: 40.0,
+            ..sample.clone()
+        };
+        assert!(throttle.admit(&busy, start + Duration::from_secs(10)));
+        assert!(!throttle.admit(&busy, start + Duration::from_secs(20)));
+        assert!(throttle.admit(&busy, start + Duration::from_secs(40)));
+    }
+}
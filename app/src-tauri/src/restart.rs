@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+use crate::error::SidecarError;
+
+/// Outcome of a restart: the new port (`None` in socket mode) or why it failed.
+pub(crate) type RestartResult = Result<Option<u16>, SidecarError>;
+
+type ResultRx = watch::Receiver<Option<RestartResult>>;
+
+/// Tracks the in-flight restart so repeated requests share its result instead
+/// of spawning and killing processes back to back.
+#[derive(Default)]
+pub(crate) struct RestartGate {
+    current: Option<ResultRx>,
+}
+
+/// What a caller should do after asking the gate for a restart.
+pub(crate) enum Turn {
+    /// No restart is running: perform it, then hand the result to `finish`.
+    Lead(RestartLease),
+    /// A restart is already running: wait for its result with `join`.
+    Join(ResultRx),
+}
+
+/// Held by whoever is performing the restart. Dropping it without calling
+/// `finish` releases the gate and fails any waiters.
+pub(crate) struct RestartLease {
+    tx: watch::Sender<Option<RestartResult>>,
+}
+
+impl RestartGate {
+    /// Whether a restart is currently in progress.
+    pub fn is_restarting(&self) -> bool {
+        // The sender lives exactly as long as the lease, so a closed channel
+        // means the restart finished (or was abandoned).
+        self.current.as_ref().is_some_and(|rx| rx.has_changed().is_ok())
+    }
+
+    pub fn begin(&mut self) -> Turn {
+        if let Some(rx) = self.current.as_ref().filter(|_| self.is_restarting()) {
+            return Turn::Join(rx.clone());
+        }
+        let (tx, rx) = watch::channel(None);
+        self.current = Some(rx);
+        Turn::Lead(RestartLease { tx })
+    }
+}
+
+impl RestartLease {
+    pub fn finish(self, result: &RestartResult) {
+        self.tx.send_replace(Some(result.clone()));
+    }
+}
+
+/// How many automatic restarts are allowed within a sliding window before the
+/// supervisor treats the sidecar as crash-looping.
+#[derive(Default)]
+pub(crate) struct CrashBudget {
+    history: VecDeque<Instant>,
+}
+
+impl CrashBudget {
+    /// Record a restart at `now` if fewer than `limit` happened within `window`,
+    /// returning its attempt number; `None` means the budget is exhausted.
+    pub fn spend(&mut self, now: Instant, window: Duration, limit: u32) -> Option<u32> {
+        while self.history.front().is_some_and(|t| now.duration_since(*t) > window) {
+            self.history.pop_front();
+        }
+        if self.history.len() as u32 >= limit {
+            return None;
+        }
+        self.history.push_back(now);
+        Some(self.history.len() as u32)
+    }
+
+    /// Forget past restarts, e.g. when the user retries after the supervisor gave up.
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// Restarts counted against the budget as of the last `spend`.
+    pub fn recent(&self) -> usize {
+        self.history.len()
+    }
+}
+
+/// Wait for the in-flight restart and return its result.
+pub(crate) async fn join(mut rx: ResultRx) -> RestartResult {
+    let _ = rx.wait_for(Option::is_some).await;
+    let result = rx.borrow().clone();
+    result.unwrap_or(Err(SidecarError::RestartAbandoned))
+}
+
+/// Run `restart` unless one is already in flight, in which case wait for that
+/// one instead. The lock is only held to consult the gate, never across awaits.
+pub(crate) async fn debounced<S, F, Fut>(
+    state: &Mutex<S>,
+    gate: fn(&mut S) -> &mut RestartGate,
+    restart: F,
+) -> RestartResult
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = RestartResult>,
+{
+    let turn = {
+        let mut s = state.lock().map_err(|_| SidecarError::LockPoisoned)?;
+        gate(&mut s).begin()
+    };
+    match turn {
+        Turn::Join(rx) => join(rx).await,
+        Turn::Lead(lease) => {
+            let result = restart().await;
+            lease.finish(&result);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    fn identity(gate: &mut RestartGate) -> &mut RestartGate {
+        gate
+    }
+
+    #[tokio::test]
+    async fn concurrent_restarts_share_one_spawn() {
+        let state = Mutex::new(RestartGate::default());
+        let spawns = AtomicU32::new(0);
+        let restart = || async {
+            let n = spawns.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(Some(40_000 + n as u16))
+        };
+
+        let (a, b, c) = tokio::join!(
+            debounced(&state, identity, restart),
+            debounced(&state, identity, restart),
+            debounced(&state, identity, restart),
+        );
+
+        assert_eq!(spawns.load(Ordering::SeqCst), 1);
+        assert_eq!([a, b, c].map(Result::unwrap), [Some(40_001); 3]);
+        assert!(!state.lock().unwrap().is_restarting());
+    }
+
+    #[tokio::test]
+    async fn restart_after_completion_spawns_again() {
+        let state = Mutex::new(RestartGate::default());
+        let spawns = AtomicU32::new(0);
+        let restart = || async {
+            spawns.fetch_add(1, Ordering::SeqCst);
+            Ok(None)
+        };
+
+        debounced(&state, identity, restart).await.unwrap();
+        debounced(&state, identity, restart).await.unwrap();
+
+        assert_eq!(spawns.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn abandoned_restart_fails_waiters_and_frees_gate() {
+        let mut gate = RestartGate::default();
+        let Turn::Lead(lease) = gate.begin() else { panic!("gate should be free") };
+        let Turn::Join(rx) = gate.begin() else { panic!("restart should be in flight") };
+
+        drop(lease);
+
+        assert!(matches!(join(rx).await, Err(SidecarError::RestartAbandoned)));
+        assert!(matches!(gate.begin(), Turn::Lead(_)));
+    }
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn restarts_within_the_window_exhaust_the_budget() {
+        let mut budget = CrashBudget::default();
+        let start = Instant::now();
+        let attempts: Vec<_> =
+            (0..3).map(|i| budget.spend(start + Duration::from_secs(i), WINDOW, 3)).collect();
+
+        assert_eq!(attempts, [Some(1), Some(2), Some(3)]);
+        assert_eq!(budget.spend(start + Duration::from_secs(3), WINDOW, 3), None);
+        assert_eq!(budget.recent(), 3);
+    }
+
+    #[test]
+    fn restarts_outside_the_window_expire() {
+        let mut budget = CrashBudget::default();
+        let start = Instant::now();
+        budget.spend(start, WINDOW, 2);
+        budget.spend(start + Duration::from_secs(30), WINDOW, 2);
+
+        // The first restart has aged out; the second still counts.
+        assert_eq!(budget.spend(start + Duration::from_secs(61), WINDOW, 2), Some(2));
+        assert_eq!(budget.spend(start + Duration::from_secs(62), WINDOW, 2), None);
+    }
+
+    #[test]
+    fn reset_restores_the_full_budget() {
+        let mut budget = CrashBudget::default();
+        let now = Instant::now();
+        budget.spend(now, WINDOW, 1);
+        assert_eq!(budget.spend(now, WINDOW, 1), None);
+
+        budget.reset();
+
+        assert_eq!(budget.recent(), 0);
+        assert_eq!(budget.spend(now, WINDOW, 1), Some(1));
+    }
+}
@@ -0,0 +1,13 @@
+//! Scratch space for tests that touch the filesystem.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// A fresh, empty directory of the test's own, `claudetini-<name>-<pid>`
+/// under the temp dir. Whatever an earlier run left there is removed first.
+pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("claudetini-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
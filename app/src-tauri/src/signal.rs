@@ -0,0 +1,81 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::SidecarError;
+
+/// Signals `signal_sidecar` may send, for sidecars that reload config or dump
+/// state on them. Termination signals are excluded: stopping goes through the
+/// supervisor, so the exit isn't mistaken for a crash.
+#[cfg(unix)]
+const ALLOWED_SIGNALS: &[libc::c_int] = &[libc::SIGHUP, libc::SIGUSR1, libc::SIGUSR2];
+
+/// Send signal `signum` to process `pid` if it's on the allowlist.
+#[cfg(unix)]
+pub(crate) fn send(pid: u32, signum: i32) -> Result<(), SidecarError> {
+    if !ALLOWED_SIGNALS.contains(&signum) {
+        return Err(SidecarError::InvalidSignal(signum));
+    }
+    // SAFETY: kill(2) has no memory-safety preconditions.
+    if unsafe { libc::kill(pid as libc::pid_t, signum) } == 0 {
+        Ok(())
+    } else {
+        Err(SidecarError::Signal(Arc::new(io::Error::last_os_error())))
+    }
+}
+
+/// Ask process `pid` to shut down with SIGTERM, then give `exited` up to
+/// `timeout` to resolve. Returns whether it exited in time; if not, the caller
+/// should kill it. The caller must still hold the child, so it can't have been
+/// reaped and its pid reused.
+#[cfg(unix)]
+pub(crate) async fn terminate_gracefully(
+    pid: u32,
+    exited: impl Future,
+    timeout: Duration,
+) -> bool {
+    // SAFETY: kill(2) has no memory-safety preconditions.
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+        return false;
+    }
+    tokio::time::timeout(timeout, exited).await.is_ok()
+}
+
+/// Windows has no signals to send.
+#[cfg(windows)]
+pub(crate) fn send(_pid: u32, _signum: i32) -> Result<(), SidecarError> {
+    Err(SidecarError::Signal(Arc::new(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "signals are only supported on Unix",
+    ))))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::stub_sidecar::StubSidecar;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn rejects_signals_outside_allowlist() {
+        // Rejected before anything is sent, so this can't hit the test process.
+        let result = send(std::process::id(), libc::SIGKILL);
+        assert!(matches!(result, Err(SidecarError::InvalidSignal(libc::SIGKILL))));
+        assert!(matches!(send(std::process::id(), 0), Err(SidecarError::InvalidSignal(0))));
+    }
+
+    #[tokio::test]
+    async fn stub_stops_on_sigterm() {
+        let mut stub = StubSidecar::spawn(&[]);
+        let pid = stub.pid();
+        let exited = async {
+            while stub.try_wait().is_none() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+
+        assert!(terminate_gracefully(pid, exited, Duration::from_secs(5)).await);
+        assert_eq!(stub.wait().signal(), Some(libc::SIGTERM));
+    }
+}
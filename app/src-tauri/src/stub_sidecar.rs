@@ -0,0 +1,231 @@
+//! Test harness around `examples/stub_sidecar.rs`, a tiny stand-in for the
+//! Python sidecar that `cargo test` builds alongside the unit tests. Its own
+//! tests run the real `spawn_sidecar` on it, as a custom binary under Tauri's
+//! mock runtime, through to a clean exit or an early crash.
+
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::transport::{SidecarEndpoint, LOOPBACK_HOST};
+
+/// How long the stub may take to bind and print `READY`.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A running stub sidecar on an OS-assigned loopback port. Killed on drop,
+/// so a failing test doesn't leave it behind.
+pub(crate) struct StubSidecar {
+    child: Child,
+    pub port: u16,
+}
+
+impl StubSidecar {
+    /// Start the stub with extra `args` and wait until it prints `READY`.
+    pub fn spawn(args: &[&str]) -> Self {
+        let mut child = Command::new(binary_path())
+            .args(["--port", "0"])
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start the stub sidecar");
+        let stdout = child.stdout.take().expect("stub stdout is piped");
+
+        // Keep draining stdout on a thread so the stub never blocks on a full pipe.
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut port = None;
+        loop {
+            let line = rx.recv_timeout(STARTUP_TIMEOUT).expect("stub never printed READY");
+            if let Some(p) = line.strip_prefix("CLAUDETINI_PORT=") {
+                port = p.parse().ok();
+            }
+            if line == "READY" {
+                break;
+            }
+        }
+        Self {
+            child,
+            port: port.expect("stub didn't announce its port"),
+        }
+    }
+
+    pub fn endpoint(&self) -> SidecarEndpoint {
+        SidecarEndpoint::tcp(LOOPBACK_HOST, self.port)
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Exit status, if the stub has exited.
+    pub fn try_wait(&mut self) -> Option<ExitStatus> {
+        self.child.try_wait().expect("failed to poll the stub")
+    }
+
+    /// Block until the stub exits.
+    pub fn wait(&mut self) -> ExitStatus {
+        self.child.wait().expect("failed to wait for the stub")
+    }
+}
+
+impl Drop for StubSidecar {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// `target/<profile>/examples/stub_sidecar`, next to the `deps` directory the
+/// test binary runs from.
+fn binary_path() -> PathBuf {
+    let exe = std::env::current_exe().expect("no test executable path");
+    let profile_dir = exe
+        .parent()
+        .and_then(|deps| deps.parent())
+        .expect("test binary outside target dir");
+    let name = format!("stub_sidecar{}", std::env::consts::EXE_SUFFIX);
+    let path = profile_dir.join("examples").join(name);
+    assert!(
+        path.exists(),
+        "{} is missing; run plain `cargo test`, which builds the examples",
+        path.display()
+    );
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    use tauri::test::{mock_builder, mock_context, noop_assets};
+    use tauri::utils::config::AppDirectoriesOverride;
+    use tauri::{App, Listener, Manager};
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use super::*;
+    use crate::config::SidecarConfig;
+    use crate::logwriter::LogWriter;
+    use crate::resources::Sampler;
+    use crate::scratch::scratch_dir;
+    use crate::visibility::Visibility;
+    use crate::{
+        manage_supervisor, shutdown_sidecar, spawn_sidecar, SidecarRuntime, SidecarState,
+        SidecarStateChangedPayload, SidecarStatus,
+    };
+
+    type StateChanges = UnboundedReceiver<SidecarStateChangedPayload>;
+
+    /// A mock app supervising the stub with `config`. Logs, the discovery
+    /// file and the like go to the returned scratch dir rather than the real
+    /// app dirs.
+    fn supervised_stub(
+        name: &str,
+        config: SidecarConfig,
+    ) -> (App<SidecarRuntime>, PathBuf, StateChanges) {
+        let dir = scratch_dir(name);
+        let mut context = mock_context(noop_assets());
+        context.config_mut().app.app_directories_override =
+            Some(AppDirectoriesOverride::Root(dir.clone()));
+        let app = mock_builder()
+            .plugin(tauri_plugin_shell::init())
+            .manage(Visibility::new())
+            .build(context)
+            .unwrap();
+        let config = SidecarConfig {
+            custom_binary: Some(binary_path()),
+            ..config
+        };
+        let state_changes =
+            manage_supervisor(app.handle(), config, LogWriter::spawn(), Instant::now());
+        (app, dir, state_changes)
+    }
+
+    /// Payloads of every `event` the app emits from now on.
+    fn payloads(app: &App<SidecarRuntime>, event: &str) -> mpsc::Receiver<serde_json::Value> {
+        let (tx, rx) = mpsc::channel();
+        app.listen(event, move |event| {
+            let _ = tx.send(serde_json::from_str(event.payload()).unwrap());
+        });
+        rx
+    }
+
+    #[test]
+    fn supervises_a_custom_binary_from_spawn_to_exit() {
+        let (app, dir, _state_changes) = supervised_stub("spawn", SidecarConfig::default());
+        let ready_rx = payloads(&app, "sidecar-ready");
+        spawn_sidecar(app.handle()).unwrap();
+        let ready = ready_rx.recv_timeout(STARTUP_TIMEOUT).expect("sidecar-ready never came");
+
+        let state = app.state::<Mutex<SidecarState>>();
+        let (pid, port) = {
+            let s = state.lock().unwrap();
+            assert!(matches!(s.status, SidecarStatus::Ready));
+            (s.child.as_ref().unwrap().pid(), s.endpoint.as_ref().and_then(|e| e.port()))
+        };
+        assert_eq!(ready["port"].as_u64(), port.map(u64::from));
+
+        shutdown_sidecar(app.handle());
+        assert!(state.lock().unwrap().child.is_none());
+        let mut sampler = Sampler::new(pid);
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        while sampler.sample(0).is_some() {
+            assert!(Instant::now() < deadline, "sidecar {pid} outlived the app");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reports_an_early_exit_and_gives_up_without_ready() {
+        let config = SidecarConfig {
+            extra_args: vec!["--exit-code".into(), "3".into()],
+            // Give up on the first crash, so the test ends in a known state.
+            max_restarts: 0,
+            ..SidecarConfig::default()
+        };
+        let (app, dir, mut state_changes) = supervised_stub("early-exit", config);
+        let ready_rx = payloads(&app, "sidecar-ready");
+        let error_rx = payloads(&app, "sidecar-error");
+        let failed_rx = payloads(&app, "sidecar-failed");
+        spawn_sidecar(app.handle()).unwrap();
+
+        let error = error_rx.recv_timeout(STARTUP_TIMEOUT).expect("sidecar-error never came");
+        assert_eq!(error["kind"], "exited_during_startup");
+        assert_eq!(error["exit_code"], 3);
+        failed_rx.recv_timeout(STARTUP_TIMEOUT).expect("sidecar-failed never came");
+
+        // Degraded follows `sidecar-failed`, so wait for it rather than look once.
+        let mut seen = Vec::new();
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        while !matches!(seen.last(), Some(SidecarStatus::Degraded { .. })) {
+            match state_changes.try_recv() {
+                Ok(change) => seen.push(change.to),
+                Err(_) => {
+                    assert!(Instant::now() < deadline, "never degraded: {seen:?}");
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+            }
+        }
+        assert_eq!(seen.first(), Some(&SidecarStatus::Starting));
+        assert!(ready_rx.try_recv().is_err(), "a sidecar that exited was announced ready");
+        {
+            let state = app.state::<Mutex<SidecarState>>();
+            let s = state.lock().unwrap();
+            assert!(s.child.is_none());
+            assert_eq!(s.last_exit.as_ref().and_then(|exit| exit.code), Some(3));
+        }
+
+        shutdown_sidecar(app.handle());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
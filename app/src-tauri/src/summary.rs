@@ -0,0 +1,85 @@
+use std::fmt::Write as _;
+
+/// Output lines quoted at the end of the summary.
+pub(crate) const SUMMARY_LOG_LINES: usize = 20;
+/// Stands in for anything that couldn't be found out, e.g. with the sidecar down.
+const UNAVAILABLE: &str = "unavailable";
+
+/// What goes into the plain-text summary for a quick bug report. Anything
+/// `None` is printed as "unavailable".
+#[derive(Default)]
+pub(crate) struct Summary {
+    pub app_version: String,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub sidecar_version: Option<String>,
+    pub status: Option<String>,
+    pub uptime_ms: Option<u64>,
+    pub restart_count: Option<u32>,
+    pub last_error: Option<String>,
+    /// Already redacted.
+    pub recent_logs: Vec<String>,
+}
+
+impl Summary {
+    pub fn render(&self) -> String {
+        fn or_unavailable(value: Option<String>) -> String {
+            value.unwrap_or_else(|| UNAVAILABLE.to_string())
+        }
+        let mut out = String::new();
+        let _ = writeln!(out, "Claudetini {} on {}/{}", self.app_version, self.os, self.arch);
+        let _ = writeln!(out, "Sidecar version: {}", or_unavailable(self.sidecar_version.clone()));
+        let _ = writeln!(out, "Sidecar status: {}", or_unavailable(self.status.clone()));
+        let uptime = self.uptime_ms.map(|ms| format!("{}s", ms / 1000));
+        let _ = writeln!(out, "Uptime: {}", or_unavailable(uptime));
+        let restarts = self.restart_count.map(|n| n.to_string());
+        let _ = writeln!(out, "Restarts: {}", or_unavailable(restarts));
+        let last_error = self.last_error.as_deref().unwrap_or("none");
+        let _ = writeln!(out, "Last error: {last_error}");
+        if self.recent_logs.is_empty() {
+            let _ = writeln!(out, "Recent output: {UNAVAILABLE}");
+        } else {
+            let _ = writeln!(out, "Recent output ({} lines):", self.recent_logs.len());
+            for line in &self.recent_logs {
+                let _ = writeln!(out, "  {line}");
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_unavailable_fields() {
+        let summary = Summary {
+            app_version: "0.1.0".into(),
+            os: "macos",
+            arch: "aarch64",
+            status: Some("stopped".into()),
+            ..Summary::default()
+        };
+        assert_eq!(
+            summary.render(),
+            "Claudetini 0.1.0 on macos/aarch64\n\
+             Sidecar version: unavailable\n\
+             Sidecar status: stopped\n\
+             Uptime: unavailable\n\
+             Restarts: unavailable\n\
+             Last error: none\n\
+             Recent output: unavailable\n"
+        );
+
+        let summary = Summary {
+            uptime_ms: Some(61_500),
+            restart_count: Some(2),
+            recent_logs: vec!["[stdout] started".into()],
+            ..summary
+        };
+        let text = summary.render();
+        assert!(text.contains("Uptime: 61s\nRestarts: 2\n"));
+        assert!(text.ends_with("Recent output (1 lines):\n  [stdout] started\n"));
+    }
+}
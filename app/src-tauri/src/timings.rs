@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Points on the way from app launch to the first `sidecar-ready`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Milestone {
+    /// The sidecar's port (or socket) is settled: reserved, announced by a
+    /// `--port 0` sidecar, or the dev port.
+    PortAcquired,
+    Spawned,
+    FirstHealthAttempt,
+    Ready,
+}
+
+impl Milestone {
+    const ALL: [Milestone; 4] = [
+        Milestone::PortAcquired,
+        Milestone::Spawned,
+        Milestone::FirstHealthAttempt,
+        Milestone::Ready,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Milestone::PortAcquired => "port",
+            Milestone::Spawned => "spawned",
+            Milestone::FirstHealthAttempt => "first health check",
+            Milestone::Ready => "ready",
+        }
+    }
+}
+
+/// When each milestone was first reached, measured from app setup. Later
+/// restarts don't move them: they describe how the app booted.
+pub(crate) struct StartupTimings {
+    setup_started: Instant,
+    reached: [Option<Duration>; Milestone::ALL.len()],
+}
+
+/// Milliseconds from app setup to each milestone, `None` until reached.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct StartupTimingsReport {
+    pub port_acquired_ms: Option<u64>,
+    pub sidecar_spawned_ms: Option<u64>,
+    pub first_health_attempt_ms: Option<u64>,
+    pub ready_ms: Option<u64>,
+}
+
+impl StartupTimings {
+    pub fn new(setup_started: Instant) -> Self {
+        Self {
+            setup_started,
+            reached: [None; Milestone::ALL.len()],
+        }
+    }
+
+    /// Record `milestone` as reached now, unless it already was. Returns
+    /// whether this was the first time.
+    pub fn mark(&mut self, milestone: Milestone) -> bool {
+        self.mark_at(milestone, Instant::now())
+    }
+
+    fn mark_at(&mut self, milestone: Milestone, at: Instant) -> bool {
+        let slot = &mut self.reached[milestone as usize];
+        if slot.is_some() {
+            return false;
+        }
+        *slot = Some(at.saturating_duration_since(self.setup_started));
+        true
+    }
+
+    fn ms(&self, milestone: Milestone) -> Option<u64> {
+        self.reached[milestone as usize].map(|d| d.as_millis() as u64)
+    }
+
+    pub fn report(&self) -> StartupTimingsReport {
+        StartupTimingsReport {
+            port_acquired_ms: self.ms(Milestone::PortAcquired),
+            sidecar_spawned_ms: self.ms(Milestone::Spawned),
+            first_health_attempt_ms: self.ms(Milestone::FirstHealthAttempt),
+            ready_ms: self.ms(Milestone::Ready),
+        }
+    }
+
+    /// One line for the log listing the milestones reached so far.
+    pub fn summary(&self) -> String {
+        let parts: Vec<_> = Milestone::ALL
+            .iter()
+            .filter_map(|&m| Some(format!("{} {}ms", m.label(), self.ms(m)?)))
+            .collect();
+        format!("Startup timings since app setup: {}", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_first_time_each_milestone_is_reached() {
+        let start = Instant::now();
+        let mut timings = StartupTimings::new(start);
+        assert!(timings.mark_at(Milestone::PortAcquired, start + Duration::from_millis(12)));
+        assert!(timings.mark_at(Milestone::Spawned, start + Duration::from_millis(30)));
+        assert!(!timings.mark_at(Milestone::Spawned, start + Duration::from_millis(900)));
+        assert!(timings.mark_at(Milestone::Ready, start + Duration::from_millis(420)));
+
+        let report = timings.report();
+        assert_eq!(report.sidecar_spawned_ms, Some(30));
+        assert_eq!(report.first_health_attempt_ms, None);
+        assert_eq!(
+            timings.summary(),
+            "Startup timings since app setup: port 12ms, spawned 30ms, ready 420ms"
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::transport::Connection;
+
+/// Variables telling the sidecar where its certificate and key are. Passed
+/// in the environment rather than as flags so older sidecars just ignore
+/// them and keep serving plain HTTP.
+pub(crate) const CERT_ENV: &str = "CLAUDETINI_TLS_CERT";
+pub(crate) const KEY_ENV: &str = "CLAUDETINI_TLS_KEY";
+/// Subdirectory of the app data dir holding the certificate and key.
+pub(crate) const DIR_NAME: &str = "tls";
+const CERT_FILE_NAME: &str = "sidecar-cert.pem";
+const KEY_FILE_NAME: &str = "sidecar-key.pem";
+/// The certificate covers every name the sidecar is reached by locally.
+const SUBJECT_NAMES: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+/// This install's self-signed certificate for the sidecar, kept in the app
+/// data dir until rotated.
+#[derive(Clone)]
+pub(crate) struct Certificate {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    der: CertificateDer<'static>,
+    pem: String,
+}
+
+impl Certificate {
+    /// The certificate in `dir`, generating one if there is none or it
+    /// doesn't parse.
+    pub fn load_or_generate(dir: &Path) -> io::Result<Self> {
+        let cert_path = dir.join(CERT_FILE_NAME);
+        let key_path = dir.join(KEY_FILE_NAME);
+        if key_path.is_file() {
+            if let Ok(pem) = fs::read_to_string(&cert_path) {
+                if let Ok(der) = CertificateDer::from_pem_slice(pem.as_bytes()) {
+                    return Ok(Self {
+                        cert_path,
+                        key_path,
+                        der,
+                        pem,
+                    });
+                }
+            }
+        }
+        Self::generate(dir)
+    }
+
+    /// Make a fresh certificate and key in `dir`, replacing any there.
+    pub fn generate(dir: &Path) -> io::Result<Self> {
+        let names = SUBJECT_NAMES.map(str::to_string).to_vec();
+        let generated = rcgen::generate_simple_self_signed(names).map_err(io::Error::other)?;
+        fs::create_dir_all(dir)?;
+        let cert_path = dir.join(CERT_FILE_NAME);
+        let key_path = dir.join(KEY_FILE_NAME);
+        write_private(&key_path, generated.signing_key.serialize_pem().as_bytes())?;
+        let pem = generated.cert.pem();
+        fs::write(&cert_path, &pem)?;
+        Ok(Self {
+            cert_path,
+            key_path,
+            der: generated.cert.der().clone(),
+            pem,
+        })
+    }
+
+    /// SHA-256 of the certificate as colon-separated hex, the form browsers
+    /// and `openssl x509 -fingerprint` show.
+    pub fn fingerprint(&self) -> String {
+        let digest = Sha256::digest(&self.der);
+        let hex: Vec<_> = digest.iter().map(|b| format!("{b:02X}")).collect();
+        hex.join(":")
+    }
+
+    pub fn pem(&self) -> &str {
+        &self.pem
+    }
+}
+
+/// The key is only for the sidecar, so other local users can't read it.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(contents)
+    }
+    #[cfg(not(unix))]
+    fs::write(path, contents)
+}
+
+/// How one child is reached over TLS: only with the certificate it was
+/// given, or in the clear if it turned out to be an older sidecar before it
+/// became ready. Shared by every copy of the child's endpoint.
+pub(crate) struct Pin {
+    connector: TlsConnector,
+    fingerprint: String,
+    /// Cleared by `settle` once the child is ready, after which a sidecar that
+    /// stops speaking TLS is an error rather than a downgrade.
+    may_fall_back: AtomicBool,
+    plain: AtomicBool,
+}
+
+impl Pin {
+    pub fn new(certificate: &Certificate) -> Self {
+        let provider = Arc::new(ring::default_provider());
+        let verifier = PinnedVerifier {
+            der: certificate.der.clone(),
+            provider: provider.clone(),
+        };
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .expect("the ring provider supports the default protocol versions")
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        Self {
+            connector: TlsConnector::from(Arc::new(config)),
+            fingerprint: certificate.fingerprint(),
+            may_fall_back: AtomicBool::new(true),
+            plain: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether the child is being reached over plain HTTP after all.
+    pub fn is_plain(&self) -> bool {
+        self.plain.load(Ordering::Relaxed)
+    }
+
+    /// The pinned certificate's fingerprint, while TLS is in use.
+    pub fn fingerprint(&self) -> Option<&str> {
+        (!self.is_plain()).then_some(self.fingerprint.as_str())
+    }
+
+    /// Keep whichever of TLS or plain HTTP the child answered during startup.
+    pub fn settle(&self) {
+        self.may_fall_back.store(false, Ordering::Relaxed);
+    }
+
+    pub async fn connect(&self, host: &str, port: u16) -> io::Result<Box<dyn Connection>> {
+        let stream = TcpStream::connect((host, port)).await?;
+        if self.is_plain() {
+            return Ok(Box::new(stream));
+        }
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        match self.connector.connect(name, stream).await {
+            Ok(stream) => Ok(Box::new(stream)),
+            Err(e) if speaks_plain_http(&e) && self.may_fall_back.load(Ordering::Relaxed) => {
+                self.plain.store(true, Ordering::Relaxed);
+                Ok(Box::new(TcpStream::connect((host, port)).await?))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl fmt::Debug for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pin")
+            .field("fingerprint", &self.fingerprint)
+            .field("plain", &self.is_plain())
+            .finish()
+    }
+}
+
+/// A handshake answered with something other than TLS, e.g. an HTTP 400, or
+/// not answered at all, as a plain HTTP server does. A wrong certificate is
+/// not this.
+fn speaks_plain_http(e: &io::Error) -> bool {
+    let tls_error = e.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>());
+    matches!(tls_error, Some(rustls::Error::InvalidMessage(_)))
+        || e.kind() == io::ErrorKind::UnexpectedEof
+}
+
+/// Trusts exactly one certificate, whatever names it carries.
+#[derive(Debug)]
+struct PinnedVerifier {
+    der: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.der.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.provider.signature_verification_algorithms;
+        verify_tls12_signature(message, cert, dss, algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.provider.signature_verification_algorithms;
+        verify_tls13_signature(message, cert, dss, algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::ServerConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    /// Serve one TLS connection with `certificate`, echoing a line back.
+    async fn serve_tls(certificate: &Certificate) -> u16 {
+        let key = PrivateKeyDer::from_pem_file(&certificate.key_path).unwrap();
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate.der.clone()], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let Ok(mut stream) = acceptor.accept(stream).await else { continue };
+                let mut buf = [0; 5];
+                if stream.read_exact(&mut buf).await.is_ok() {
+                    let _ = stream.write_all(&buf).await;
+                }
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn keeps_the_certificate_until_rotated() {
+        let dir = scratch_dir("tls-keep");
+        let first = Certificate::load_or_generate(&dir).unwrap();
+        assert!(first.pem().starts_with("-----BEGIN CERTIFICATE-----"));
+        assert_eq!(first.fingerprint().len(), 32 * 3 - 1);
+        let again = Certificate::load_or_generate(&dir).unwrap();
+        assert_eq!(again.fingerprint(), first.fingerprint());
+        let rotated = Certificate::generate(&dir).unwrap();
+        assert_ne!(rotated.fingerprint(), first.fingerprint());
+        assert_eq!(rotated.cert_path, first.cert_path);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn connects_only_to_the_pinned_certificate() {
+        let dir = scratch_dir("tls-pin");
+        let served = Certificate::generate(&dir.join("served")).unwrap();
+        let other = Certificate::generate(&dir.join("other")).unwrap();
+        let port = serve_tls(&served).await;
+
+        let pin = Pin::new(&served);
+        let mut stream = pin.connect("127.0.0.1", port).await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        let mut echoed = [0; 5];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+        assert_eq!(pin.fingerprint(), Some(served.fingerprint().as_str()));
+
+        // Mismatched certificates are refused, never downgraded.
+        let wrong = Pin::new(&other);
+        assert!(wrong.connect("127.0.0.1", port).await.is_err());
+        assert!(!wrong.is_plain());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_plain_http_only_before_settling() {
+        let dir = scratch_dir("tls-plain");
+        let certificate = Certificate::generate(&dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await;
+            }
+        });
+
+        let settled = Pin::new(&certificate);
+        settled.settle();
+        assert!(settled.connect("127.0.0.1", port).await.is_err());
+
+        let starting = Pin::new(&certificate);
+        assert!(starting.connect("127.0.0.1", port).await.is_ok());
+        assert!(starting.is_plain());
+        assert_eq!(starting.fingerprint(), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
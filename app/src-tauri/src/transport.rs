@@ -0,0 +1,347 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::tls::Pin;
+
+/// Default address the sidecar listens on in TCP mode.
+pub(crate) const LOOPBACK_HOST: &str = "127.0.0.1";
+pub(crate) const LOOPBACK_V6_HOST: &str = "::1";
+/// Address a LAN-bound sidecar listens on; health checks still use loopback.
+pub(crate) const LAN_BIND_HOST: &str = "0.0.0.0";
+
+/// Longest socket path we'll hand to the sidecar; `sun_path` is 104 bytes on macOS.
+#[cfg(unix)]
+const MAX_SOCKET_PATH_LEN: usize = 100;
+
+/// How the Rust side and the sidecar talk to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Transport {
+    /// Loopback TCP port. Visible to every local user, but the webview can reach it.
+    Tcp,
+    /// Unix domain socket under the app data dir (named pipe on Windows).
+    Socket,
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(Transport::Tcp),
+            "socket" | "unix" | "pipe" => Ok(Transport::Socket),
+            other => Err(format!("unknown transport {other:?} (expected tcp or socket)")),
+        }
+    }
+}
+
+/// Canonical base URL for the sidecar, so the frontend never assembles one
+/// by hand. Sockets use the `http+unix` convention with the path encoded;
+/// a TLS sidecar is `https`.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct SidecarUrl {
+    pub base_url: String,
+    pub scheme: &'static str,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+/// Where the running sidecar can be reached.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "transport", rename_all = "snake_case")]
+pub(crate) enum SidecarEndpoint {
+    Tcp {
+        host: String,
+        port: u16,
+        /// Set when the child was given a certificate to serve HTTPS with.
+        #[serde(skip)]
+        tls: Option<Arc<Pin>>,
+    },
+    /// Unix domain socket path, or named pipe name on Windows.
+    Socket { path: PathBuf },
+}
+
+impl SidecarEndpoint {
+    pub fn tcp(host: &str, port: u16) -> Self {
+        SidecarEndpoint::Tcp {
+            host: host.to_string(),
+            port,
+            tls: None,
+        }
+    }
+
+    /// The same endpoint, reached over TLS pinned by `pin`. Sockets are
+    /// private to the user already and stay as they are.
+    pub fn with_tls(self, pin: Arc<Pin>) -> Self {
+        match self {
+            SidecarEndpoint::Tcp { host, port, .. } => SidecarEndpoint::Tcp {
+                host,
+                port,
+                tls: Some(pin),
+            },
+            socket => socket,
+        }
+    }
+
+    /// How TLS connections are pinned, if this endpoint uses it.
+    pub fn tls(&self) -> Option<&Pin> {
+        match self {
+            SidecarEndpoint::Tcp { tls, .. } => tls.as_deref(),
+            SidecarEndpoint::Socket { .. } => None,
+        }
+    }
+
+    /// The same endpoint on `port`, once the sidecar has announced it.
+    pub fn with_port(self, port: u16) -> Self {
+        match self {
+            SidecarEndpoint::Tcp { host, tls, .. } => SidecarEndpoint::Tcp { host, port, tls },
+            socket => socket,
+        }
+    }
+
+    /// The TCP port, if this endpoint has one.
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            SidecarEndpoint::Tcp { port, .. } => Some(*port),
+            SidecarEndpoint::Socket { .. } => None,
+        }
+    }
+
+    /// For a loopback TCP endpoint, the same port on both IP families with the
+    /// preferred one first, so a sidecar that only bound `::1` (or only
+    /// `127.0.0.1`) is still found. Other endpoints have no alternate.
+    pub fn loopback_families(
+        &self,
+        prefer_ipv6: bool,
+    ) -> (SidecarEndpoint, Option<SidecarEndpoint>) {
+        let SidecarEndpoint::Tcp { host, port, tls } = self else {
+            return (self.clone(), None);
+        };
+        let Some(other_host) = other_loopback(host) else {
+            return (self.clone(), None);
+        };
+        let host_is_v6 = other_host == LOOPBACK_HOST;
+        let other = SidecarEndpoint::Tcp {
+            host: other_host.to_string(),
+            port: *port,
+            tls: tls.clone(),
+        };
+        if host_is_v6 == prefer_ipv6 {
+            (self.clone(), Some(other))
+        } else {
+            (other, Some(self.clone()))
+        }
+    }
+
+    /// Command-line arguments telling the sidecar where to listen.
+    pub fn listen_args(&self) -> Vec<String> {
+        match self {
+            SidecarEndpoint::Tcp { host, port, .. } => {
+                let mut args = vec!["--port".into(), port.to_string()];
+                // Only pass --host when overridden, so sidecars without the flag still work.
+                if host != LOOPBACK_HOST {
+                    args.extend(["--host".into(), host.clone()]);
+                }
+                args
+            }
+            SidecarEndpoint::Socket { path } => {
+                vec!["--socket".into(), path.to_string_lossy().into_owned()]
+            }
+        }
+    }
+
+    pub fn url(&self) -> SidecarUrl {
+        match self {
+            SidecarEndpoint::Tcp { host, port, tls } => {
+                let scheme = match tls {
+                    Some(pin) if !pin.is_plain() => "https",
+                    _ => "http",
+                };
+                SidecarUrl {
+                    base_url: format!("{scheme}://{}", authority(host, *port)),
+                    scheme,
+                    host: host.clone(),
+                    port: Some(*port),
+                }
+            }
+            SidecarEndpoint::Socket { path } => {
+                let path = path.to_string_lossy().into_owned();
+                SidecarUrl {
+                    base_url: format!("http+unix://{}", percent_encode(&path)),
+                    scheme: "http+unix",
+                    host: path,
+                    port: None,
+                }
+            }
+        }
+    }
+
+    /// Value for the HTTP `Host` header when talking to this endpoint.
+    pub fn host_header(&self) -> String {
+        match self {
+            SidecarEndpoint::Tcp { host, port, .. } => authority(host, *port),
+            SidecarEndpoint::Socket { .. } => "localhost".to_string(),
+        }
+    }
+
+    /// Send `request` and read until the sidecar closes the connection.
+    pub async fn round_trip(&self, request: &[u8]) -> io::Result<Vec<u8>> {
+        self.round_trip_limited(request, u64::MAX).await
+    }
+
+    /// Like [`round_trip`](Self::round_trip), but stop reading after `limit`
+    /// bytes. Callers compare the length against `limit` to spot truncation.
+    pub async fn round_trip_limited(&self, request: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+        exchange(self.connect().await?, request, limit).await
+    }
+
+    /// Open a connection for callers that need to read a response as it
+    /// arrives rather than all at once. Over TLS when the endpoint has a pin.
+    pub async fn connect(&self) -> io::Result<Box<dyn Connection>> {
+        match self {
+            SidecarEndpoint::Tcp {
+                host,
+                port,
+                tls: Some(pin),
+            } => pin.connect(host, *port).await,
+            SidecarEndpoint::Tcp { host, port, .. } => {
+                Ok(Box::new(tokio::net::TcpStream::connect((host.as_str(), *port)).await?))
+            }
+            #[cfg(unix)]
+            SidecarEndpoint::Socket { path } => {
+                Ok(Box::new(tokio::net::UnixStream::connect(path).await?))
+            }
+            #[cfg(windows)]
+            SidecarEndpoint::Socket { path } => {
+                let pipe = tokio::net::windows::named_pipe::ClientOptions::new().open(path)?;
+                Ok(Box::new(pipe))
+            }
+        }
+    }
+}
+
+/// A connected stream to the sidecar over either transport.
+pub(crate) trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// `host:port`, with IPv6 literals bracketed as URLs and `Host` headers need.
+pub(crate) fn authority(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// Encode everything but RFC 3986 unreserved characters.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// The loopback address of the other IP family, if `host` is a loopback IP.
+/// `localhost` already resolves to both, so it has none.
+pub(crate) fn other_loopback(host: &str) -> Option<&'static str> {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) if ip.is_loopback() => Some(LOOPBACK_V6_HOST),
+        Ok(IpAddr::V6(ip)) if ip.is_loopback() => Some(LOOPBACK_HOST),
+        _ => None,
+    }
+}
+
+async fn exchange<S>(mut stream: S, request: &[u8], limit: u64) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(request).await?;
+    let mut response = Vec::new();
+    stream.take(limit).read_to_end(&mut response).await?;
+    Ok(response)
+}
+
+impl fmt::Display for SidecarEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SidecarEndpoint::Tcp { host, port, .. } => f.write_str(&authority(host, *port)),
+            SidecarEndpoint::Socket { path } => write!(f, "socket {}", path.display()),
+        }
+    }
+}
+
+/// Socket path for this app's sidecar, inside the per-user data dir so other
+/// local users can't reach it. Any stale socket from a previous run is removed.
+#[cfg(unix)]
+pub(crate) fn socket_endpoint(data_dir: &Path) -> Result<SidecarEndpoint, String> {
+    let path = data_dir.join("sidecar.sock");
+    if path.as_os_str().len() > MAX_SOCKET_PATH_LEN {
+        return Err(format!(
+            "Socket path {} is too long for a unix domain socket; use the tcp transport instead",
+            path.display()
+        ));
+    }
+    match std::fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Could not remove stale socket {}: {e}", path.display())),
+    }
+    Ok(SidecarEndpoint::Socket { path })
+}
+
+/// Named pipe for this app's sidecar. Pipes live in a global namespace, so the
+/// name includes our pid to avoid clashing with other instances.
+#[cfg(windows)]
+pub(crate) fn socket_endpoint(_data_dir: &Path) -> Result<SidecarEndpoint, String> {
+    let path = PathBuf::from(format!(r"\\.\pipe\claudetini-sidecar-{}", std::process::id()));
+    Ok(SidecarEndpoint::Socket { path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv6_hosts_are_bracketed() {
+        assert_eq!(SidecarEndpoint::tcp("::1", 8000).to_string(), "[::1]:8000");
+        assert_eq!(SidecarEndpoint::tcp(LOOPBACK_HOST, 8000).host_header(), "127.0.0.1:8000");
+    }
+
+    #[test]
+    fn loopback_families_follow_preference() {
+        let v4 = SidecarEndpoint::tcp(LOOPBACK_HOST, 8000);
+        let (first, alternate) = v4.loopback_families(true);
+        assert_eq!(first.to_string(), "[::1]:8000");
+        assert_eq!(alternate.unwrap().to_string(), "127.0.0.1:8000");
+
+        let (first, alternate) = v4.loopback_families(false);
+        assert_eq!(first.to_string(), "127.0.0.1:8000");
+        assert_eq!(alternate.unwrap().to_string(), "[::1]:8000");
+
+        let remote = SidecarEndpoint::tcp("10.0.0.5", 8000);
+        assert!(remote.loopback_families(false).1.is_none());
+    }
+
+    #[test]
+    fn urls_are_complete() {
+        assert_eq!(SidecarEndpoint::tcp("::1", 8000).url().base_url, "http://[::1]:8000");
+        let socket = SidecarEndpoint::Socket {
+            path: PathBuf::from("/tmp/app data/sidecar.sock"),
+        };
+        let url = socket.url();
+        assert_eq!(url.base_url, "http+unix://%2Ftmp%2Fapp%20data%2Fsidecar.sock");
+        assert_eq!(url.port, None);
+    }
+}
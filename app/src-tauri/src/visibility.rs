@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use tauri::Manager;
+
+use crate::AppHandle;
+use tokio::sync::watch;
+
+/// Tracks whether every app window is hidden or minimized, so background
+/// polling can stop waking the machine while nobody is looking.
+pub(crate) struct Visibility {
+    hidden_since: watch::Sender<Option<Instant>>,
+}
+
+impl Visibility {
+    pub fn new() -> Self {
+        Self {
+            hidden_since: watch::Sender::new(None),
+        }
+    }
+
+    pub fn set_hidden(&self, hidden: bool) {
+        self.hidden_since.send_if_modified(|since| match (hidden, since.is_some()) {
+            (true, false) => {
+                *since = Some(Instant::now());
+                true
+            }
+            (false, true) => {
+                *since = None;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<Instant>> {
+        self.hidden_since.subscribe()
+    }
+}
+
+/// Re-evaluate visibility from the current state of all windows.
+pub(crate) fn refresh(app_handle: &AppHandle) {
+    let hidden = app_handle.webview_windows().values().all(|w| {
+        !w.is_visible().unwrap_or(true) || w.is_minimized().unwrap_or(false)
+    });
+    app_handle.state::<Visibility>().set_hidden(hidden);
+}
+
+/// If the app has been hidden for at least `grace`, wait until a window is
+/// visible again. Returns whether we paused.
+pub(crate) async fn pause_while_hidden(
+    rx: &mut watch::Receiver<Option<Instant>>,
+    grace: Duration,
+) -> bool {
+    let hidden_for = rx.borrow_and_update().map(|since| since.elapsed());
+    if hidden_for.is_none_or(|d| d < grace) {
+        return false;
+    }
+    let _ = rx.wait_for(Option::is_none).await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_pauses_after_grace_period() {
+        let visibility = Visibility::new();
+        let mut rx = visibility.subscribe();
+        assert!(!pause_while_hidden(&mut rx, Duration::ZERO).await);
+
+        visibility.set_hidden(true);
+        assert!(!pause_while_hidden(&mut rx, Duration::from_secs(60)).await);
+    }
+
+    #[tokio::test]
+    async fn resumes_when_window_is_shown() {
+        let visibility = Visibility::new();
+        let mut rx = visibility.subscribe();
+        visibility.set_hidden(true);
+
+        let (paused, ()) = tokio::join!(pause_while_hidden(&mut rx, Duration::ZERO), async {
+            tokio::task::yield_now().await;
+            visibility.set_hidden(false);
+        });
+
+        assert!(paused);
+        assert!(rx.borrow().is_none());
+    }
+}
@@ -1,3 +1,4 @@
+import { invoke } from "@tauri-apps/api/core";
 import type {
   Project,
   DiscoveredProject,
@@ -41,6 +42,17 @@ import type {
 let API_PORT = 9876;
 let API_BASE_URL = `http://127.0.0.1:${API_PORT}`;
 
+/**
+ * With TLS on, the sidecar's certificate is self-signed and the webview won't
+ * trust it, and a socket sidecar (`http+unix`) can't be reached from the
+ * webview at all, so requests go through the Rust side's `proxy_request`
+ * instead of straight to the sidecar.
+ */
+let PROXY_REQUESTS = false;
+
+/** URL schemes the webview can't fetch directly. */
+const PROXIED_SCHEMES = ["https", "http+unix"];
+
 /**
  * Update the sidecar port at runtime.
  * Called when the Rust backend emits the "sidecar-ready" event with a dynamic port.
@@ -48,10 +60,94 @@ let API_BASE_URL = `http://127.0.0.1:${API_PORT}`;
 export function setApiPort(port: number): void {
   API_PORT = port;
   API_BASE_URL = `http://127.0.0.1:${port}`;
+  PROXY_REQUESTS = false;
+}
+
+/**
+ * Use the canonical base URL reported by the Rust backend
+ * (`sidecar-ready` payload or `get_sidecar_url`).
+ */
+export function setApiBaseUrl(url: SidecarUrl): void {
+  if (url.port !== null) {
+    API_PORT = url.port;
+  }
+  API_BASE_URL = url.base_url;
+  PROXY_REQUESTS = PROXIED_SCHEMES.includes(url.scheme);
+}
+
+/** Mirrors `SidecarUrl` on the Rust side. */
+export interface SidecarUrl {
+  base_url: string;
+  scheme: string;
+  host: string;
+  port: number | null;
+}
+
+/** Bearer token for the sidecar, from `get_sidecar_token`; rotates on restart. */
+let API_TOKEN: string | null = null;
+
+export function setApiToken(token: string | null): void {
+  API_TOKEN = token;
+}
+
+function authHeaders(): Record<string, string> {
+  return API_TOKEN ? { Authorization: `Bearer ${API_TOKEN}` } : {};
 }
 
 export { API_BASE_URL };
 
+/** Mirrors `ProxyResponse` on the Rust side. */
+interface ProxyResponse {
+  status: number;
+  headers: [string, string][];
+  body: string;
+  body_base64: boolean;
+}
+
+/**
+ * `fetch` a sidecar path, through `proxy_request` when the sidecar is on
+ * HTTPS or a socket. Proxied requests carry string bodies only, are buffered whole and
+ * time out after `timeoutMs` (at most two minutes) rather than on `signal`.
+ */
+async function sidecarFetch(
+  path: string,
+  init: RequestInit & { timeoutMs?: number } = {}
+): Promise<Response> {
+  const { timeoutMs, ...fetchInit } = init;
+  if (!PROXY_REQUESTS) {
+    return fetch(`${API_BASE_URL}${path}`, fetchInit);
+  }
+  // The proxy attaches the token itself and rejects a caller-set one.
+  const headers = [...new Headers(fetchInit.headers).entries()].filter(
+    ([name]) => name !== "authorization"
+  );
+  const reply = await invoke<ProxyResponse>("proxy_request", {
+    request: {
+      method: fetchInit.method ?? "GET",
+      path,
+      headers,
+      body: typeof fetchInit.body === "string" ? fetchInit.body : null,
+      timeout_ms: timeoutMs ?? null,
+    },
+  });
+  const body = [204, 205, 304].includes(reply.status)
+    ? null
+    : reply.body_base64
+      ? Uint8Array.from(atob(reply.body), (c) => c.charCodeAt(0))
+      : reply.body;
+  return new Response(body, { status: reply.status, headers: reply.headers });
+}
+
+/**
+ * URL of an SSE stream on the sidecar. `EventSource` can't send headers, so
+ * the token goes in a `token` query parameter, which the sidecar accepts on
+ * its stream routes only.
+ */
+export function streamUrl(path: string): string {
+  const url = `${API_BASE_URL}${path}`;
+  return API_TOKEN ? `${url}?token=${encodeURIComponent(API_TOKEN)}` : url;
+}
+
 let backendConnected = false;
 
 /**
@@ -94,7 +190,7 @@ export async function stopBackend(): Promise<void> {
 async function waitForHealthy(maxAttempts = 10): Promise<void> {
   for (let i = 0; i < maxAttempts; i++) {
     try {
-      const response = await fetch(`${API_BASE_URL}/health`);
+      const response = await sidecarFetch("/health", { headers: authHeaders() });
       if (response.ok) {
         const data = await response.json();
         if (data.status === "ok") {
@@ -163,7 +259,6 @@ const _inflightGets = new Map<string, Promise<unknown>>();
  * Internal fetch implementation with timing instrumentation.
  */
 async function _doFetch<T>(
-  url: string,
   endpoint: string,
   options?: RequestInit & { timeoutMs?: number }
 ): Promise<T> {
@@ -178,11 +273,13 @@ async function _doFetch<T>(
   const timeoutId = setTimeout(() => controller.abort(), timeout);
 
   try {
-    response = await fetch(url, {
+    response = await sidecarFetch(endpoint, {
       ...fetchOptions,
+      timeoutMs: timeout,
       signal: controller.signal,
       headers: {
         "Content-Type": "application/json",
+        ...authHeaders(),
         ...fetchOptions?.headers,
       },
     });
@@ -243,14 +340,14 @@ async function fetchApi<T>(
     const existing = _inflightGets.get(url);
     if (existing) return existing as Promise<T>;
 
-    const promise = _doFetch<T>(url, endpoint, options);
+    const promise = _doFetch<T>(endpoint, options);
     _inflightGets.set(url, promise);
     // Chain cleanup into the returned promise so we don't create an
     // uncaught derived promise (Safari reports it as Unhandled Rejection)
     return promise.finally(() => _inflightGets.delete(url));
   }
 
-  return _doFetch<T>(url, endpoint, options);
+  return _doFetch<T>(endpoint, options);
 }
 
 /**
@@ -998,9 +1095,9 @@ export const api = {
 
     const promise = (async () => {
       // 1. Kick off the background scan
-      const startResp = await fetch(`${API_BASE_URL}/api/product-map/scan${force ? "?force=true" : ""}`, {
+      const startResp = await sidecarFetch(`/api/product-map/scan${force ? "?force=true" : ""}`, {
         method: "POST",
-        headers: { "Content-Type": "application/json" },
+        headers: { "Content-Type": "application/json", ...authHeaders() },
         body: JSON.stringify({ project_path: projectPath }),
       });
       if (!startResp.ok) {
@@ -1020,8 +1117,9 @@ export const api = {
             return;
           }
           try {
-            const resp = await fetch(
-              `${API_BASE_URL}/api/product-map/scan/status?project_path=${encoded}`,
+            const resp = await sidecarFetch(
+              `/api/product-map/scan/status?project_path=${encoded}`,
+              { headers: authHeaders() },
             );
             if (!resp.ok) return; // retry next tick
             const data = await resp.json();
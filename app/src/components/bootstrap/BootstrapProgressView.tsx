@@ -6,7 +6,7 @@
  */
 
 import { useEffect, useState } from 'react';
-import { API_BASE_URL } from '../../api/backend';
+import { streamUrl } from '../../api/backend';
 
 interface BootstrapProgress {
   type: 'progress' | 'complete' | 'error';
@@ -46,9 +46,7 @@ export function BootstrapProgressView({
 
   useEffect(() => {
     // Connect to SSE endpoint
-    const eventSource = new EventSource(
-      `${API_BASE_URL}/api/bootstrap/stream/${sessionId}`
-    );
+    const eventSource = new EventSource(streamUrl(`/api/bootstrap/stream/${sessionId}`));
 
     eventSource.onmessage = (event) => {
       try {
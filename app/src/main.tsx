@@ -3,24 +3,35 @@ import ReactDOM from "react-dom/client";
 import { listen } from "@tauri-apps/api/event";
 import { invoke } from "@tauri-apps/api/core";
 import { AppRouter } from "./AppRouter";
-import { setApiPort } from "./api/backend";
+import { setApiBaseUrl, setApiToken, type SidecarUrl } from "./api/backend";
 import "./styles/global.css";
 
 // Listen for sidecar ready event (emitted by Rust after health poll succeeds).
 // This handles the normal case where the listener registers before the event fires.
-listen<{ port: number }>("sidecar-ready", (event) => {
-  setApiPort(event.payload.port);
+// The webview can't fetch an https sidecar (its certificate is self-signed) or
+// an http+unix one (a socket), so backend.ts sends requests for those through
+// `proxy_request` instead. SSE streams can't be proxied: over https they only
+// connect once the certificate is trusted, and over a socket not at all.
+// The auth token rotates with every spawn, so it is refetched on each ready.
+const SIDECAR_SCHEMES = ["http", "https", "http+unix"];
+
+listen<{ url: SidecarUrl }>("sidecar-ready", (event) => {
+  if (SIDECAR_SCHEMES.includes(event.payload.url.scheme)) {
+    setApiBaseUrl(event.payload.url);
+  }
+  invoke<string | null>("get_sidecar_token").then(setApiToken).catch(() => {});
 }).catch(() => {
   // Not running in Tauri context (dev server only) -- use default port
 });
 
 // Fallback: if the event already fired before the listener was registered,
-// invoke the Tauri command to get the port directly.
-invoke<number | null>("get_sidecar_port")
-  .then((port) => {
-    if (port && port > 0) {
-      setApiPort(port);
+// invoke the Tauri command to get the URL directly.
+invoke<SidecarUrl | null>("get_sidecar_url")
+  .then((url) => {
+    if (url && SIDECAR_SCHEMES.includes(url.scheme)) {
+      setApiBaseUrl(url);
     }
+    return invoke<string | null>("get_sidecar_token").then(setApiToken);
   })
   .catch(() => {
     // Not running in Tauri context or sidecar not ready yet -- use default port
@@ -1,5 +1,5 @@
 import { create } from "zustand";
-import { api, isBackendConnected, API_BASE_URL } from "../api/backend";
+import { api, isBackendConnected, streamUrl as sidecarStreamUrl } from "../api/backend";
 import { toast } from "../components/ui/Toast";
 import type {
   StreamEvent,
@@ -904,7 +904,7 @@ async function attemptStreaming(
       fn();
     };
 
-    const streamUrl = `${API_BASE_URL}/api/dispatch/stream/${encodeURIComponent(result.job_id)}`;
+    const streamUrl = sidecarStreamUrl(`/api/dispatch/stream/${encodeURIComponent(result.job_id)}`);
     const es = new EventSource(streamUrl);
     eventSource = es;
 